@@ -0,0 +1,5 @@
+//! Printing and logging the results of the CLI, apart from its commands so they can be tested
+
+pub mod log;
+pub mod output;
+pub mod report;
@@ -0,0 +1,261 @@
+//! Logging monitor data to files, for long term studies
+//!
+//! One port is kept open for the whole run, and opened again if it goes away, like when the
+//! FEM is unplugged. Readings that fail are left out of the log and reported on stderr.
+//!
+//! Files are named for the address of the FEM and the UTC day, like `fem1-2026-10-17.csv`,
+//! with the parts of a day past the size limit numbered like `fem1-2026-10-17.1.csv`. Logging
+//! again on the same day appends to the last part.
+
+use crate::{
+    output::{Format, Output},
+    report,
+};
+use clap::ValueEnum;
+use fem_client::{Error, Fem};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError},
+    time::{Duration, Instant, SystemTime},
+};
+use tracing::{info, warn};
+use transport::MonitorPayload;
+
+/// Formats of the log files
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, ValueEnum)]
+pub enum LogFormat {
+    /// Comma separated values under a header, as with `--format csv`
+    #[default]
+    Csv,
+    /// A JSON object per line, as with `--format json`
+    Ndjson,
+}
+
+impl LogFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            LogFormat::Csv => "csv",
+            LogFormat::Ndjson => "ndjson",
+        }
+    }
+
+    fn output(self) -> Format {
+        match self {
+            LogFormat::Csv => Format::Csv,
+            LogFormat::Ndjson => Format::Json,
+        }
+    }
+}
+
+/// What to log and where
+pub struct Options {
+    /// Serial port of the FEM
+    pub port: String,
+    /// Bus address of the FEM
+    pub addr: u8,
+    /// Time between readings
+    pub interval: Duration,
+    /// Directory the files go in
+    pub dir: PathBuf,
+    pub format: LogFormat,
+    /// Size in bytes past which a new file is started
+    pub max_size: Option<u64>,
+}
+
+impl Options {
+    /// Path of a part of the log of a day
+    pub fn path(&self, day: &str, part: u32) -> PathBuf {
+        let ext = self.format.extension();
+        let name = match part {
+            0 => format!("fem{}-{day}.{ext}", self.addr),
+            part => format!("fem{}-{day}.{part}.{ext}", self.addr),
+        };
+        self.dir.join(name)
+    }
+
+    /// Whether a file has grown past the size limit
+    fn is_full(&self, path: &Path) -> bool {
+        self.max_size
+            .is_some_and(|max| fs::metadata(path).is_ok_and(|meta| meta.len() >= max))
+    }
+}
+
+/// File that counts what's written to it
+struct Counted {
+    file: BufWriter<File>,
+    len: u64,
+}
+
+impl Write for Counted {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// File being logged to
+struct LogFile {
+    /// UTC day of the file, as `YYYY-MM-DD`
+    day: String,
+    part: u32,
+    out: Output<Counted>,
+}
+
+impl LogFile {
+    /// Open the last part of the log of `day` from `part` on, or a new one if that's full
+    fn open(opts: &Options, day: &str, mut part: u32) -> io::Result<Self> {
+        while opts.path(day, part + 1).exists() || opts.is_full(&opts.path(day, part)) {
+            part += 1;
+        }
+        let path = opts.path(day, part);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        info!("Logging to {}", path.display());
+        let file = Counted {
+            file: BufWriter::new(file),
+            len,
+        };
+        let out = Output::new(opts.format.output(), opts.addr, file);
+        // A file being appended to has its header already
+        let out = if len > 0 { out.continuing() } else { out };
+        Ok(Self {
+            day: day.to_string(),
+            part,
+            out,
+        })
+    }
+
+    /// Write out everything logged and make sure it's on disk
+    fn close(self) -> io::Result<()> {
+        let mut file = self.out.into_inner().file;
+        file.flush()?;
+        file.get_ref().sync_all()
+    }
+}
+
+/// The files readings are logged to, starting a new one every UTC day and whenever one's full
+pub struct Log<'a> {
+    opts: &'a Options,
+    file: Option<LogFile>,
+}
+
+impl<'a> Log<'a> {
+    pub fn new(opts: &'a Options) -> Self {
+        Self { opts, file: None }
+    }
+
+    /// Log a reading taken at `utc`, an RFC 3339 time, `monotonic_ms` after logging started
+    pub fn write(&mut self, utc: &str, monotonic_ms: u64, p: &MonitorPayload) -> io::Result<()> {
+        let day = &utc[..10];
+        if let Some(part) = self.next_part(day) {
+            if let Some(file) = self.file.take() {
+                file.close()?;
+            }
+            self.file = Some(LogFile::open(self.opts, day, part)?);
+        }
+        let out = &mut self.file.as_mut().expect("a file is open").out;
+        out.row(&report::logged(utc, monotonic_ms, p))?;
+        // Every reading is written out, so little is lost if the host goes down
+        out.finish()
+    }
+
+    /// The part of the log of `day` to go on from if a reading of that day needs a new file,
+    /// `None` if it goes in the one that's open
+    fn next_part(&self, day: &str) -> Option<u32> {
+        let Some(file) = &self.file else {
+            return Some(0);
+        };
+        if file.day != day {
+            return Some(0);
+        }
+        let full = self
+            .opts
+            .max_size
+            .is_some_and(|max| file.out.get_ref().len >= max);
+        full.then_some(file.part + 1)
+    }
+
+    /// Write out everything logged and make sure it's on disk
+    pub fn close(self) -> io::Result<()> {
+        match self.file {
+            Some(file) => file.close(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Log until interrupted with Ctrl-C
+pub fn run(opts: &Options) -> io::Result<()> {
+    fs::create_dir_all(&opts.dir)?;
+    let (interrupt, interrupted) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = interrupt.send(());
+    })
+    .map_err(io::Error::other)?;
+    let start = Instant::now();
+    let mut fem = None;
+    let mut log = Log::new(opts);
+    let mut next = start;
+    let res = loop {
+        if let Err(e) = poll(opts, &mut fem, &mut log, start) {
+            break Err(e);
+        }
+        // Keep to the interval, skipping readings that were missed rather than catching up
+        next += opts.interval;
+        let now = Instant::now();
+        next = next.max(now);
+        match interrupted.recv_timeout(next - now) {
+            Err(RecvTimeoutError::Timeout) => continue,
+            _ => break Ok(()),
+        }
+    };
+    // Keep what was logged, whatever stopped it
+    log.close()?;
+    res
+}
+
+/// Open the port and find out what the FEM on it speaks
+fn open(opts: &Options) -> fem_client::Result<Fem> {
+    let mut fem = Fem::open(&opts.port)?.with_addr(opts.addr);
+    fem.detect()?;
+    Ok(fem)
+}
+
+/// Take a reading and log it, opening the port as needed
+fn poll(opts: &Options, fem: &mut Option<Fem>, log: &mut Log, start: Instant) -> io::Result<()> {
+    let port = match fem {
+        Some(port) => port,
+        None => match open(opts) {
+            Ok(port) => {
+                info!("Opened {}", opts.port);
+                fem.insert(port)
+            }
+            Err(e) => {
+                warn!("Can't open {}, trying again: {e}", opts.port);
+                return Ok(());
+            }
+        },
+    };
+    let payload = match port.monitor() {
+        Ok(payload) => payload,
+        // The port went away, so open it again next time
+        Err(Error::Io(e)) => {
+            warn!("Lost {}, opening it again: {e}", opts.port);
+            *fem = None;
+            return Ok(());
+        }
+        Err(e) => {
+            warn!("Missed a reading: {e}");
+            return Ok(());
+        }
+    };
+    let utc = humantime::format_rfc3339_millis(SystemTime::now()).to_string();
+    log.write(&utc, start.elapsed().as_millis() as u64, &payload)
+}
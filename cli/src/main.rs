@@ -1,17 +1,32 @@
-use std::time::Duration;
+use std::{
+    fmt,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{self, ExitCode},
+    time::Duration,
+};
 
 use clap::{Parser, Subcommand, ValueEnum};
-use postcard::{
-    accumulator::{CobsAccumulator, FeedResult},
-    to_slice_cobs,
+use cli::{log, output, report};
+use fem_client::{Error, Fem};
+use log::LogFormat;
+use output::{Format, Output};
+use transport::{
+    ActionResult, Actions, Attenuation, Capabilities, Dbm, MonitorFields, ObjectId, ParamInfo,
+    Sensors, Value, ValueKind, DEFAULT_ADDR, MAX_ADDR,
 };
-use serialport::SerialPort;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     /// Serial port for the FEM
     port: String,
+    /// Bus address of the FEM, for several FEMs sharing an RS-485 bus
+    #[arg(short, long, default_value_t = DEFAULT_ADDR, value_parser = address)]
+    address: u8,
+    /// Format to print results in
+    #[arg(short, long, global = true, value_enum, default_value_t)]
+    format: Format,
     #[command(subcommand)]
     command: Command,
 }
@@ -19,7 +34,17 @@ struct Cli {
 #[derive(Subcommand)]
 enum Command {
     /// Gets monitor data from the FEM
-    Mon,
+    Mon {
+        /// Include the raw ADC counts, sample time and validity of every reading
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Gets the firmware version and serial number of the FEM
+    Id,
+    /// Gets the current control settings of the FEM
+    State,
+    /// Gets what the FEM can do, which the other commands are checked against
+    Caps,
     /// Controls the power of the LNA
     Lna {
         /// LNA Channel
@@ -28,9 +53,100 @@ enum Command {
         setting: Setting,
     },
     /// Sets the IF "power good" threshold
-    If { level: f32 },
-    /// Sets the attenuation level in dB (0 to 31.5)
-    Atten { level: f32 },
+    If {
+        #[arg(value_parser = dbm)]
+        level: Dbm,
+    },
+    /// Sets the attenuation level in dB, within the range and steps the FEM reports
+    Atten {
+        #[arg(value_parser = atten)]
+        level: Attenuation,
+    },
+    /// Streams monitor data from the FEM until interrupted
+    Stream {
+        /// Time between frames in milliseconds
+        #[arg(long, default_value_t = 1000)]
+        interval_ms: u32,
+        /// Fields to stream, all of those the FEM has if not given
+        #[arg(long, value_enum, value_delimiter = ',')]
+        fields: Vec<Field>,
+    },
+    /// Prints events reported by the FEM until interrupted
+    Events,
+    /// Logs monitor data to files until interrupted
+    ///
+    /// Readings are stamped with the UTC time and the host's monotonic clock, in milliseconds
+    /// since logging started. A new file is started every UTC day, and whenever one grows past
+    /// `--max-size`. The port is opened again if it goes away.
+    Log {
+        /// Time between readings, like `1s` or `500ms`
+        #[arg(long, default_value = "1s", value_parser = humantime::parse_duration)]
+        interval: Duration,
+        /// Directory to write the files to, made if it isn't there
+        #[arg(long)]
+        output: PathBuf,
+        /// Format of the files
+        #[arg(long, value_enum, default_value_t)]
+        file_format: LogFormat,
+        /// Size in bytes to start a new file at
+        #[arg(long)]
+        max_size: Option<u64>,
+    },
+    /// Gives the FEM a new bus address, kept across power cycles
+    ///
+    /// Every FEM starts out at the default address, so connect new ones to the bus one at a
+    /// time to give them addresses of their own.
+    Addr {
+        #[arg(value_parser = address)]
+        new: u8,
+    },
+    /// Lists the FEMs on the bus
+    Scan {
+        /// Time to wait for a response from every address in milliseconds
+        #[arg(long, default_value_t = 50)]
+        timeout_ms: u64,
+    },
+    /// Lists the parameters of the FEM, which can be read with `get` and written with `set`
+    Params,
+    /// Gets the value of a parameter
+    Get {
+        /// Name of the parameter, as listed by `params`
+        name: String,
+    },
+    /// Sets the value of a parameter
+    Set {
+        /// Name of the parameter, as listed by `params`
+        name: String,
+        /// New value, `on` or `off` for switches
+        value: String,
+    },
+    /// Uploads an object to the FEM, picking up where an interrupted upload left off
+    Upload {
+        object: Object,
+        /// File to upload
+        file: PathBuf,
+    },
+    /// Downloads an object from the FEM
+    Download {
+        object: Object,
+        /// File to write the object to, stdout if not given
+        file: Option<PathBuf>,
+    },
+    /// Applies several settings at once, either all of them or none
+    Batch {
+        /// LNA1 power setting
+        #[arg(long)]
+        lna1: Option<Setting>,
+        /// LNA2 power setting
+        #[arg(long)]
+        lna2: Option<Setting>,
+        /// Attenuation level in dB, within the range and steps the FEM reports
+        #[arg(long, value_parser = atten)]
+        atten: Option<Attenuation>,
+        /// IF "power good" threshold in dBm
+        #[arg(long = "if", value_parser = dbm)]
+        if_level: Option<Dbm>,
+    },
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -45,6 +161,123 @@ enum Lna {
     Ch2,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Field {
+    IfPower,
+    IcTemp,
+    Lna1Power,
+    Lna2Power,
+    AnalogPower,
+}
+
+/// Objects too large for a frame, moved in chunks
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Object {
+    /// Calibration table, kept by the FEM for the MnC software
+    CalTable,
+}
+
+impl Object {
+    fn id(&self) -> ObjectId {
+        match self {
+            Object::CalTable => ObjectId::CAL_TABLE,
+        }
+    }
+}
+
+impl Field {
+    fn mask(&self) -> MonitorFields {
+        match self {
+            Field::IfPower => MonitorFields::IF_POWER,
+            Field::IcTemp => MonitorFields::IC_TEMP,
+            Field::Lna1Power => MonitorFields::LNA1_POWER,
+            Field::Lna2Power => MonitorFields::LNA2_POWER,
+            Field::AnalogPower => MonitorFields::ANALOG_POWER,
+        }
+    }
+
+    /// Why the FEM can't measure the field, if it can't
+    fn unsupported(&self, caps: &Capabilities) -> Option<String> {
+        let (sensor, name) = match self {
+            Field::IfPower => (Sensors::IF_POWER, "IF power detectors"),
+            Field::IcTemp => (Sensors::IC_TEMP, "RP2040 temperature sensor"),
+            Field::Lna2Power if caps.channels < 2 => return Some(no_channel(2)),
+            Field::Lna1Power | Field::Lna2Power | Field::AnalogPower => {
+                (Sensors::RAIL_POWER, "rail power monitor")
+            }
+        };
+        (!caps.sensors.contains(sensor)).then(|| format!("the FEM has no {name}"))
+    }
+}
+
+impl Lna {
+    /// Why the FEM can't switch the LNA, if it can't
+    fn unsupported(&self, caps: &Capabilities) -> Option<String> {
+        let (n, action) = match self {
+            Lna::Ch1 => (1, Actions::LNA1_POWER),
+            Lna::Ch2 => (2, Actions::LNA2_POWER),
+        };
+        if caps.channels < n {
+            return Some(no_channel(n));
+        }
+        lacks(caps, action, &format!("switch LNA{n}"))
+    }
+}
+
+fn no_channel(n: u8) -> String {
+    format!("the FEM has no channel {n}")
+}
+
+/// Reason the FEM can't do `what`, if `action` isn't one of its controls
+fn lacks(caps: &Capabilities, action: Actions, what: &str) -> Option<String> {
+    (!caps.actions.contains(action)).then(|| format!("the FEM can't {what}"))
+}
+
+impl Command {
+    /// Why the FEM can't carry out the command, going by what it reports it can do
+    fn unsupported(&self, caps: &Capabilities) -> Option<String> {
+        let atten = || lacks(caps, Actions::SET_ATTEN, "set the attenuation");
+        let if_level = || lacks(caps, Actions::SET_IF_LEVEL, "set the IF threshold");
+        match self {
+            Command::Lna { channel, .. } => channel.unsupported(caps),
+            Command::If { .. } => if_level(),
+            Command::Atten { .. } => atten(),
+            Command::Stream { fields, .. } => fields.iter().find_map(|f| f.unsupported(caps)),
+            Command::Batch {
+                lna1,
+                lna2,
+                atten: level,
+                if_level: threshold,
+            } => [
+                lna1.and_then(|_| Lna::Ch1.unsupported(caps)),
+                lna2.and_then(|_| Lna::Ch2.unsupported(caps)),
+                level.and_then(|_| atten()),
+                threshold.and_then(|_| if_level()),
+            ]
+            .into_iter()
+            .flatten()
+            .next(),
+            _ => None,
+        }
+    }
+
+    /// Name of the command if it does something rather than get something, to report how
+    /// that went under
+    fn action(&self) -> Option<&'static str> {
+        match self {
+            Command::Lna { .. } => Some("lna"),
+            Command::If { .. } => Some("if"),
+            Command::Atten { .. } => Some("atten"),
+            Command::Addr { .. } => Some("addr"),
+            Command::Set { .. } => Some("set"),
+            Command::Upload { .. } => Some("upload"),
+            Command::Download { file: Some(_), .. } => Some("download"),
+            Command::Batch { .. } => Some("batch"),
+            _ => None,
+        }
+    }
+}
+
 impl Setting {
     fn en(&self) -> bool {
         match self {
@@ -54,84 +287,404 @@ impl Setting {
     }
 }
 
-/// Write a command out on the serial port (COBS) and wait for the response
-fn write_read(
-    cmd: &transport::Command,
-    mut port: Box<dyn SerialPort>,
-) -> Option<transport::Response> {
-    let mut buf = [0u8; 256];
-    let s = to_slice_cobs(cmd, &mut buf).unwrap();
-    port.write_all(s).expect("Serial write failed");
-
-    // Bytes per read
-    let mut raw_buf = [0u8; 256];
-    // Bytes in the accumulator (COBS)
-    let mut cobs_buf: CobsAccumulator<256> = CobsAccumulator::new();
-    // Keep truckin until we've got a response
-    while let Ok(n) = port.read(&mut raw_buf) {
-        if n == 0 {
-            // We're done reading
-            break;
+/// Ways a command can fail
+#[derive(Debug)]
+enum Failure {
+    /// Talking to the FEM failed, or the FEM refused
+    Fem(fem_client::Error),
+    /// What was asked can't be done, going by the files or names given
+    Invalid(String),
+    /// Printing the result or writing a log failed
+    Output(io::Error),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Fem(e) => write!(f, "{e}"),
+            Failure::Invalid(reason) => write!(f, "{reason}"),
+            Failure::Output(e) => write!(f, "can't write the output: {e}"),
+        }
+    }
+}
+
+impl From<fem_client::Error> for Failure {
+    fn from(e: fem_client::Error) -> Self {
+        Failure::Fem(e)
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Failure::Output(e)
+    }
+}
+
+impl Failure {
+    /// Process exit code for this failure, distinct for every error code
+    fn exit_code(&self) -> u8 {
+        match self {
+            Failure::Invalid(_) | Failure::Fem(Error::Unsupported(_)) => 2,
+            Failure::Fem(Error::Timeout) => 3,
+            Failure::Fem(Error::Decode(_)) => 4,
+            Failure::Fem(Error::Io(_) | Error::Encode(_)) | Failure::Output(_) => 5,
+            Failure::Fem(Error::Nak(code)) => match code {
+                transport::ErrorCode::Deserialize => 10,
+                transport::ErrorCode::OutOfRange { .. } => 11,
+                transport::ErrorCode::SpiFault => 12,
+                transport::ErrorCode::I2cFault => 13,
+                transport::ErrorCode::Unsupported => 14,
+                transport::ErrorCode::Busy => 15,
+                transport::ErrorCode::Checksum => 16,
+                transport::ErrorCode::Unknown => 17,
+                transport::ErrorCode::ReadOnly => 18,
+                transport::ErrorCode::WrongType => 19,
+                transport::ErrorCode::NotStarted => 20,
+            },
         }
-        let buf = &raw_buf[..n];
-        let mut window = buf;
-        'cobs: while !window.is_empty() {
-            window = match cobs_buf.feed::<transport::Response>(window) {
-                FeedResult::Consumed => break 'cobs,
-                FeedResult::OverFull(new_wind) => new_wind,
-                FeedResult::DeserError(new_wind) => new_wind,
-                FeedResult::Success { data, remaining: _ } => return Some(data),
-            };
+    }
+}
+
+fn monitor(fem: &mut Fem, out: &mut Output) -> Result<(), Failure> {
+    Ok(out.show(&report::monitor(&fem.monitor()?))?)
+}
+
+fn monitor_raw(fem: &mut Fem, out: &mut Output) -> Result<(), Failure> {
+    Ok(out.show(&report::monitor_raw(&fem.monitor_raw()?))?)
+}
+
+/// How long to wait for the FEM to stop its telemetry when interrupted
+const UNSUBSCRIBE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Exit code after Ctrl-C, as shells give it
+const INTERRUPTED: i32 = 130;
+
+fn stream(
+    fem: &mut Fem,
+    out: &mut Output,
+    interval_ms: u32,
+    fields: &[Field],
+) -> Result<(), Failure> {
+    let fields = match (fields, fem.capabilities()?) {
+        ([], None) => MonitorFields::ALL,
+        ([], Some(caps)) => Field::value_variants()
+            .iter()
+            .filter(|f| f.unsupported(&caps).is_none())
+            .fold(MonitorFields::NONE, |acc, f| acc | f.mask()),
+        (fields, _) => fields
+            .iter()
+            .fold(MonitorFields::NONE, |acc, f| acc | f.mask()),
+    };
+    // Stop the telemetry when interrupted too, or the FEM keeps sending it to nobody
+    let mut last_word = fem.try_clone(UNSUBSCRIBE_TIMEOUT)?;
+    ctrlc::set_handler(move || {
+        let _ = last_word.unsubscribe();
+        process::exit(INTERRUPTED);
+    })
+    .map_err(io::Error::other)?;
+    fem.subscribe(interval_ms, fields)?;
+    let res = show_telemetry(fem, out);
+    let _ = fem.unsubscribe();
+    res
+}
+
+fn show_telemetry(fem: &mut Fem, out: &mut Output) -> Result<(), Failure> {
+    for payload in fem.telemetry() {
+        out.row(&report::telemetry(&payload?))?;
+        out.finish()?;
+    }
+    Ok(())
+}
+
+fn events(fem: &mut Fem, out: &mut Output) -> Result<(), Failure> {
+    for event in fem.events() {
+        match event {
+            Ok(event) => {
+                out.row(&report::event(&event))?;
+                out.finish()?;
+            }
+            // Events only come when something changes
+            Err(Error::Timeout) => continue,
+            Err(e) => return Err(e.into()),
         }
     }
-    None
+    Ok(())
+}
+
+fn identify(fem: &mut Fem, out: &mut Output) -> Result<(), Failure> {
+    let identity = fem.identify()?;
+    out.show(&report::identity(&identity))?;
+    if identity.protocol_version != transport::PROTOCOL_VERSION {
+        eprintln!(
+            "Warning: FEM speaks protocol version {}, but this CLI speaks version {}",
+            identity.protocol_version,
+            transport::PROTOCOL_VERSION
+        );
+    }
+    Ok(())
+}
+
+fn control_state(fem: &mut Fem, out: &mut Output) -> Result<(), Failure> {
+    Ok(out.show(&report::state(&fem.state()?))?)
+}
+
+fn capabilities(fem: &mut Fem, out: &mut Output) -> Result<(), Failure> {
+    let Some(caps) = fem.capabilities()? else {
+        return Err(Error::Unsupported("the FEM's firmware is too old to say".into()).into());
+    };
+    Ok(out.show(&report::capabilities(&caps))?)
+}
+
+fn lna_power(fem: &mut Fem, channel: Lna, setting: Setting) -> Result<(), Failure> {
+    let channel = match channel {
+        Lna::Ch1 => fem_client::Lna::Ch1,
+        Lna::Ch2 => fem_client::Lna::Ch2,
+    };
+    Ok(fem.set_lna(channel, setting.en())?)
+}
+
+fn scan(fem: &mut Fem, out: &mut Output) -> Result<(), Failure> {
+    let found = fem.scan()?;
+    if found.is_empty() {
+        return Err(Error::Timeout.into());
+    }
+    for (addr, identity) in found {
+        out.row(&report::found(addr, &identity))?;
+    }
+    Ok(out.finish()?)
+}
+
+/// Look up a parameter of the FEM by name
+fn param(fem: &mut Fem, name: &str) -> Result<ParamInfo, Failure> {
+    let params = fem.params()?;
+    let names: Vec<_> = params.iter().map(|p| p.name.as_str()).collect();
+    let names = names.join(", ");
+    params
+        .into_iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| Failure::Invalid(format!("no parameter called {name}, try one of {names}")))
+}
+
+/// Parse a value for a parameter and make sure the FEM would take it
+fn param_value(info: &ParamInfo, s: &str) -> Result<Value, Failure> {
+    let value = match info.kind {
+        ValueKind::Bool => match s.to_ascii_lowercase().as_str() {
+            "on" | "enabled" | "true" | "1" => Some(Value::Bool(true)),
+            "off" | "disabled" | "false" | "0" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        ValueKind::F32 => s.parse().ok().map(Value::F32),
+        ValueKind::U32 => s.parse().ok().map(Value::U32),
+    };
+    let value = value.ok_or_else(|| {
+        Failure::Invalid(format!(
+            "{} takes a {:?} value, not {s}",
+            info.name, info.kind
+        ))
+    })?;
+    info.check(&value)
+        .map_err(|e| Failure::Invalid(format!("can't set {}: {e}", info.name)))?;
+    Ok(value)
+}
+
+fn params(fem: &mut Fem, out: &mut Output) -> Result<(), Failure> {
+    for info in fem.params()? {
+        out.row(&report::param(&info))?;
+    }
+    Ok(out.finish()?)
+}
+
+fn get(fem: &mut Fem, out: &mut Output, name: &str) -> Result<(), Failure> {
+    let info = param(fem, name)?;
+    let value = fem.get(info.id)?;
+    Ok(out.show(&report::value(&info, &value))?)
+}
+
+fn set(fem: &mut Fem, name: &str, value: &str) -> Result<(), Failure> {
+    let info = param(fem, name)?;
+    let value = param_value(&info, value)?;
+    Ok(fem.set(info.id, value)?)
 }
 
-fn monitor(port: Box<dyn SerialPort>) {
-    dbg!(write_read(&transport::Command::Monitor, port));
+fn upload(fem: &mut Fem, object: Object, file: &Path) -> Result<(), Failure> {
+    let data = std::fs::read(file)
+        .map_err(|e| Failure::Invalid(format!("can't read {}: {e}", file.display())))?;
+    Ok(fem.upload(object.id(), &data)?)
 }
 
-fn lna_power(port: Box<dyn SerialPort>, channel: Lna, setting: Setting) {
-    let cmd = match channel {
-        Lna::Ch1 => transport::Command::Control(transport::Action::Lna1Power(setting.en())),
-        Lna::Ch2 => transport::Command::Control(transport::Action::Lna2Power(setting.en())),
+fn download(fem: &mut Fem, object: Object, file: Option<&Path>) -> Result<(), Failure> {
+    let data = fem.download(object.id())?;
+    // The object itself is the output when there's no file for it
+    let res = match file {
+        Some(file) => std::fs::write(file, &data),
+        None => io::stdout().write_all(&data),
     };
-    dbg!(write_read(&cmd, port));
+    res.map_err(|e| {
+        Failure::Invalid(format!(
+            "can't write the {} bytes downloaded: {e}",
+            data.len()
+        ))
+    })
+}
+
+/// Parse a bus address a FEM can be given
+fn address(s: &str) -> Result<u8, String> {
+    match s.parse() {
+        Ok(addr) if transport::is_valid_addr(addr) => Ok(addr),
+        _ => Err(format!("must be a number between 1 and {MAX_ADDR}")),
+    }
 }
 
-fn if_level(port: Box<dyn SerialPort>, level: f32) {
-    dbg!(write_read(
-        &transport::Command::Control(transport::Action::SetIfLevel(level)),
-        port,
-    ));
+fn atten(s: &str) -> Result<Attenuation, String> {
+    let db = s.parse::<f32>().map_err(|e| e.to_string())?;
+    Attenuation::new(db).map_err(|e| e.to_string())
 }
 
-fn attenuation(port: Box<dyn SerialPort>, level: f32) {
-    assert!(
-        (0.0..=31.5).contains(&level),
-        "Attenuation level must be between 0 and 31.5"
-    );
-    dbg!(write_read(
-        &transport::Command::Control(transport::Action::SetAtten(level)),
-        port,
-    ));
+fn dbm(s: &str) -> Result<Dbm, String> {
+    let dbm = s.parse::<f32>().map_err(|e| e.to_string())?;
+    Dbm::new(dbm).map_err(|_| "must be a finite number".into())
 }
 
-const FEM_BAUD: u32 = 115_200;
+/// Apply the settings given all at once, keeping the outcome of every one in `results`
+fn batch(
+    fem: &mut Fem,
+    results: &mut Vec<(transport::Action, ActionResult)>,
+    lna1: Option<Setting>,
+    lna2: Option<Setting>,
+    atten: Option<Attenuation>,
+    if_level: Option<Dbm>,
+) -> Result<(), Failure> {
+    let actions: Vec<_> = [
+        lna1.map(|s| transport::Action::Lna1Power(s.en())),
+        lna2.map(|s| transport::Action::Lna2Power(s.en())),
+        atten.map(transport::Action::SetAtten),
+        if_level.map(transport::Action::SetIfLevel),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let outcomes = fem.batch(&actions)?;
+    results.extend(actions.into_iter().zip(outcomes));
+    // Report the first action that sunk the batch
+    match results.iter().find_map(|(_, r)| match r {
+        ActionResult::Rejected(code) => Some(*code),
+        _ => None,
+    }) {
+        Some(code) => Err(Error::Nak(code).into()),
+        None => Ok(()),
+    }
+}
 
-fn main() {
-    // Parse the CLI
+fn main() -> ExitCode {
+    // Parse the CLI and log to stderr
     let cli = Cli::parse();
-    // Try to open the serial port
-    let port = serialport::new(cli.port, FEM_BAUD)
-        .timeout(Duration::from_millis(1000))
-        .open()
-        .expect("Failed to open serial port");
-    // Dispath on action
-    match cli.command {
-        Command::Mon => monitor(port),
-        Command::If { level } => if_level(port, level),
-        Command::Atten { level } => attenuation(port, level),
-        Command::Lna { channel, setting } => lna_power(port, channel, setting),
+    tracing_subscriber::fmt().with_writer(io::stderr).init();
+    // Try to open the serial port, scanning the bus shouldn't take all day and streamed
+    // frames get a little longer than the interval to show up
+    let timeout = match cli.command {
+        Command::Scan { timeout_ms } => Duration::from_millis(timeout_ms),
+        Command::Stream { interval_ms, .. } => {
+            Duration::from_millis(interval_ms.into()) + Duration::from_secs(1)
+        }
+        _ => Duration::from_millis(1000),
+    };
+    let mut out = Output::new(cli.format, cli.address, io::stdout());
+    let action = cli.command.action();
+    let mut results = vec![];
+    let res = match cli.command {
+        // Logging opens the port itself, and again whenever it goes away
+        Command::Log {
+            interval,
+            output,
+            file_format,
+            max_size,
+        } => log::run(&log::Options {
+            port: cli.port,
+            addr: cli.address,
+            interval,
+            dir: output,
+            format: file_format,
+            max_size,
+        })
+        .map_err(Failure::from),
+        command => Fem::open_with_timeout(&cli.port, timeout)
+            .map_err(Failure::from)
+            .and_then(|fem| {
+                let mut fem = fem.with_addr(cli.address);
+                check(&mut fem, &command)?;
+                run(&mut fem, &mut out, &mut results, command)
+            }),
+    };
+    // Commands that do something report how it went either way, the rest only complain
+    if let Some(command) = action {
+        let error = res.as_ref().err().map(|e| (e.to_string(), e.exit_code()));
+        if let Err(e) = out.show(&report::outcome(command, error, &results)) {
+            eprintln!("Error: {}", Failure::Output(e));
+        }
+    }
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if action.is_none() {
+                eprintln!("Error: {e}");
+            }
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+/// Find out what the FEM speaks and make sure it can carry out the command, before asking it to
+fn check(fem: &mut Fem, command: &Command) -> Result<(), Failure> {
+    // Scanning asks every address for itself
+    if let Command::Scan { .. } = command {
+        return Ok(());
+    }
+    // Keep talking to boards still running 0.2 firmware
+    fem.detect()?;
+    // Which check everything themselves, like the older firmware that can't say what it can do
+    let Some(caps) = fem.capabilities()? else {
+        return Ok(());
+    };
+    match command.unsupported(&caps) {
+        Some(reason) => Err(Error::Unsupported(reason).into()),
+        None => Ok(()),
+    }
+}
+
+/// Dispatch on the command
+fn run(
+    fem: &mut Fem,
+    out: &mut Output,
+    results: &mut Vec<(transport::Action, ActionResult)>,
+    command: Command,
+) -> Result<(), Failure> {
+    match command {
+        Command::Mon { raw: false } => monitor(fem, out),
+        Command::Mon { raw: true } => monitor_raw(fem, out),
+        Command::Stream {
+            interval_ms,
+            fields,
+        } => stream(fem, out, interval_ms, &fields),
+        Command::Events => events(fem, out),
+        Command::Log { .. } => unreachable!("logging opens the port itself"),
+        Command::Id => identify(fem, out),
+        Command::State => control_state(fem, out),
+        Command::Caps => capabilities(fem, out),
+        Command::If { level } => Ok(fem.set_if_threshold(level)?),
+        Command::Atten { level } => Ok(fem.set_attenuation(level)?),
+        Command::Lna { channel, setting } => lna_power(fem, channel, setting),
+        Command::Addr { new } => Ok(fem.set_address(new)?),
+        Command::Scan { .. } => scan(fem, out),
+        Command::Params => params(fem, out),
+        Command::Get { name } => get(fem, out, &name),
+        Command::Set { name, value } => set(fem, &name, &value),
+        Command::Upload { object, file } => upload(fem, object, &file),
+        Command::Download { object, file } => download(fem, object, file.as_deref()),
+        Command::Batch {
+            lna1,
+            lna2,
+            atten,
+            if_level,
+        } => batch(fem, results, lna1, lna2, atten, if_level),
     }
 }
@@ -0,0 +1,454 @@
+//! Printing results in the format picked with `--format`
+//!
+//! Every result is a [`Record`], the [`Field`]s the table, CSV and Prometheus formats are made
+//! of along with its JSON form. The JSON is that of the messages (see `transport::schema`), so
+//! it's as stable as they are. The machine formats give quantities in the units of the
+//! messages (volts, amps, milliseconds), tables in whatever reads best.
+
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::{self, Write};
+
+/// Formats results can be printed in
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, ValueEnum)]
+pub enum Format {
+    /// Aligned, with units and channel labels
+    #[default]
+    Table,
+    /// A JSON object per result, one per line
+    Json,
+    /// Comma separated values under a header
+    Csv,
+    /// Prometheus text exposition format, as taken by the node exporter's textfile collector
+    PrometheusText,
+}
+
+/// Unit of a field
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Unit {
+    None,
+    Db,
+    Dbm,
+    Volts,
+    Amps,
+    Celsius,
+    Millis,
+}
+
+impl Unit {
+    /// Suffix of column and metric names
+    fn suffix(self) -> &'static str {
+        match self {
+            Unit::None => "",
+            Unit::Db => "db",
+            Unit::Dbm => "dbm",
+            Unit::Volts => "volts",
+            Unit::Amps => "amps",
+            Unit::Celsius => "celsius",
+            Unit::Millis => "ms",
+        }
+    }
+}
+
+/// Value of a field
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cell {
+    Float(f32),
+    Int(u64),
+    Bool(bool),
+    Text(String),
+    /// Not known, like a reading that wasn't valid
+    Missing,
+}
+
+impl From<f32> for Cell {
+    fn from(x: f32) -> Self {
+        Cell::Float(x)
+    }
+}
+
+impl From<u64> for Cell {
+    fn from(x: u64) -> Self {
+        Cell::Int(x)
+    }
+}
+
+impl From<u32> for Cell {
+    fn from(x: u32) -> Self {
+        Cell::Int(x.into())
+    }
+}
+
+impl From<u16> for Cell {
+    fn from(x: u16) -> Self {
+        Cell::Int(x.into())
+    }
+}
+
+impl From<u8> for Cell {
+    fn from(x: u8) -> Self {
+        Cell::Int(x.into())
+    }
+}
+
+impl From<bool> for Cell {
+    fn from(b: bool) -> Self {
+        Cell::Bool(b)
+    }
+}
+
+impl<T: Into<Cell>> From<Option<T>> for Cell {
+    fn from(x: Option<T>) -> Self {
+        x.map_or(Cell::Missing, Into::into)
+    }
+}
+
+/// One value of a result
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    /// Name of the column or metric, in `snake_case` without the unit
+    name: &'static str,
+    /// Heading in tables
+    title: &'static str,
+    /// Which of several alike fields this is, like `("channel", "ch1")`
+    tag: Option<(&'static str, &'static str)>,
+    unit: Unit,
+    cell: Cell,
+}
+
+impl Field {
+    pub fn new(name: &'static str, title: &'static str, unit: Unit, cell: impl Into<Cell>) -> Self {
+        Self {
+            name,
+            title,
+            tag: None,
+            unit,
+            cell: cell.into(),
+        }
+    }
+
+    /// Field holding text
+    pub fn text(name: &'static str, title: &'static str, text: impl ToString) -> Self {
+        Self::new(name, title, Unit::None, Cell::Text(text.to_string()))
+    }
+
+    /// Tell the field apart from others of the same name, by channel or rail
+    pub fn tag(mut self, key: &'static str, value: &'static str) -> Self {
+        self.tag = Some((key, value));
+        self
+    }
+
+    /// Name of the field as a CSV column, like `ch1_if_power_dbm`
+    fn column(&self) -> String {
+        let tag = self.tag.map(|(_, value)| value);
+        [tag, Some(self.name), Some(self.unit.suffix())]
+            .into_iter()
+            .flatten()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("_")
+    }
+
+    /// Name of the field as a Prometheus metric, like `fem_if_power_dbm`
+    fn metric(&self) -> String {
+        match self.unit.suffix() {
+            "" => format!("fem_{}", self.name),
+            suffix => format!("fem_{}_{suffix}", self.name),
+        }
+    }
+
+    /// Heading of the field in tables, like `IF power ch1`
+    fn heading(&self) -> String {
+        match self.tag {
+            Some((_, value)) => format!("{} {value}", self.title),
+            None => self.title.to_string(),
+        }
+    }
+
+    /// The value as it's shown in tables, with its unit
+    fn human(&self) -> String {
+        match (&self.cell, self.unit) {
+            (Cell::Missing, _) => "-".into(),
+            (Cell::Bool(b), _) => if *b { "yes" } else { "no" }.into(),
+            (Cell::Float(x), Unit::Db) => format!("{x} dB"),
+            (Cell::Float(x), Unit::Dbm) => format!("{x:.2} dBm"),
+            (Cell::Float(x), Unit::Volts) => format!("{x:.3} V"),
+            (Cell::Float(x), Unit::Amps) => format!("{:.1} mA", x * 1000.0),
+            (Cell::Float(x), Unit::Celsius) => format!("{x:.1} °C"),
+            (Cell::Int(ms), Unit::Millis) => format!("{:.3} s", *ms as f64 / 1000.0),
+            _ => self.machine(),
+        }
+    }
+
+    /// The value as it's given in CSV
+    fn machine(&self) -> String {
+        match &self.cell {
+            Cell::Float(x) => x.to_string(),
+            Cell::Int(x) => x.to_string(),
+            Cell::Bool(b) => b.to_string(),
+            Cell::Text(text) => text.clone(),
+            Cell::Missing => String::new(),
+        }
+    }
+
+    /// The value as a Prometheus sample, `NaN` for missing quantities and `None` for text and
+    /// other missing values
+    fn sample(&self) -> Option<String> {
+        match self.cell {
+            Cell::Missing if self.unit != Unit::None => Some("NaN".into()),
+            Cell::Float(x) if x.is_nan() => Some("NaN".into()),
+            Cell::Float(x) if x.is_infinite() => Some(if x > 0.0 { "+Inf" } else { "-Inf" }.into()),
+            Cell::Float(x) => Some(x.to_string()),
+            Cell::Int(x) => Some(x.to_string()),
+            Cell::Bool(b) => Some(u8::from(b).to_string()),
+            Cell::Text(_) | Cell::Missing => None,
+        }
+    }
+}
+
+/// A result, as the fields of the table, CSV and Prometheus formats and as JSON
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    fields: Vec<Field>,
+    /// JSON form, written straight from the message so `f32`s keep their shortest form
+    json: String,
+}
+
+impl Record {
+    /// Record with the JSON form of `json` and no fields yet
+    pub fn new(json: impl Serialize) -> Self {
+        Self {
+            fields: vec![],
+            // Only maps with keys that aren't strings fail, which the messages don't have
+            json: serde_json::to_string(&json).expect("results can be written as JSON"),
+        }
+    }
+
+    pub fn push(&mut self, field: Field) {
+        self.fields.push(field);
+    }
+
+    pub fn with(mut self, field: Field) -> Self {
+        self.push(field);
+        self
+    }
+}
+
+/// Prints records in a [`Format`]
+pub struct Output<W = io::Stdout> {
+    format: Format,
+    /// Address of the FEM the results came from, a label of every metric
+    addr: u8,
+    out: W,
+    /// Widths of the columns of a series of rows, once its header is printed
+    columns: Option<Vec<usize>>,
+    /// Metrics and their samples, held back to print every metric's samples together
+    metrics: Vec<(String, &'static str, Vec<String>)>,
+    /// Metrics whose help and type are printed, which only come before their first samples
+    described: Vec<String>,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(format: Format, addr: u8, out: W) -> Self {
+        Self {
+            format,
+            addr,
+            out,
+            columns: None,
+            metrics: vec![],
+            described: vec![],
+        }
+    }
+
+    /// Carry on a series of CSV rows or Prometheus samples under a header that's already there,
+    /// like in a file being appended to
+    pub fn continuing(mut self) -> Self {
+        self.columns = Some(vec![]);
+        self
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.out
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Print a result on its own
+    pub fn show(&mut self, record: &Record) -> io::Result<()> {
+        match self.format {
+            Format::Table => {
+                let width = record
+                    .fields
+                    .iter()
+                    .map(|f| f.heading().chars().count())
+                    .max()
+                    .unwrap_or(0);
+                for field in &record.fields {
+                    let heading = field.heading();
+                    writeln!(self.out, "{heading:<width$}  {}", field.human())?;
+                }
+            }
+            Format::Json => self.json(record)?,
+            Format::Csv => {
+                self.csv_header(record)?;
+                self.csv_row(record)?;
+            }
+            Format::PrometheusText => {
+                self.collect(record);
+                self.finish()?;
+            }
+        }
+        self.out.flush()
+    }
+
+    /// Print one of a series of results, as a row under a header for tables and CSV
+    ///
+    /// The rows of a series should all have the same fields. [`Output::finish`] should be
+    /// called once a series is done, or whenever the rows so far should be seen.
+    pub fn row(&mut self, record: &Record) -> io::Result<()> {
+        match self.format {
+            Format::Table => {
+                let columns = match &self.columns {
+                    Some(columns) => columns.clone(),
+                    None => {
+                        let columns: Vec<_> = record
+                            .fields
+                            .iter()
+                            .map(|f| f.heading().chars().count().max(f.human().chars().count()))
+                            .collect();
+                        let headings = record.fields.iter().map(Field::heading);
+                        self.table_line(headings, &columns)?;
+                        self.columns = Some(columns.clone());
+                        columns
+                    }
+                };
+                let values = record.fields.iter().map(Field::human);
+                self.table_line(values, &columns)?;
+            }
+            Format::Json => self.json(record)?,
+            Format::Csv => {
+                if self.columns.is_none() {
+                    self.csv_header(record)?;
+                    self.columns = Some(vec![]);
+                }
+                self.csv_row(record)?;
+            }
+            Format::PrometheusText => self.collect(record),
+        }
+        Ok(())
+    }
+
+    /// Print whatever of a series of results is held back
+    pub fn finish(&mut self) -> io::Result<()> {
+        for (metric, help, samples) in self.metrics.drain(..) {
+            // The columns are only set for metrics by `continuing`, under help already there
+            if self.columns.is_none() && !self.described.contains(&metric) {
+                writeln!(self.out, "# HELP {metric} {help}")?;
+                writeln!(self.out, "# TYPE {metric} gauge")?;
+                self.described.push(metric);
+            }
+            for sample in samples {
+                writeln!(self.out, "{sample}")?;
+            }
+        }
+        self.out.flush()
+    }
+
+    fn json(&mut self, record: &Record) -> io::Result<()> {
+        writeln!(self.out, "{}", record.json)
+    }
+
+    fn table_line(
+        &mut self,
+        cells: impl Iterator<Item = String>,
+        columns: &[usize],
+    ) -> io::Result<()> {
+        let cells: Vec<_> = cells
+            .zip(columns)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        writeln!(self.out, "{}", cells.join("  ").trim_end())
+    }
+
+    fn csv_header(&mut self, record: &Record) -> io::Result<()> {
+        let columns: Vec<_> = record
+            .fields
+            .iter()
+            .map(|f| csv_escape(&f.column()))
+            .collect();
+        writeln!(self.out, "{}", columns.join(","))
+    }
+
+    fn csv_row(&mut self, record: &Record) -> io::Result<()> {
+        let values: Vec<_> = record
+            .fields
+            .iter()
+            .map(|f| csv_escape(&f.machine()))
+            .collect();
+        writeln!(self.out, "{}", values.join(","))
+    }
+
+    /// Hold back the samples of a record, labelled by its text fields
+    ///
+    /// A record with nothing but text is given a `fem_info` metric of 1 to hang the labels on.
+    fn collect(&mut self, record: &Record) {
+        let mut labels = vec![];
+        // A record can be of a FEM other than the one asked, like when scanning the bus
+        let own_addr = record
+            .fields
+            .iter()
+            .any(|f| f.name == "addr" && matches!(f.cell, Cell::Text(_)));
+        if !own_addr {
+            labels.push(("addr".to_string(), self.addr.to_string()));
+        }
+        for field in &record.fields {
+            if let Cell::Text(text) = &field.cell {
+                labels.push((field.column(), text.clone()));
+            }
+        }
+        let mut samples: Vec<_> = record
+            .fields
+            .iter()
+            .filter_map(|f| Some((f.metric(), f.title, f.tag, f.sample()?)))
+            .collect();
+        if samples.is_empty() {
+            samples.push((
+                "fem_info".into(),
+                "Information about the FEM",
+                None,
+                "1".into(),
+            ));
+        }
+        for (metric, help, tag, value) in samples {
+            let labels: Vec<_> = labels
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .chain(tag)
+                .map(|(key, value)| format!("{key}=\"{}\"", label_escape(value)))
+                .collect();
+            let sample = format!("{metric}{{{}}} {value}", labels.join(","));
+            match self.metrics.iter_mut().find(|(m, ..)| *m == metric) {
+                Some((.., samples)) => samples.push(sample),
+                None => self.metrics.push((metric, help, vec![sample])),
+            }
+        }
+    }
+}
+
+/// Quote a CSV value if it needs it
+fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Escape a Prometheus label value
+fn label_escape(s: &str) -> String {
+    s.replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
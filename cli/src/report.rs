@@ -0,0 +1,464 @@
+//! Results of the commands as [`Record`]s
+
+use crate::output::{Cell, Field, Record, Unit};
+use serde::Serialize;
+use transport::{
+    Action, ActionResult, Actions, Capabilities, ControlState, Dbm, Event, EventKind, Features,
+    Identity, MonitorPayload, MonitorPayloadV2, ParamInfo, Power, Rail, Sensors, TelemetryPayload,
+    Validity, Value, ValueKind,
+};
+
+/// Tags of the IF channels
+const CHANNELS: [&str; 2] = ["ch1", "ch2"];
+
+/// Readings of a payload, `None` for the ones there aren't
+struct Readings {
+    if_power: [Option<f32>; 2],
+    ic_temp: Option<f32>,
+    /// Voltage and current of every rail, in the order of [`RAILS`]
+    rails: [(Option<f32>, Option<f32>); 3],
+}
+
+/// Tags of the power rails
+const RAILS: [&str; 3] = ["lna1", "lna2", "analog"];
+
+impl Readings {
+    /// The readings of a payload that are `valid`
+    fn valid(p: &MonitorPayload, valid: Validity) -> Self {
+        let pick = |flag, x: f32| valid.contains(flag).then_some(x);
+        let rail = |power: &Power, voltage, current| {
+            (
+                pick(voltage, power.voltage.volts()),
+                pick(current, power.current.amps()),
+            )
+        };
+        Self {
+            if_power: [
+                pick(Validity::IF1_POWER, p.if1_power.dbm()),
+                pick(Validity::IF2_POWER, p.if2_power.dbm()),
+            ],
+            ic_temp: pick(Validity::IC_TEMP, p.ic_temp),
+            rails: [
+                rail(
+                    &p.lna1_power,
+                    Validity::LNA1_VOLTAGE,
+                    Validity::LNA1_CURRENT,
+                ),
+                rail(
+                    &p.lna2_power,
+                    Validity::LNA2_VOLTAGE,
+                    Validity::LNA2_CURRENT,
+                ),
+                rail(
+                    &p.analog_power,
+                    Validity::ANALOG_VOLTAGE,
+                    Validity::ANALOG_CURRENT,
+                ),
+            ],
+        }
+    }
+
+    /// The readings of a telemetry frame, which only has the fields subscribed to
+    fn telemetry(p: &TelemetryPayload) -> Self {
+        let rail = |power: &Option<Power>| {
+            (
+                power.as_ref().map(|p| p.voltage.volts()),
+                power.as_ref().map(|p| p.current.amps()),
+            )
+        };
+        Self {
+            if_power: [p.if1_power.map(Dbm::dbm), p.if2_power.map(Dbm::dbm)],
+            ic_temp: p.ic_temp,
+            rails: [
+                rail(&p.lna1_power),
+                rail(&p.lna2_power),
+                rail(&p.analog_power),
+            ],
+        }
+    }
+
+    /// Add the readings to `record` as fields
+    fn push(self, record: &mut Record) {
+        for (power, channel) in self.if_power.into_iter().zip(CHANNELS) {
+            record
+                .push(Field::new("if_power", "IF power", Unit::Dbm, power).tag("channel", channel));
+        }
+        record.push(Field::new(
+            "ic_temp",
+            "RP2040 temp",
+            Unit::Celsius,
+            self.ic_temp,
+        ));
+        for ((voltage, current), rail) in self.rails.into_iter().zip(RAILS) {
+            record.push(Field::new("voltage", "Voltage", Unit::Volts, voltage).tag("rail", rail));
+            record.push(Field::new("current", "Current", Unit::Amps, current).tag("rail", rail));
+        }
+    }
+}
+
+pub fn monitor(p: &MonitorPayload) -> Record {
+    let mut record = Record::new(p);
+    Readings::valid(p, Validity::ALL).push(&mut record);
+    record
+}
+
+/// JSON form of a logged reading
+#[derive(Serialize)]
+struct Logged<'a> {
+    utc: &'a str,
+    monotonic_ms: u64,
+    monitor: &'a MonitorPayload,
+}
+
+/// A reading stamped with the UTC time it was taken and the host's monotonic clock
+pub fn logged(utc: &str, monotonic_ms: u64, p: &MonitorPayload) -> Record {
+    let mut record = Record::new(Logged {
+        utc,
+        monotonic_ms,
+        monitor: p,
+    })
+    .with(Field::text("utc", "UTC", utc))
+    .with(Field::new(
+        "monotonic",
+        "Monotonic",
+        Unit::Millis,
+        monotonic_ms,
+    ));
+    Readings::valid(p, Validity::ALL).push(&mut record);
+    record
+}
+
+/// A telemetry frame, with the fields that weren't subscribed to missing
+pub fn telemetry(p: &TelemetryPayload) -> Record {
+    let mut record = Record::new(p);
+    Readings::telemetry(p).push(&mut record);
+    record
+}
+
+pub fn monitor_raw(p: &MonitorPayloadV2) -> Record {
+    let mut record = Record::new(p)
+        .with(Field::new("uptime", "Uptime", Unit::Millis, p.uptime_ms))
+        .with(Field::new("sample_seq", "Sample", Unit::None, p.sample_seq));
+    Readings::valid(&p.clone().into(), p.valid).push(&mut record);
+    for (counts, channel) in [p.if1_counts, p.if2_counts].into_iter().zip(CHANNELS) {
+        record.push(
+            Field::new("if_power_counts", "IF power counts", Unit::None, counts)
+                .tag("channel", channel),
+        );
+    }
+    record.with(Field::new(
+        "ic_temp_counts",
+        "RP2040 temp counts",
+        Unit::None,
+        p.ic_temp_counts,
+    ))
+}
+
+pub fn identity(identity: &Identity) -> Record {
+    Record::new(identity)
+        .with(Field::text(
+            "firmware_version",
+            "Firmware version",
+            &identity.firmware_version,
+        ))
+        .with(Field::text("git_hash", "Git hash", &identity.git_hash))
+        .with(Field::new(
+            "build_timestamp",
+            "Build timestamp",
+            Unit::None,
+            identity.build_timestamp,
+        ))
+        .with(Field::text(
+            "serial",
+            "Serial number",
+            format!("{:016x}", identity.serial),
+        ))
+        .with(Field::new(
+            "protocol_version",
+            "Protocol version",
+            Unit::None,
+            identity.protocol_version,
+        ))
+}
+
+pub fn state(state: &ControlState) -> Record {
+    let mut record = Record::new(state);
+    for (en, channel) in [state.lna1_enabled, state.lna2_enabled]
+        .into_iter()
+        .zip(CHANNELS)
+    {
+        record
+            .push(Field::new("lna_enabled", "LNA enabled", Unit::None, en).tag("channel", channel));
+    }
+    for (atten, channel) in [state.atten1, state.atten2].into_iter().zip(CHANNELS) {
+        record.push(
+            Field::new("attenuation", "Attenuation", Unit::Db, atten.db()).tag("channel", channel),
+        );
+    }
+    record.push(Field::new(
+        "if_threshold",
+        "IF threshold",
+        Unit::Dbm,
+        state.if_good_threshold.dbm(),
+    ));
+    // Missing if the board doesn't have one
+    for (cal, channel) in [state.cal1, state.cal2].into_iter().zip(CHANNELS) {
+        record.push(Field::new("cal_tone", "Cal tone", Unit::None, cal).tag("channel", channel));
+    }
+    record.with(Field::new(
+        "uptime",
+        "Uptime",
+        Unit::Millis,
+        state.uptime_ms,
+    ))
+}
+
+/// Names of the flags that are set, `none` if there aren't any
+fn names(flags: &[(bool, &str)]) -> String {
+    let names: Vec<_> = flags
+        .iter()
+        .filter(|(has, _)| *has)
+        .map(|(_, name)| *name)
+        .collect();
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(" ")
+    }
+}
+
+pub fn capabilities(caps: &Capabilities) -> Record {
+    let actions = names(&[
+        (caps.actions.contains(Actions::LNA1_POWER), "lna-ch1"),
+        (caps.actions.contains(Actions::LNA2_POWER), "lna-ch2"),
+        (caps.actions.contains(Actions::SET_ATTEN), "atten"),
+        (caps.actions.contains(Actions::SET_IF_LEVEL), "if"),
+    ]);
+    let sensors = names(&[
+        (caps.sensors.contains(Sensors::IF_POWER), "if-power"),
+        (caps.sensors.contains(Sensors::IC_TEMP), "ic-temp"),
+        (caps.sensors.contains(Sensors::RAIL_POWER), "rail-power"),
+        (caps.sensors.contains(Sensors::BOARD_TEMP), "board-temp"),
+    ]);
+    let features = names(&[(caps.features.contains(Features::CAL_TONE), "cal-tone")]);
+    Record::new(caps)
+        .with(Field::text("controls", "Controls", actions))
+        .with(Field::new(
+            "channels",
+            "Channels",
+            Unit::None,
+            caps.channels,
+        ))
+        .with(Field::new(
+            "atten_min",
+            "Attenuation min",
+            Unit::Db,
+            caps.atten_min,
+        ))
+        .with(Field::new(
+            "atten_max",
+            "Attenuation max",
+            Unit::Db,
+            caps.atten_max,
+        ))
+        .with(Field::new(
+            "atten_step",
+            "Attenuation step",
+            Unit::Db,
+            caps.atten_step,
+        ))
+        .with(Field::text("sensors", "Sensors", sensors))
+        .with(Field::text("features", "Features", features))
+}
+
+pub fn param(info: &ParamInfo) -> Record {
+    // Switches have no range and read-only parameters can't be set
+    let range = |x: f32| (info.kind != ValueKind::Bool && !info.read_only).then_some(x);
+    Record::new(info)
+        .with(Field::text("name", "Name", &info.name))
+        .with(Field::text(
+            "kind",
+            "Kind",
+            format!("{:?}", info.kind).to_lowercase(),
+        ))
+        .with(Field::text("unit", "Unit", &info.unit))
+        .with(Field::new("min", "Min", Unit::None, range(info.min)))
+        .with(Field::new("max", "Max", Unit::None, range(info.max)))
+        .with(Field::new(
+            "read_only",
+            "Read-only",
+            Unit::None,
+            info.read_only,
+        ))
+        .with(Field::new(
+            "persisted",
+            "Persisted",
+            Unit::None,
+            info.persisted,
+        ))
+}
+
+/// JSON form of the value of a parameter
+#[derive(Serialize)]
+struct NamedValue<'a> {
+    name: &'a str,
+    value: &'a Value,
+    unit: &'a str,
+}
+
+pub fn value(info: &ParamInfo, value: &Value) -> Record {
+    let cell = match *value {
+        Value::Bool(b) => Cell::Bool(b),
+        Value::F32(x) => Cell::Float(x),
+        Value::U32(x) => Cell::Int(x.into()),
+    };
+    Record::new(NamedValue {
+        name: &info.name,
+        value,
+        unit: &info.unit,
+    })
+    .with(Field::text("name", "Name", &info.name))
+    .with(Field::new("value", "Value", Unit::None, cell))
+    .with(Field::text("unit", "Unit", &info.unit))
+}
+
+pub fn event(event: &Event) -> Record {
+    let (name, channel, rail, amps, celsius, reason) = match event.kind {
+        EventKind::IfLost { channel } => ("if-lost", Some(channel), None, None, None, None),
+        EventKind::IfRestored { channel } => ("if-restored", Some(channel), None, None, None, None),
+        EventKind::OverCurrent { rail, amps } => {
+            ("over-current", None, Some(rail), Some(amps), None, None)
+        }
+        EventKind::CurrentRestored { rail, amps } => {
+            ("current-restored", None, Some(rail), Some(amps), None, None)
+        }
+        EventKind::OverTemp { celsius } => ("over-temp", None, None, None, Some(celsius), None),
+        EventKind::TempRestored { celsius } => {
+            ("temp-restored", None, None, None, Some(celsius), None)
+        }
+        EventKind::Rebooted { reason } => ("rebooted", None, None, None, None, Some(reason)),
+    };
+    let text = |s: Option<String>| s.map_or(Cell::Missing, Cell::Text);
+    let rail = rail.map(|rail| match rail {
+        Rail::Lna1 => "lna1".to_string(),
+        Rail::Lna2 => "lna2".to_string(),
+        Rail::Analog => "analog".to_string(),
+    });
+    Record::new(event)
+        .with(Field::new(
+            "uptime",
+            "Uptime",
+            Unit::Millis,
+            event.uptime_ms,
+        ))
+        .with(Field::text("event", "Event", name))
+        .with(Field::new("channel", "Channel", Unit::None, channel))
+        .with(Field::new("rail", "Rail", Unit::None, text(rail)))
+        .with(Field::new(
+            "current",
+            "Current",
+            Unit::Amps,
+            amps.map(|a| a.amps()),
+        ))
+        .with(Field::new("temp", "Temp", Unit::Celsius, celsius))
+        .with(Field::new(
+            "reason",
+            "Reason",
+            Unit::None,
+            text(reason.map(|r| format!("{r:?}").to_lowercase())),
+        ))
+}
+
+/// JSON form of a FEM found on the bus
+#[derive(Serialize)]
+struct Found<'a> {
+    addr: u8,
+    identity: &'a Identity,
+}
+
+/// A FEM found on the bus
+pub fn found(addr: u8, identity: &Identity) -> Record {
+    // The address is text so it stands in for the one asked in Prometheus labels
+    Record::new(Found { addr, identity })
+        .with(Field::text("addr", "Address", addr))
+        .with(Field::text(
+            "serial",
+            "Serial number",
+            format!("{:016x}", identity.serial),
+        ))
+        .with(Field::text(
+            "firmware_version",
+            "Firmware version",
+            &identity.firmware_version,
+        ))
+        .with(Field::text("git_hash", "Git hash", &identity.git_hash))
+        .with(Field::new(
+            "protocol_version",
+            "Protocol version",
+            Unit::None,
+            identity.protocol_version,
+        ))
+}
+
+/// JSON form of how a command that does something went
+#[derive(Serialize)]
+struct Outcome<'a> {
+    command: &'a str,
+    ok: bool,
+    error: Option<&'a str>,
+    code: Option<u8>,
+    /// Outcome of every action of a batch
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    results: Vec<BatchResult<'a>>,
+}
+
+#[derive(Serialize)]
+struct BatchResult<'a> {
+    action: &'a Action,
+    result: &'a ActionResult,
+}
+
+/// Result of a command that does something, `error` being its message and exit code if it
+/// failed, and `results` the outcome of every action of a batch
+pub fn outcome(
+    command: &'static str,
+    error: Option<(String, u8)>,
+    results: &[(Action, ActionResult)],
+) -> Record {
+    let (message, code) = error.unzip();
+    let json = Outcome {
+        command,
+        ok: message.is_none(),
+        error: message.as_deref(),
+        code,
+        results: results
+            .iter()
+            .map(|(action, result)| BatchResult { action, result })
+            .collect(),
+    };
+    let mut record = Record::new(json)
+        .with(Field::text("command", "Command", command))
+        .with(Field::new("ok", "Ok", Unit::None, message.is_none()))
+        .with(Field::new(
+            "error",
+            "Error",
+            Unit::None,
+            message.map_or(Cell::Missing, Cell::Text),
+        ))
+        .with(Field::new("code", "Exit code", Unit::None, code));
+    for (action, result) in results {
+        let what = match action {
+            Action::Lna1Power(_) => "lna1",
+            Action::Lna2Power(_) => "lna2",
+            Action::SetAtten(_) => "atten",
+            Action::SetIfLevel(_) => "if",
+        };
+        let result = match result {
+            ActionResult::Applied => "applied".to_string(),
+            ActionResult::Rejected(code) => format!("rejected: {code}"),
+            ActionResult::Skipped => "skipped".to_string(),
+        };
+        record.push(Field::text("result", "Result", result).tag("action", what));
+    }
+    record
+}
@@ -0,0 +1,193 @@
+//! The CLI against a FEM on the other end of a pseudo terminal, that can do less than most
+#![cfg(unix)]
+
+use serialport::{SerialPort, TTYPort};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use transport::{
+    codec::{FrameDecoder, FrameEncoder},
+    Action, Actions, Capabilities, Command, Features, Frame, Identity, MonitorFields, Response,
+    Sensors, TelemetryPayload, MAX_COMMAND_FRAME_LEN, MAX_RESPONSE_FRAME_LEN, NO_SEQ,
+};
+
+/// A FEM with a single channel, an LNA it can't switch and no rail power monitor
+fn caps() -> Capabilities {
+    Capabilities {
+        actions: Actions::LNA1_POWER | Actions::SET_IF_LEVEL,
+        channels: 1,
+        atten_min: 0.0,
+        atten_max: 31.5,
+        atten_step: 0.5,
+        sensors: Sensors::IF_POWER | Sensors::IC_TEMP,
+        features: Features::NONE,
+    }
+}
+
+fn handle(cmd: &Command) -> Response {
+    match cmd {
+        Command::Identify => Response::Identity(Identity {
+            protocol_version: transport::PROTOCOL_VERSION,
+            ..Default::default()
+        }),
+        Command::Capabilities => Response::Capabilities(caps()),
+        Command::Control(_) | Command::Subscribe { .. } | Command::Unsubscribe => Response::Ack,
+        _ => Response::Error(transport::ErrorCode::Unsupported),
+    }
+}
+
+/// Play the FEM on `fem` until `done`, returning what it was sent
+///
+/// With `streaming`, it sends telemetry from when it's subscribed to until it's unsubscribed.
+fn fake(mut fem: TTYPort, done: Arc<AtomicBool>, streaming: bool) -> Vec<Command> {
+    fem.set_timeout(Duration::from_millis(10)).unwrap();
+    let mut decoder = FrameDecoder::<Frame<Command>, MAX_COMMAND_FRAME_LEN>::new();
+    let mut encoder = FrameEncoder::<MAX_RESPONSE_FRAME_LEN>::new();
+    let mut received = vec![];
+    let mut subscribed = false;
+    let mut buf = [0u8; 256];
+    while !done.load(Ordering::Relaxed) {
+        if subscribed {
+            let telemetry = Response::Telemetry(TelemetryPayload {
+                ic_temp: Some(30.0),
+                ..Default::default()
+            });
+            fem.write_all(encoder.encode_bounded(&Frame::new(NO_SEQ, telemetry)))
+                .unwrap();
+        }
+        let Ok(n) = fem.read(&mut buf) else {
+            continue;
+        };
+        let frames: Vec<_> = decoder.feed(&buf[..n]).filter_map(Result::ok).collect();
+        for frame in frames {
+            let resp = Frame::new(frame.seq, handle(&frame.body)).with_addr(frame.addr);
+            fem.write_all(encoder.encode_bounded(&resp)).unwrap();
+            match frame.body {
+                Command::Subscribe { .. } => subscribed = streaming,
+                Command::Unsubscribe => subscribed = false,
+                _ => (),
+            }
+            received.push(frame.body);
+        }
+    }
+    received
+}
+
+/// Run the CLI with `args` after the port, returning what it did and what the FEM was sent
+fn cli(args: &[&str]) -> (process::Output, Vec<Command>) {
+    let (fem, port) = TTYPort::pair().unwrap();
+    let done = Arc::new(AtomicBool::new(false));
+    let fake = thread::spawn({
+        let done = done.clone();
+        move || fake(fem, done, false)
+    });
+    let output = process::Command::new(env!("CARGO_BIN_EXE_cli"))
+        .arg(port.name().unwrap())
+        .args(args)
+        .output()
+        .unwrap();
+    done.store(true, Ordering::Relaxed);
+    (output, fake.join().unwrap())
+}
+
+fn controls(received: &[Command]) -> Vec<Action> {
+    received
+        .iter()
+        .filter_map(|cmd| match cmd {
+            Command::Control(action) => Some(*action),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn supported_commands_go_through() {
+    let (output, received) = cli(&["lna", "ch1", "enabled"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(controls(&received), [Action::Lna1Power(true)]);
+}
+
+#[test]
+fn unsupported_commands_are_refused() {
+    for (args, reason) in [
+        (&["lna", "ch2", "enabled"][..], "the FEM has no channel 2"),
+        (&["atten", "10"], "the FEM can't set the attenuation"),
+        (
+            &["batch", "--lna1", "enabled", "--atten", "10"],
+            "the FEM can't set the attenuation",
+        ),
+    ] {
+        let (output, received) = cli(&[&["-f", "json"], args].concat());
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains(reason), "{args:?}: {stdout}");
+        // None of it was sent
+        assert_eq!(controls(&received), [], "{args:?}");
+    }
+}
+
+#[test]
+fn unsupported_fields_are_refused() {
+    let (output, received) = cli(&["stream", "--fields", "if-power,lna2-power"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("the FEM has no channel 2"), "{stderr}");
+    assert!(!received
+        .iter()
+        .any(|cmd| matches!(cmd, Command::Subscribe { .. })));
+}
+
+#[test]
+fn streams_only_what_the_fem_has() {
+    // Nothing comes of the subscription, so it gives up after its first frame is late
+    let (_, received) = cli(&["stream", "--interval-ms", "10"]);
+    let fields = received.iter().find_map(|cmd| match cmd {
+        Command::Subscribe { fields, .. } => Some(*fields),
+        _ => None,
+    });
+    assert_eq!(
+        fields,
+        Some(MonitorFields::IF_POWER | MonitorFields::IC_TEMP)
+    );
+    assert_eq!(received.last(), Some(&Command::Unsubscribe));
+}
+
+#[test]
+fn interrupted_streams_unsubscribe() {
+    let (fem, port) = TTYPort::pair().unwrap();
+    let done = Arc::new(AtomicBool::new(false));
+    let fake = thread::spawn({
+        let done = done.clone();
+        move || fake(fem, done, true)
+    });
+    let mut child = process::Command::new(env!("CARGO_BIN_EXE_cli"))
+        .arg(port.name().unwrap())
+        .args(["-f", "json", "stream", "--interval-ms", "10"])
+        .stdout(process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Fields that weren't subscribed to are missing rather than zero
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    assert!(line.contains(r#""ic_temp":30.0"#), "{line}");
+    assert!(line.contains(r#""if1_power":null"#), "{line}");
+
+    let interrupted = process::Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(interrupted.success());
+    assert_eq!(child.wait().unwrap().code(), Some(130));
+    done.store(true, Ordering::Relaxed);
+    let received = fake.join().unwrap();
+    assert_eq!(received.last(), Some(&Command::Unsubscribe));
+}
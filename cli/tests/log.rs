@@ -0,0 +1,168 @@
+//! Where logged readings go, as the days turn over and files fill up
+
+use cli::log::{Log, LogFormat, Options};
+use std::{fs, path::Path, time::Duration};
+use tempfile::TempDir;
+use transport::MonitorPayload;
+
+fn options(dir: &Path, max_size: Option<u64>) -> Options {
+    Options {
+        port: String::new(),
+        addr: 1,
+        interval: Duration::from_secs(1),
+        dir: dir.to_path_buf(),
+        format: LogFormat::Csv,
+        max_size,
+    }
+}
+
+/// Log a reading at every time in `utcs`, with a log of its own
+fn log(opts: &Options, utcs: &[&str]) {
+    let mut log = Log::new(opts);
+    for (i, utc) in utcs.iter().enumerate() {
+        log.write(utc, i as u64 * 1000, &MonitorPayload::default())
+            .unwrap();
+    }
+    log.close().unwrap();
+}
+
+/// Names of the files in `dir`, in order
+fn files(dir: &Path) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Times of the readings in a CSV log, and how many headers it has
+fn readings(path: &Path) -> (Vec<String>, usize) {
+    let text = fs::read_to_string(path).unwrap();
+    let headers = text.lines().filter(|line| line.starts_with("utc,")).count();
+    let utcs = text
+        .lines()
+        .filter(|line| !line.starts_with("utc,"))
+        .map(|line| line.split(',').next().unwrap().to_string())
+        .collect();
+    (utcs, headers)
+}
+
+#[test]
+fn files_are_named_for_the_fem_and_day() {
+    let mut opts = options(Path::new("logs"), None);
+    opts.addr = 5;
+    assert_eq!(
+        opts.path("2026-10-17", 0),
+        Path::new("logs/fem5-2026-10-17.csv")
+    );
+    opts.format = LogFormat::Ndjson;
+    assert_eq!(
+        opts.path("2026-10-17", 2),
+        Path::new("logs/fem5-2026-10-17.2.ndjson")
+    );
+}
+
+#[test]
+fn new_files_are_started_at_midnight_utc() {
+    let dir = TempDir::new().unwrap();
+    let opts = options(dir.path(), None);
+    log(
+        &opts,
+        &[
+            "2026-10-17T23:59:59.000Z",
+            "2026-10-17T23:59:59.999Z",
+            "2026-10-18T00:00:00.000Z",
+        ],
+    );
+    assert_eq!(
+        files(dir.path()),
+        ["fem1-2026-10-17.csv", "fem1-2026-10-18.csv"]
+    );
+    assert_eq!(
+        readings(&opts.path("2026-10-17", 0)),
+        (
+            vec![
+                "2026-10-17T23:59:59.000Z".to_string(),
+                "2026-10-17T23:59:59.999Z".to_string()
+            ],
+            1
+        )
+    );
+    assert_eq!(
+        readings(&opts.path("2026-10-18", 0)),
+        (vec!["2026-10-18T00:00:00.000Z".to_string()], 1)
+    );
+}
+
+#[test]
+fn full_files_are_followed_by_another_part() {
+    let dir = TempDir::new().unwrap();
+    // Room for a header and a reading, but not another
+    let header_and_row = {
+        let probe = TempDir::new().unwrap();
+        let opts = options(probe.path(), None);
+        log(&opts, &["2026-10-17T12:00:00.000Z"]);
+        fs::metadata(opts.path("2026-10-17", 0)).unwrap().len()
+    };
+    let opts = options(dir.path(), Some(header_and_row + 1));
+    log(
+        &opts,
+        &[
+            "2026-10-17T12:00:00.000Z",
+            "2026-10-17T12:00:01.000Z",
+            "2026-10-17T12:00:02.000Z",
+        ],
+    );
+    assert_eq!(
+        files(dir.path()),
+        ["fem1-2026-10-17.1.csv", "fem1-2026-10-17.csv"]
+    );
+    assert_eq!(readings(&opts.path("2026-10-17", 0)).0.len(), 2);
+    assert_eq!(
+        readings(&opts.path("2026-10-17", 1)),
+        (vec!["2026-10-17T12:00:02.000Z".to_string()], 1)
+    );
+}
+
+#[test]
+fn logging_again_appends_without_another_header() {
+    let dir = TempDir::new().unwrap();
+    let opts = options(dir.path(), None);
+    log(&opts, &["2026-10-17T12:00:00.000Z"]);
+    log(&opts, &["2026-10-17T13:00:00.000Z"]);
+    assert_eq!(files(dir.path()), ["fem1-2026-10-17.csv"]);
+    assert_eq!(
+        readings(&opts.path("2026-10-17", 0)),
+        (
+            vec![
+                "2026-10-17T12:00:00.000Z".to_string(),
+                "2026-10-17T13:00:00.000Z".to_string()
+            ],
+            1
+        )
+    );
+}
+
+#[test]
+fn full_parts_are_skipped() {
+    let dir = TempDir::new().unwrap();
+    let opts = options(dir.path(), Some(1000));
+    fs::write(opts.path("2026-10-17", 0), [b'x'; 1000]).unwrap();
+    log(&opts, &["2026-10-17T12:00:00.000Z"]);
+    assert_eq!(
+        files(dir.path()),
+        ["fem1-2026-10-17.1.csv", "fem1-2026-10-17.csv"]
+    );
+    assert_eq!(fs::read(opts.path("2026-10-17", 0)).unwrap(), [b'x'; 1000]);
+    assert_eq!(
+        readings(&opts.path("2026-10-17", 1)),
+        (vec!["2026-10-17T12:00:00.000Z".to_string()], 1)
+    );
+
+    // Nor is an earlier part gone back to once there's a later one
+    fs::remove_file(opts.path("2026-10-17", 0)).unwrap();
+    log(&opts, &["2026-10-17T13:00:00.000Z"]);
+    assert_eq!(files(dir.path()), ["fem1-2026-10-17.1.csv"]);
+    assert_eq!(readings(&opts.path("2026-10-17", 1)).0.len(), 2);
+}
@@ -0,0 +1,284 @@
+//! Exactly what every format prints for the results of the commands
+
+use cli::{
+    output::{Field, Format, Output, Record},
+    report,
+};
+use transport::{
+    Amps, Dbm, Identity, MonitorPayload, MonitorPayloadV2, Power, TelemetryPayload, Validity, Volts,
+};
+
+/// Print `records` as a series of rows from the FEM at address 1
+fn rows(format: Format, records: &[Record]) -> String {
+    let mut out = Output::new(format, 1, vec![]);
+    for record in records {
+        out.row(record).unwrap();
+    }
+    out.finish().unwrap();
+    String::from_utf8(out.into_inner()).unwrap()
+}
+
+/// Print `record` on its own for the FEM at address 1
+fn show(format: Format, record: &Record) -> String {
+    let mut out = Output::new(format, 1, vec![]);
+    out.show(record).unwrap();
+    String::from_utf8(out.into_inner()).unwrap()
+}
+
+fn power(voltage: f32, current: f32) -> Power {
+    Power {
+        voltage: Volts::new(voltage).unwrap(),
+        current: Amps::new(current).unwrap(),
+    }
+}
+
+fn payload() -> MonitorPayload {
+    MonitorPayload {
+        if1_power: Dbm::new(-30.5).unwrap(),
+        if2_power: Dbm::new(-31.25).unwrap(),
+        ic_temp: 25.5,
+        lna1_power: power(5.0, 0.125),
+        lna2_power: power(5.0, 0.0625),
+        analog_power: power(6.0, 0.25),
+    }
+}
+
+/// A raw reading where IF1 and the analog rail current weren't valid
+fn raw() -> MonitorPayloadV2 {
+    let p = payload();
+    MonitorPayloadV2 {
+        uptime_ms: 60_000,
+        sample_seq: 7,
+        valid: Validity(Validity::ALL.0 & !(Validity::IF1_POWER.0 | Validity::ANALOG_CURRENT.0)),
+        if1_power: p.if1_power,
+        if1_counts: 1080,
+        if2_power: p.if2_power,
+        if2_counts: 1066,
+        ic_temp: p.ic_temp,
+        ic_temp_counts: 876,
+        lna1_power: p.lna1_power,
+        lna2_power: p.lna2_power,
+        analog_power: p.analog_power,
+    }
+}
+
+const MONITOR_HEADER: &str = "ch1_if_power_dbm,ch2_if_power_dbm,ic_temp_celsius,\
+    lna1_voltage_volts,lna1_current_amps,lna2_voltage_volts,lna2_current_amps,\
+    analog_voltage_volts,analog_current_amps";
+
+#[test]
+fn csv_header_is_printed_once() {
+    let record = report::monitor(&payload());
+    let row = "-30.5,-31.25,25.5,5,0.125,5,0.0625,6,0.25";
+    assert_eq!(
+        rows(
+            Format::Csv,
+            &[record.clone(), record.clone(), record.clone()]
+        ),
+        format!("{MONITOR_HEADER}\n{row}\n{row}\n{row}\n")
+    );
+
+    // Nor again under a header that's already there
+    let mut out = Output::new(Format::Csv, 1, vec![]).continuing();
+    out.row(&record).unwrap();
+    out.row(&record).unwrap();
+    assert_eq!(out.into_inner(), format!("{row}\n{row}\n").as_bytes());
+}
+
+#[test]
+fn csv_values_are_quoted_when_they_need_it() {
+    let record = Record::new(())
+        .with(Field::text("plain", "Plain", "fem1"))
+        .with(Field::text("comma", "Comma", "a,b"))
+        .with(Field::text("quote", "Quote", r#"say "hi""#))
+        .with(Field::text("newline", "Newline", "two\nlines"))
+        .with(Field::text("return", "Return", "a\rb"));
+    assert_eq!(
+        show(Format::Csv, &record),
+        "plain,comma,quote,newline,return\nfem1,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\",\"a\rb\"\n"
+    );
+}
+
+#[test]
+fn prometheus_metrics_are_named_and_labelled() {
+    assert_eq!(
+        show(Format::PrometheusText, &report::monitor(&payload())),
+        "\
+# HELP fem_if_power_dbm IF power
+# TYPE fem_if_power_dbm gauge
+fem_if_power_dbm{addr=\"1\",channel=\"ch1\"} -30.5
+fem_if_power_dbm{addr=\"1\",channel=\"ch2\"} -31.25
+# HELP fem_ic_temp_celsius RP2040 temp
+# TYPE fem_ic_temp_celsius gauge
+fem_ic_temp_celsius{addr=\"1\"} 25.5
+# HELP fem_voltage_volts Voltage
+# TYPE fem_voltage_volts gauge
+fem_voltage_volts{addr=\"1\",rail=\"lna1\"} 5
+fem_voltage_volts{addr=\"1\",rail=\"lna2\"} 5
+fem_voltage_volts{addr=\"1\",rail=\"analog\"} 6
+# HELP fem_current_amps Current
+# TYPE fem_current_amps gauge
+fem_current_amps{addr=\"1\",rail=\"lna1\"} 0.125
+fem_current_amps{addr=\"1\",rail=\"lna2\"} 0.0625
+fem_current_amps{addr=\"1\",rail=\"analog\"} 0.25
+"
+    );
+}
+
+#[test]
+fn prometheus_labels_are_escaped() {
+    let identity = Identity {
+        protocol_version: 2,
+        firmware_version: r#"0.3.0 "rc\1""#.into(),
+        git_hash: "0123456789ab\ndirty".into(),
+        build_timestamp: 1_700_000_000,
+        serial: 0xe660_5838_3b2f_6a2c,
+    };
+    let labels = r#"addr="1",firmware_version="0.3.0 \"rc\\1\"",git_hash="0123456789ab\ndirty",serial="e66058383b2f6a2c""#;
+    assert_eq!(
+        show(Format::PrometheusText, &report::identity(&identity)),
+        format!(
+            "\
+# HELP fem_build_timestamp Build timestamp
+# TYPE fem_build_timestamp gauge
+fem_build_timestamp{{{labels}}} 1700000000
+# HELP fem_protocol_version Protocol version
+# TYPE fem_protocol_version gauge
+fem_protocol_version{{{labels}}} 2
+"
+        )
+    );
+}
+
+#[test]
+fn prometheus_series_group_samples_by_metric() {
+    let mut hot = payload();
+    hot.ic_temp = 70.0;
+    let text = rows(
+        Format::PrometheusText,
+        &[report::monitor(&payload()), report::monitor(&hot)],
+    );
+    let temps: Vec<_> = text
+        .lines()
+        .skip_while(|line| !line.starts_with("# HELP fem_ic_temp_celsius"))
+        .take(4)
+        .collect();
+    assert_eq!(
+        temps,
+        [
+            "# HELP fem_ic_temp_celsius RP2040 temp",
+            "# TYPE fem_ic_temp_celsius gauge",
+            "fem_ic_temp_celsius{addr=\"1\"} 25.5",
+            "fem_ic_temp_celsius{addr=\"1\"} 70",
+        ]
+    );
+    assert_eq!(text.matches("# HELP").count(), 4);
+}
+
+#[test]
+fn prometheus_metrics_are_described_once() {
+    let mut hot = payload();
+    hot.ic_temp = 70.0;
+    let mut out = Output::new(Format::PrometheusText, 1, vec![]);
+    out.row(&report::monitor(&payload())).unwrap();
+    out.finish().unwrap();
+    let first = out.get_ref().len();
+    out.row(&report::monitor(&hot)).unwrap();
+    out.finish().unwrap();
+    let text = String::from_utf8(out.into_inner()).unwrap();
+    assert_eq!(text.matches("# HELP").count(), 4);
+    assert_eq!(text.matches("# TYPE").count(), 4);
+    assert!(text[first..].starts_with("fem_if_power_dbm{addr=\"1\",channel=\"ch1\"} -30.5\n"));
+    assert!(text[first..].contains("fem_ic_temp_celsius{addr=\"1\"} 70\n"));
+
+    // Nor at all under ones that are already there
+    let mut out = Output::new(Format::PrometheusText, 1, vec![]).continuing();
+    out.row(&report::monitor(&hot)).unwrap();
+    out.finish().unwrap();
+    let text = String::from_utf8(out.into_inner()).unwrap();
+    assert!(!text.contains('#'), "{text}");
+}
+
+#[test]
+fn invalid_readings_are_missing() {
+    let record = report::monitor_raw(&raw());
+    assert_eq!(
+        show(Format::Csv, &record),
+        format!(
+            "uptime_ms,sample_seq,{MONITOR_HEADER},ch1_if_power_counts,ch2_if_power_counts,\
+            ic_temp_counts\n60000,7,,-31.25,25.5,5,0.125,5,0.0625,6,,1080,1066,876\n"
+        )
+    );
+
+    let text = show(Format::PrometheusText, &record);
+    for sample in [
+        "fem_if_power_dbm{addr=\"1\",channel=\"ch1\"} NaN",
+        "fem_if_power_dbm{addr=\"1\",channel=\"ch2\"} -31.25",
+        "fem_current_amps{addr=\"1\",rail=\"analog\"} NaN",
+    ] {
+        assert!(text.lines().any(|line| line == sample), "{sample}\n{text}");
+    }
+
+    let table = show(Format::Table, &record);
+    assert!(table.contains("IF power ch1         -\n"), "{table}");
+    assert!(
+        table.contains("IF power ch2         -31.25 dBm\n"),
+        "{table}"
+    );
+}
+
+#[test]
+fn unsubscribed_telemetry_is_null() {
+    let telemetry = payload().select(transport::MonitorFields::IC_TEMP);
+    let record = report::telemetry(&telemetry);
+    assert_eq!(
+        show(Format::Json, &record),
+        "{\"if1_power\":null,\"if2_power\":null,\"ic_temp\":25.5,\"lna1_power\":null,\
+        \"lna2_power\":null,\"analog_power\":null}\n"
+    );
+    assert_eq!(
+        show(Format::Csv, &record),
+        format!("{MONITOR_HEADER}\n,,25.5,,,,,,\n")
+    );
+    let text = show(
+        Format::PrometheusText,
+        &report::telemetry(&TelemetryPayload::default()),
+    );
+    let samples: Vec<_> = text.lines().filter(|l| !l.starts_with('#')).collect();
+    assert_eq!(samples.len(), 9);
+    assert!(samples.iter().all(|s| s.ends_with(" NaN")), "{text}");
+}
+
+#[test]
+fn json_is_a_line_per_result() {
+    let done = report::outcome("atten", None, &[]);
+    let failed = report::outcome("lna", Some(("no response from the FEM".into(), 3)), &[]);
+    assert_eq!(
+        rows(Format::Json, &[done, failed]),
+        "{\"command\":\"atten\",\"ok\":true,\"error\":null,\"code\":null}\n\
+        {\"command\":\"lna\",\"ok\":false,\"error\":\"no response from the FEM\",\"code\":3}\n"
+    );
+}
+
+#[test]
+fn tables_are_aligned_under_one_header() {
+    let record = report::outcome("lna", Some(("no response from the FEM".into(), 3)), &[]);
+    assert_eq!(
+        show(Format::Table, &record),
+        "\
+Command    lna
+Ok         no
+Error      no response from the FEM
+Exit code  3
+"
+    );
+    let done = report::outcome("atten", None, &[]);
+    assert_eq!(
+        rows(Format::Table, &[done, record]),
+        "\
+Command  Ok   Error  Exit code
+atten    yes  -      -
+lna      no   no response from the FEM  3
+"
+    );
+}
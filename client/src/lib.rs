@@ -0,0 +1,407 @@
+//! Client library for monitoring and controlling a FEM over its serial port
+//!
+//! ```no_run
+//! use fem_client::{Fem, Lna};
+//! use transport::Attenuation;
+//!
+//! let mut fem = Fem::open("/dev/ttyACM0")?;
+//! fem.set_lna(Lna::Ch1, true)?;
+//! fem.set_attenuation(Attenuation::new(10.5)?)?;
+//! println!("{:?}", fem.monitor()?);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Only the current protocol is spoken unless asked otherwise, with [`Fem::detect`] or
+//! [`Fem::with_protocol`], for boards in the field still running 0.2 firmware.
+
+use serialport::SerialPort;
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+use tracing::warn;
+use transport::{
+    codec,
+    io::{BlockingClient, Protocol, Stats},
+    v0_2, Action, ActionResult, Attenuation, Capabilities, Command, ControlState, Dbm, ErrorCode,
+    Event, Identity, MonitorFields, MonitorPayload, MonitorPayloadV2, ObjectId, ParamId, ParamInfo,
+    Response, TelemetryPayload, Value, MAX_BATCH,
+};
+
+/// Baud rate of the FEM's serial port
+pub const BAUD: u32 = 115_200;
+
+/// Errors talking to the FEM
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The FEM didn't respond in time
+    #[error("no response from the FEM")]
+    Timeout,
+    /// Opening, reading or writing the port failed
+    #[error("serial port error: {0}")]
+    Io(#[from] io::Error),
+    /// The FEM responded with something other than what was asked for
+    #[error("unexpected response from the FEM: {0}")]
+    Decode(String),
+    /// The FEM refused or failed to carry out the command
+    #[error("{0}")]
+    Nak(ErrorCode),
+    /// The command couldn't be encoded
+    #[error("failed to encode command: {0}")]
+    Encode(codec::Error),
+    /// The FEM can't do what was asked, going by its [`Capabilities`]
+    #[error("{0}")]
+    Unsupported(String),
+}
+
+impl From<transport::io::Error> for Error {
+    fn from(e: transport::io::Error) -> Self {
+        match e {
+            transport::io::Error::Timeout => Error::Timeout,
+            transport::io::Error::Io(e) => Error::Io(e),
+            transport::io::Error::Closed => Error::Io(io::ErrorKind::UnexpectedEof.into()),
+            transport::io::Error::Encode(e) => Error::Encode(e),
+            transport::io::Error::Rejected(code) => Error::Nak(code),
+            transport::io::Error::Unexpected => {
+                Error::Decode("out of step with the transfer".into())
+            }
+            transport::io::Error::Unaddressable => Error::Unsupported(e.to_string()),
+        }
+    }
+}
+
+/// Error for a response that isn't the one asked for
+fn unexpected(resp: Response) -> Error {
+    Error::Decode(format!("{resp:?}"))
+}
+
+impl From<serialport::Error> for Error {
+    fn from(e: serialport::Error) -> Self {
+        Error::Io(e.into())
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// LNA channels of the FEM
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Lna {
+    Ch1,
+    Ch2,
+}
+
+/// Connection to a FEM, over a serial port unless made with [`Fem::new`]
+pub struct Fem<P = Box<dyn SerialPort>> {
+    client: BlockingClient<P>,
+    /// What the FEM can do, once it's been asked, `None` if its firmware is too old to say
+    caps: Option<Option<Capabilities>>,
+    /// Protocol the FEM speaks
+    protocol: Protocol,
+}
+
+impl Fem {
+    /// Open the FEM on the serial port at `path`
+    pub fn open(path: &str) -> Result<Self> {
+        Self::open_with_timeout(path, BlockingClient::<Box<dyn SerialPort>>::TIMEOUT)
+    }
+
+    /// Open the FEM on the serial port at `path`, waiting up to `timeout` for every response
+    pub fn open_with_timeout(path: &str, timeout: Duration) -> Result<Self> {
+        let port = serialport::new(path, BAUD).timeout(timeout).open()?;
+        Ok(Self::new(port).with_timeout(timeout))
+    }
+
+    /// Open a second connection to the same FEM on the same port, speaking the same protocol
+    ///
+    /// Meant for a last word from somewhere else, like unsubscribing from a signal handler,
+    /// so it waits no longer than `timeout` for responses.
+    pub fn try_clone(&self, timeout: Duration) -> Result<Self> {
+        let mut port = self.client.get_ref().try_clone()?;
+        port.set_timeout(timeout)?;
+        Ok(Self::new(port)
+            .with_timeout(timeout)
+            .with_addr(self.client.addr())
+            .with_protocol(self.protocol))
+    }
+}
+
+impl<P> Fem<P>
+where
+    P: Read + Write,
+{
+    /// Talk to the FEM over anything that can be read from and written to
+    ///
+    /// Reads on the port should time out rather than block forever.
+    pub fn new(port: P) -> Self {
+        Self {
+            client: BlockingClient::new(port),
+            caps: None,
+            protocol: Protocol::Framed,
+        }
+    }
+
+    /// Set the time to wait for a response
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.with_timeout(timeout);
+        self
+    }
+
+    /// Talk to the FEM in `protocol`, rather than the current one
+    ///
+    /// Only monitor and control are in the 0.2 protocol, everything else fails with
+    /// [`Error::Unsupported`].
+    pub fn with_protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Talk to the FEM at `addr` on the bus, rather than the one at [`transport::DEFAULT_ADDR`]
+    pub fn with_addr(mut self, addr: u8) -> Self {
+        self.client = self.client.with_addr(addr);
+        self
+    }
+
+    /// Send a command and wait for its response, turning errors reported by the FEM into
+    /// [`Error::Nak`]
+    pub fn request(&mut self, cmd: Command) -> Result<Response> {
+        let resp = match self.protocol {
+            Protocol::Framed => self.client.request(cmd),
+            Protocol::V0_2 => {
+                let cmd = v0_2::Command::try_from(cmd).map_err(|_| {
+                    Error::Unsupported("the FEM's 0.2 firmware can only monitor and control".into())
+                })?;
+                self.client.request_v0_2(cmd).map(Response::from)
+            }
+        };
+        let stats = self.client.stats();
+        if stats != Stats::default() {
+            warn!("Discarded frames while waiting for the response - {stats:?}");
+        }
+        match resp? {
+            Response::Error(code) => Err(Error::Nak(code)),
+            resp => Ok(resp),
+        }
+    }
+
+    /// Find out which protocol the FEM speaks and speak it from then on, see
+    /// [`BlockingClient::detect`]
+    ///
+    /// Without this, a FEM running 0.2 firmware just never answers.
+    pub fn detect(&mut self) -> Result<Protocol> {
+        self.protocol = self.client.detect()?;
+        // What a FEM can do goes with what it speaks
+        self.caps = None;
+        if self.protocol == Protocol::V0_2 {
+            warn!("The FEM is running 0.2 firmware and should be updated");
+        }
+        Ok(self.protocol)
+    }
+
+    /// Send a command that's only acknowledged
+    fn command(&mut self, cmd: Command) -> Result<()> {
+        match self.request(cmd)? {
+            Response::Ack => Ok(()),
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    /// Get the latest monitor data
+    pub fn monitor(&mut self) -> Result<MonitorPayload> {
+        match self.request(Command::Monitor)? {
+            Response::Monitor(payload) => Ok(payload),
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    /// Get the latest monitor data along with the raw readings behind it
+    pub fn monitor_raw(&mut self) -> Result<MonitorPayloadV2> {
+        match self.request(Command::MonitorV2)? {
+            Response::MonitorV2(payload) => Ok(payload),
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    /// Get the firmware version and serial number of the FEM
+    pub fn identify(&mut self) -> Result<Identity> {
+        match self.request(Command::Identify)? {
+            Response::Identity(identity) => Ok(identity),
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    /// Get the current control settings
+    pub fn state(&mut self) -> Result<ControlState> {
+        match self.request(Command::GetState)? {
+            Response::State(state) => Ok(state),
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    /// Get what the FEM can do, `None` if its firmware is too old to say
+    ///
+    /// The FEM is only asked until it answers, later calls return what it said then. One that
+    /// never answers the question but still says who it is is taken to be too old to.
+    pub fn capabilities(&mut self) -> Result<Option<Capabilities>> {
+        if let Some(caps) = &self.caps {
+            return Ok(caps.clone());
+        }
+        if self.protocol == Protocol::V0_2 {
+            return Ok(self.caps.insert(None).clone());
+        }
+        // A FEM that doesn't answer could just be slow, so nothing's kept from it
+        let caps = match self.client.request(Command::Capabilities) {
+            Ok(Response::Capabilities(caps)) => Some(caps),
+            // Older firmware can't read the command, and checks everything itself
+            Ok(Response::Error(ErrorCode::Deserialize | ErrorCode::Unsupported)) => None,
+            Ok(Response::Error(code)) => return Err(Error::Nak(code)),
+            Ok(resp) => return Err(unexpected(resp)),
+            // Some firmware drops what it can't read, so see whether it's there at all
+            Err(transport::io::Error::Timeout) => {
+                self.identify()?;
+                None
+            }
+            Err(e) => return Err(e.into()),
+        };
+        self.caps = Some(caps.clone());
+        Ok(caps)
+    }
+
+    /// Make sure the FEM can perform an action before asking it to
+    fn check_action(&mut self, action: &Action) -> Result<()> {
+        let Some(caps) = self.capabilities()? else {
+            return Ok(());
+        };
+        if !caps.actions.contains(action.into()) {
+            return Err(Error::Unsupported(format!("the FEM can't do {action:?}")));
+        }
+        match action {
+            Action::SetAtten(level) if !caps.atten_valid(level.db()) => {
+                Err(Error::Unsupported(format!(
+                    "attenuation level must be between {} and {} dB in steps of {} dB",
+                    caps.atten_min, caps.atten_max, caps.atten_step
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Perform a single action, if the FEM can
+    pub fn control(&mut self, action: Action) -> Result<()> {
+        self.check_action(&action)?;
+        self.command(Command::Control(action))
+    }
+
+    /// Turn the power of an LNA on or off
+    pub fn set_lna(&mut self, channel: Lna, on: bool) -> Result<()> {
+        self.control(match channel {
+            Lna::Ch1 => Action::Lna1Power(on),
+            Lna::Ch2 => Action::Lna2Power(on),
+        })
+    }
+
+    /// Set the attenuation of both channels
+    pub fn set_attenuation(&mut self, db: Attenuation) -> Result<()> {
+        self.control(Action::SetAtten(db))
+    }
+
+    /// Set the IF "power good" threshold
+    pub fn set_if_threshold(&mut self, dbm: Dbm) -> Result<()> {
+        self.control(Action::SetIfLevel(dbm))
+    }
+
+    /// Perform all of the actions or none of them, returning the outcome of every one
+    ///
+    /// A batch the FEM rejected isn't an error here, it's up to the caller to go through the
+    /// outcomes.
+    pub fn batch(&mut self, actions: &[Action]) -> Result<Vec<ActionResult>> {
+        let batch = heapless::Vec::<_, MAX_BATCH>::from_slice(actions).map_err(|()| {
+            Error::Unsupported(format!("a batch holds at most {MAX_BATCH} actions"))
+        })?;
+        for action in actions {
+            self.check_action(action)?;
+        }
+        match self.request(Command::Batch(batch))? {
+            Response::Batch(results) => Ok(results.into_iter().collect()),
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    /// Give the FEM a new bus address, kept across power cycles
+    ///
+    /// The client carries on talking to the old address.
+    pub fn set_address(&mut self, addr: u8) -> Result<()> {
+        self.command(Command::SetAddress(addr))
+    }
+
+    /// Find every FEM on the bus, see [`BlockingClient::enumerate`]
+    pub fn scan(&mut self) -> Result<Vec<(u8, Identity)>> {
+        Ok(self.client.enumerate()?)
+    }
+
+    /// Get the description of every parameter of the FEM
+    pub fn params(&mut self) -> Result<Vec<ParamInfo>> {
+        match self.request(Command::ListParams)? {
+            Response::Params(params) => Ok(params.into_iter().collect()),
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    /// Get the value of a parameter
+    pub fn get(&mut self, id: ParamId) -> Result<Value> {
+        match self.request(Command::Get(id))? {
+            Response::Value(value) => Ok(value),
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    /// Set the value of a parameter
+    pub fn set(&mut self, id: ParamId, value: Value) -> Result<()> {
+        self.command(Command::Set(id, value))
+    }
+
+    /// Upload an object to the FEM, see [`BlockingClient::upload`]
+    pub fn upload(&mut self, object: ObjectId, data: &[u8]) -> Result<()> {
+        Ok(self.client.upload(object, data)?)
+    }
+
+    /// Download an object from the FEM, see [`BlockingClient::download`]
+    pub fn download(&mut self, object: ObjectId) -> Result<Vec<u8>> {
+        Ok(self.client.download(object)?)
+    }
+
+    /// Have the FEM send the selected monitor fields every `interval_ms`, to be read with
+    /// [`Fem::telemetry`]
+    pub fn subscribe(&mut self, interval_ms: u32, fields: MonitorFields) -> Result<()> {
+        self.command(Command::Subscribe {
+            interval_ms,
+            fields,
+        })
+    }
+
+    /// Stop the telemetry started with [`Fem::subscribe`]
+    pub fn unsubscribe(&mut self) -> Result<()> {
+        self.command(Command::Unsubscribe)
+    }
+
+    /// Iterate over the telemetry frames of a subscription
+    ///
+    /// Every frame is waited on for up to the timeout of the client, so it should be set
+    /// longer than the interval of the subscription. Fields that weren't subscribed to are
+    /// `None`.
+    pub fn telemetry(&mut self) -> impl Iterator<Item = Result<TelemetryPayload>> + '_ {
+        self.client.telemetry().map(|p| p.map_err(Error::from))
+    }
+
+    /// Iterate over the events reported by the FEM
+    ///
+    /// Every event is waited on for up to the timeout of the client, yielding
+    /// [`Error::Timeout`] if there wasn't one.
+    pub fn events(&mut self) -> impl Iterator<Item = Result<Event>> + '_ {
+        self.client.events().map(|e| e.map_err(Error::from))
+    }
+
+    /// Get back the underlying port
+    pub fn into_inner(self) -> P {
+        self.client.into_inner()
+    }
+}
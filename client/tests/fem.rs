@@ -0,0 +1,305 @@
+//! The client against a FEM that lives in the test
+
+use fem_client::{Error, Fem, Lna};
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+use transport::{
+    codec::{FrameDecoder, FrameEncoder},
+    io::Protocol,
+    v0_2, Action, Actions, Attenuation, Capabilities, Command, Dbm, ErrorCode, Features, Frame,
+    Identity, MonitorFields, MonitorPayload, Response, Sensors, MAX_COMMAND_FRAME_LEN,
+    MAX_RESPONSE_FRAME_LEN, NO_SEQ,
+};
+
+/// FEM that answers with canned responses and remembers what it was sent
+struct FakeFem {
+    decoder: FrameDecoder<Frame<Command>, MAX_COMMAND_FRAME_LEN>,
+    encoder: FrameEncoder<MAX_RESPONSE_FRAME_LEN>,
+    outbox: Vec<u8>,
+    received: Vec<Command>,
+    /// What the FEM says it can do, `None` for firmware that predates capabilities
+    caps: Option<Capabilities>,
+    /// Error to reject every action with
+    reject: Option<ErrorCode>,
+    /// Whether the FEM answers at all
+    silent: bool,
+    /// Whether the FEM drops commands it doesn't know, rather than rejecting them
+    drop_unknown: bool,
+    /// Whether the FEM runs 0.2 firmware, and only speaks its protocol
+    v0_2: bool,
+    /// Whether the FEM has a subscription running, and loses the acks to controls
+    streaming: bool,
+}
+
+impl Default for FakeFem {
+    fn default() -> Self {
+        Self {
+            decoder: FrameDecoder::new(),
+            encoder: FrameEncoder::new(),
+            outbox: vec![],
+            received: vec![],
+            caps: Some(Capabilities {
+                actions: Actions::ALL,
+                channels: 2,
+                atten_min: 0.0,
+                atten_max: 31.5,
+                atten_step: 0.5,
+                sensors: Sensors::IF_POWER,
+                features: Features::NONE,
+            }),
+            reject: None,
+            silent: false,
+            drop_unknown: false,
+            v0_2: false,
+            streaming: false,
+        }
+    }
+}
+
+impl FakeFem {
+    fn handle(&mut self, cmd: &Command) -> Response {
+        match (cmd, &self.caps, self.reject) {
+            (Command::Monitor, ..) => Response::Monitor(payload()),
+            (Command::Identify, ..) => Response::Identity(Identity::default()),
+            (Command::Capabilities, Some(caps), _) => Response::Capabilities(caps.clone()),
+            (Command::Control(_), _, Some(code)) => Response::Error(code),
+            (Command::Control(_), _, None) => Response::Ack,
+            _ => Response::Error(ErrorCode::Unsupported),
+        }
+    }
+
+    /// Controls the FEM was asked to perform
+    fn actions(&self) -> Vec<Action> {
+        self.received
+            .iter()
+            .filter_map(|cmd| match cmd {
+                Command::Control(action) => Some(*action),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Write for FakeFem {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.v0_2 {
+            return self.write_v0_2(buf);
+        }
+        // Like the firmware, drop what it can't read
+        let frames: Vec<_> = self.decoder.feed(buf).filter_map(Result::ok).collect();
+        for frame in frames {
+            let resp = self.handle(&frame.body);
+            self.received.push(frame.body);
+            let lost = self.streaming && resp == Response::Ack
+                || self.drop_unknown && resp == Response::Error(ErrorCode::Unsupported);
+            if !self.silent && !lost {
+                let bytes = self.encoder.encode_bounded(&Frame::new(frame.seq, resp));
+                self.outbox.extend_from_slice(bytes);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl FakeFem {
+    /// Like 0.2 firmware, answer monitor and control in bare frames and ignore the rest
+    fn write_v0_2(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut bytes = buf.to_vec();
+        for frame in bytes.split_mut(|&b| b == 0).filter(|f| !f.is_empty()) {
+            let Ok(cmd) = v0_2::decode::<v0_2::Command>(frame) else {
+                continue;
+            };
+            let cmd = Command::from(cmd);
+            let resp = v0_2::Response::try_from(self.handle(&cmd)).unwrap();
+            self.received.push(cmd);
+            let mut out = [0u8; MAX_RESPONSE_FRAME_LEN];
+            self.outbox
+                .extend_from_slice(v0_2::encode(&resp, &mut out).unwrap());
+        }
+        Ok(buf.len())
+    }
+}
+
+impl Read for FakeFem {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.streaming {
+            let telemetry = payload().select(MonitorFields::IC_TEMP);
+            let frame = Frame::new(NO_SEQ, Response::Telemetry(telemetry));
+            let bytes = self.encoder.encode_bounded(&frame);
+            self.outbox.extend_from_slice(bytes);
+        }
+        if self.outbox.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let n = buf.len().min(self.outbox.len());
+        buf[..n].copy_from_slice(&self.outbox[..n]);
+        self.outbox.drain(..n);
+        Ok(n)
+    }
+}
+
+fn payload() -> MonitorPayload {
+    MonitorPayload {
+        if1_power: Dbm::new(-30.5).unwrap(),
+        ic_temp: 27.0,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn monitor_and_control() {
+    let mut fem = Fem::new(FakeFem::default());
+    assert_eq!(fem.monitor().unwrap(), payload());
+    fem.set_lna(Lna::Ch2, true).unwrap();
+    fem.set_attenuation(Attenuation::new(10.5).unwrap())
+        .unwrap();
+    fem.set_if_threshold(Dbm::new(-20.0).unwrap()).unwrap();
+
+    let fake = fem.into_inner();
+    assert_eq!(
+        fake.actions(),
+        [
+            Action::Lna2Power(true),
+            Action::SetAtten(Attenuation::new(10.5).unwrap()),
+            Action::SetIfLevel(Dbm::new(-20.0).unwrap()),
+        ]
+    );
+    // The capabilities were only asked for once
+    let asked = fake
+        .received
+        .iter()
+        .filter(|cmd| **cmd == Command::Capabilities);
+    assert_eq!(asked.count(), 1);
+}
+
+#[test]
+fn naks_are_errors() {
+    let fake = FakeFem {
+        reject: Some(ErrorCode::Busy),
+        ..Default::default()
+    };
+    let mut fem = Fem::new(fake);
+    assert!(matches!(
+        fem.set_lna(Lna::Ch1, false),
+        Err(Error::Nak(ErrorCode::Busy))
+    ));
+}
+
+#[test]
+fn actions_checked_against_capabilities() {
+    let mut fake = FakeFem::default();
+    if let Some(caps) = &mut fake.caps {
+        caps.actions = Actions::LNA1_POWER | Actions::SET_ATTEN;
+        caps.atten_step = 1.0;
+    }
+    let mut fem = Fem::new(fake);
+    assert!(matches!(
+        fem.set_lna(Lna::Ch2, true),
+        Err(Error::Unsupported(_))
+    ));
+    assert!(matches!(
+        fem.set_attenuation(Attenuation::new(10.5).unwrap()),
+        Err(Error::Unsupported(_))
+    ));
+    assert!(matches!(
+        fem.batch(&[Action::Lna1Power(true), Action::Lna2Power(true)]),
+        Err(Error::Unsupported(_))
+    ));
+    // None of that was sent
+    assert_eq!(fem.into_inner().actions(), []);
+}
+
+#[test]
+fn old_firmware_checks_for_itself() {
+    let fake = FakeFem {
+        caps: None,
+        ..Default::default()
+    };
+    let mut fem = Fem::new(fake);
+    assert_eq!(fem.capabilities().unwrap(), None);
+    fem.set_lna(Lna::Ch2, true).unwrap();
+    assert_eq!(fem.into_inner().actions(), [Action::Lna2Power(true)]);
+}
+
+#[test]
+fn old_firmware_that_drops_commands_checks_for_itself() {
+    let fake = FakeFem {
+        caps: None,
+        drop_unknown: true,
+        ..Default::default()
+    };
+    let mut fem = Fem::new(fake).with_timeout(Duration::from_millis(50));
+    fem.set_lna(Lna::Ch2, true).unwrap();
+    fem.set_lna(Lna::Ch1, false).unwrap();
+    assert_eq!(fem.capabilities().unwrap(), None);
+    let fake = fem.into_inner();
+    assert_eq!(
+        fake.actions(),
+        [Action::Lna2Power(true), Action::Lna1Power(false)]
+    );
+    // Only asked the once
+    let asked = fake
+        .received
+        .iter()
+        .filter(|cmd| **cmd == Command::Capabilities);
+    assert_eq!(asked.count(), 1);
+}
+
+#[test]
+fn silent_fems_time_out() {
+    let fake = FakeFem {
+        silent: true,
+        ..Default::default()
+    };
+    let mut fem = Fem::new(fake).with_timeout(Duration::from_millis(50));
+    assert!(matches!(fem.monitor(), Err(Error::Timeout)));
+    assert!(matches!(fem.set_lna(Lna::Ch1, true), Err(Error::Timeout)));
+    // Not taken for old firmware, which would stop the checks
+    assert!(matches!(fem.capabilities(), Err(Error::Timeout)));
+    let fake = fem.into_inner();
+    let asked = fake
+        .received
+        .iter()
+        .filter(|cmd| **cmd == Command::Capabilities);
+    assert_eq!(asked.count(), 2);
+    assert_eq!(fake.actions(), []);
+}
+
+#[test]
+fn lost_acks_are_never_taken_from_telemetry() {
+    let fake = FakeFem {
+        streaming: true,
+        ..Default::default()
+    };
+    let mut fem = Fem::new(fake).with_timeout(Duration::from_millis(50));
+    assert!(matches!(fem.set_lna(Lna::Ch1, true), Err(Error::Timeout)));
+    let telemetry = fem.telemetry().next().unwrap().unwrap();
+    assert_eq!(telemetry.ic_temp, Some(payload().ic_temp));
+    assert_eq!(telemetry.if1_power, None);
+    // It was only sent the once
+    assert_eq!(fem.into_inner().actions(), [Action::Lna1Power(true)]);
+}
+
+#[test]
+fn old_firmware_only_when_asked() {
+    let fake = FakeFem {
+        v0_2: true,
+        ..Default::default()
+    };
+    let mut fem = Fem::new(fake).with_timeout(Duration::from_millis(50));
+    assert!(matches!(fem.monitor(), Err(Error::Timeout)));
+    assert!(matches!(fem.set_lna(Lna::Ch2, true), Err(Error::Timeout)));
+
+    assert_eq!(fem.detect().unwrap(), Protocol::V0_2);
+    assert_eq!(fem.monitor().unwrap(), payload());
+    fem.set_lna(Lna::Ch2, true).unwrap();
+    assert_eq!(fem.capabilities().unwrap(), None);
+    assert!(matches!(fem.identify(), Err(Error::Unsupported(_))));
+    assert_eq!(fem.into_inner().actions(), [Action::Lna2Power(true)]);
+}
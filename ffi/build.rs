@@ -0,0 +1,14 @@
+//! Generates `include/fem.h` from the exported types and functions, so the header that's
+//! checked in always matches the library.
+
+use std::{env, path::PathBuf};
+
+fn main() {
+    let dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+    cbindgen::generate_with_config(&dir, config)
+        .expect("Failed to generate the C header")
+        .write_to_file(dir.join("include/fem.h"));
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
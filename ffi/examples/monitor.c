@@ -0,0 +1,37 @@
+// Print the monitor data of a FEM
+//
+// cargo build --release -p fem-ffi
+// cc ffi/examples/monitor.c -Iffi/include -Ltarget/release -lfem -o monitor
+
+#include <stdio.h>
+
+#include "fem.h"
+
+int main(int argc, char **argv) {
+  if (argc != 2) {
+    fprintf(stderr, "usage: %s <serial port>\n", argv[0]);
+    return 1;
+  }
+  FemClient *fem = fem_open(argv[1], 1000);
+  if (fem == NULL) {
+    fprintf(stderr, "failed to open %s\n", argv[1]);
+    return 1;
+  }
+
+  FemMonitorPayload mon;
+  FemErrorCode error;
+  FemStatus status = fem_monitor(fem, &mon, &error);
+  if (status == FEM_STATUS_OK) {
+    printf("IF1 power:   %.2f dBm\n", mon.if1_power);
+    printf("IF2 power:   %.2f dBm\n", mon.if2_power);
+    printf("Temperature: %.1f C\n", mon.ic_temp);
+    printf("LNA1:        %.2f V, %.3f A\n", mon.lna1_power.voltage, mon.lna1_power.current);
+    printf("LNA2:        %.2f V, %.3f A\n", mon.lna2_power.voltage, mon.lna2_power.current);
+    printf("Analog:      %.2f V, %.3f A\n", mon.analog_power.voltage, mon.analog_power.current);
+  } else {
+    fprintf(stderr, "monitor failed with status %d\n", status);
+  }
+
+  fem_close(fem);
+  return status == FEM_STATUS_OK ? 0 : 1;
+}
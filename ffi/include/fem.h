@@ -0,0 +1,253 @@
+#ifndef FEM_H
+#define FEM_H
+
+/* Generated by cbindgen from ffi/src, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Outcome of every call
+typedef enum FemStatus {
+  FEM_STATUS_OK,
+  // A required pointer was null
+  FEM_STATUS_NULL_ARGUMENT,
+  // The output buffer can't hold the frame
+  FEM_STATUS_BUFFER_TOO_SMALL,
+  // The frame couldn't be decoded
+  FEM_STATUS_MALFORMED,
+  // The frame failed its checksum
+  FEM_STATUS_CHECKSUM,
+  // The message has no C equivalent
+  FEM_STATUS_UNSUPPORTED,
+  // The serial port couldn't be opened, read or written
+  FEM_STATUS_IO,
+  // The FEM didn't respond in time
+  FEM_STATUS_NO_RESPONSE,
+  // The FEM refused or failed to carry out the command, see the [`FemErrorCode`]
+  FEM_STATUS_REJECTED,
+  // A value is out of range for its quantity, like a NaN attenuation
+  FEM_STATUS_OUT_OF_RANGE,
+} FemStatus;
+
+// Connection to a FEM, only ever handled through a pointer
+typedef struct FemClient FemClient;
+
+// Actions that can be performed
+typedef enum FemAction_Tag {
+  // Set the IF "Good" power threshold in dBm
+  FEM_ACTION_SET_IF_LEVEL,
+  // Control the power state of the LNA1 regulator
+  FEM_ACTION_LNA1_POWER,
+  // Control the power state of the LNA2 regulator
+  FEM_ACTION_LNA2_POWER,
+  // Set attenuation
+  FEM_ACTION_SET_ATTEN,
+} FemAction_Tag;
+
+typedef struct FemAction {
+  FemAction_Tag tag;
+  union {
+    struct {
+      float set_if_level;
+    };
+    struct {
+      bool lna1_power;
+    };
+    struct {
+      bool lna2_power;
+    };
+    struct {
+      float set_atten;
+    };
+  };
+} FemAction;
+
+// Payloads from MnC software to the FEM
+typedef enum FemCommand_Tag {
+  FEM_COMMAND_MONITOR,
+  FEM_COMMAND_CONTROL,
+} FemCommand_Tag;
+
+typedef struct FemCommand {
+  FemCommand_Tag tag;
+  union {
+    struct {
+      struct FemAction control;
+    };
+  };
+} FemCommand;
+
+// Reasons the FEM could not carry out a command
+typedef enum FemErrorCode_Tag {
+  // The incoming command could not be deserialized
+  FEM_ERROR_CODE_DESERIALIZE,
+  // A value was outside of the supported (inclusive) range
+  FEM_ERROR_CODE_OUT_OF_RANGE,
+  // Communication with an SPI peripheral failed
+  FEM_ERROR_CODE_SPI_FAULT,
+  // Communication with an I2C peripheral failed
+  FEM_ERROR_CODE_I2C_FAULT,
+  // The command is not supported by this FEM
+  FEM_ERROR_CODE_UNSUPPORTED,
+  // The FEM can't handle the command right now
+  FEM_ERROR_CODE_BUSY,
+  // The command was corrupted on the way to the FEM
+  FEM_ERROR_CODE_CHECKSUM,
+  // The FEM failed without saying why, only reported by 0.2 firmware
+  FEM_ERROR_CODE_UNKNOWN,
+  // The parameter can't be set
+  FEM_ERROR_CODE_READ_ONLY,
+  // The value is the wrong kind for the parameter
+  FEM_ERROR_CODE_WRONG_TYPE,
+  // A chunk arrived without an upload underway
+  FEM_ERROR_CODE_NOT_STARTED,
+} FemErrorCode_Tag;
+
+typedef struct FemErrorCode_OutOfRange_Body {
+  float min;
+  float max;
+} FemErrorCode_OutOfRange_Body;
+
+typedef struct FemErrorCode {
+  FemErrorCode_Tag tag;
+  union {
+    FemErrorCode_OutOfRange_Body OUT_OF_RANGE;
+  };
+} FemErrorCode;
+
+// Voltage and current of a supply rail
+typedef struct FemPower {
+  // Voltage in volts
+  float voltage;
+  // Current in amps
+  float current;
+} FemPower;
+
+// Monitor data sent in response to a monitor command
+typedef struct FemMonitorPayload {
+  // IF1 power in dBm
+  float if1_power;
+  // IF2 power in dBm
+  float if2_power;
+  // RP2040 internal temperature in C
+  float ic_temp;
+  // Voltage and current of LNA1
+  struct FemPower lna1_power;
+  // Voltage and current of LNA2
+  struct FemPower lna2_power;
+  // Voltage and current of the analog rail
+  struct FemPower analog_power;
+} FemMonitorPayload;
+
+// Payloads from FEM to MnC software
+typedef enum FemResponse_Tag {
+  // Previous command was ok, but didn't need a response
+  FEM_RESPONSE_ACK,
+  // Previous command failed
+  FEM_RESPONSE_ERROR,
+  // Response to monitor request
+  FEM_RESPONSE_MONITOR,
+} FemResponse_Tag;
+
+typedef struct FemResponse {
+  FemResponse_Tag tag;
+  union {
+    struct {
+      struct FemErrorCode error;
+    };
+    struct {
+      struct FemMonitorPayload monitor;
+    };
+  };
+} FemResponse;
+
+// Encode a command with sequence number `seq` as a complete frame, including the zero
+// delimiter, writing its length to `written`
+//
+// Fails with [`FemStatus::OutOfRange`] if a quantity in it is out of range, like a NaN.
+//
+// # Safety
+// `cmd` must point to a valid command, `buf` must be valid for writes of `len` bytes and
+// `written` must be null or valid for writes.
+enum FemStatus fem_encode_command(uint16_t seq,
+                                  const struct FemCommand *cmd,
+                                  uint8_t *buf,
+                                  size_t len,
+                                  size_t *written);
+
+// Decode a command frame, with or without its zero delimiter
+//
+// # Safety
+// `frame` must be valid for reads of `len` bytes, `seq` and `cmd` must be null or valid for
+// writes.
+enum FemStatus fem_decode_command(const uint8_t *frame,
+                                  size_t len,
+                                  uint16_t *seq,
+                                  struct FemCommand *cmd);
+
+// Encode a response with sequence number `seq` as a complete frame, including the zero
+// delimiter, writing its length to `written`
+//
+// Fails with [`FemStatus::OutOfRange`] if a quantity in it is out of range, like a NaN.
+//
+// # Safety
+// `resp` must point to a valid response, `buf` must be valid for writes of `len` bytes and
+// `written` must be null or valid for writes.
+enum FemStatus fem_encode_response(uint16_t seq,
+                                   const struct FemResponse *resp,
+                                   uint8_t *buf,
+                                   size_t len,
+                                   size_t *written);
+
+// Decode a response frame, with or without its zero delimiter
+//
+// # Safety
+// `frame` must be valid for reads of `len` bytes, `seq` and `resp` must be null or valid for
+// writes.
+enum FemStatus fem_decode_response(const uint8_t *frame,
+                                   size_t len,
+                                   uint16_t *seq,
+                                   struct FemResponse *resp);
+
+// Open the FEM on the serial port at `path`, waiting up to `timeout_ms` for every response
+//
+// Returns null if the port couldn't be opened. Close the client with [`fem_close`].
+//
+// # Safety
+// `path` must be a valid, nul-terminated string.
+struct FemClient *fem_open(const char *path, uint32_t timeout_ms);
+
+// Close a client opened with [`fem_open`]
+//
+// # Safety
+// `client` must be null or a client from [`fem_open`] that hasn't been closed yet.
+void fem_close(struct FemClient *client);
+
+// Get the latest monitor data from the FEM
+//
+// If the FEM reports an error, [`FemStatus::Rejected`] is returned and the reason is
+// written to `error`.
+//
+// # Safety
+// `client` must be an open client, `payload` must be valid for writes and `error` must be
+// null or valid for writes.
+enum FemStatus fem_monitor(struct FemClient *client,
+                           struct FemMonitorPayload *payload,
+                           struct FemErrorCode *error);
+
+// Perform an action on the FEM
+//
+// If the FEM reports an error, [`FemStatus::Rejected`] is returned and the reason is
+// written to `error`. An action with a value out of range isn't sent at all, and fails with
+// [`FemStatus::OutOfRange`].
+//
+// # Safety
+// `client` must be an open client and `error` must be null or valid for writes.
+enum FemStatus fem_control(struct FemClient *client,
+                           struct FemAction action,
+                           struct FemErrorCode *error);
+
+#endif  /* FEM_H */
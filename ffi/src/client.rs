@@ -0,0 +1,152 @@
+//! Blocking client over a serial port, for C callers
+
+use crate::{FemAction, FemErrorCode, FemMonitorPayload, FemStatus};
+use serialport::SerialPort;
+use std::{ffi::CStr, os::raw::c_char, time::Duration};
+use transport::{
+    io::{BlockingClient, Protocol},
+    v0_2, Command, Response,
+};
+
+/// Baud rate of the FEM's serial port
+const FEM_BAUD: u32 = 115_200;
+
+/// Connection to a FEM, only ever handled through a pointer
+pub struct FemClient {
+    client: BlockingClient<Box<dyn SerialPort>>,
+    /// Protocol the FEM speaks, once it's been found out
+    protocol: Option<Protocol>,
+}
+
+impl FemClient {
+    /// Send a command and wait for its response, in whichever protocol the FEM speaks
+    fn request(&mut self, cmd: Command) -> Result<Response, FemStatus> {
+        self.try_request(cmd).map_err(|e| match e {
+            transport::io::Error::Timeout => FemStatus::NoResponse,
+            transport::io::Error::Encode(e) => e.into(),
+            transport::io::Error::Io(_) | transport::io::Error::Closed => FemStatus::Io,
+            // Only bulk transfers fail like these
+            transport::io::Error::Rejected(_) => FemStatus::Rejected,
+            transport::io::Error::Unexpected | transport::io::Error::Unaddressable => {
+                FemStatus::Unsupported
+            }
+        })
+    }
+
+    fn try_request(&mut self, cmd: Command) -> Result<Response, transport::io::Error> {
+        let protocol = match self.protocol {
+            Some(protocol) => protocol,
+            None => *self.protocol.insert(self.client.detect()?),
+        };
+        match protocol {
+            Protocol::Framed => self.client.request(cmd),
+            Protocol::V0_2 => match v0_2::Command::try_from(cmd) {
+                Ok(cmd) => self.client.request_v0_2(cmd).map(Response::from),
+                Err(code) => Ok(Response::Error(code)),
+            },
+        }
+    }
+}
+
+/// Report an error from the FEM through `error`, if it's not null
+///
+/// # Safety
+/// `error` must be null or valid for writes.
+unsafe fn rejected(code: transport::ErrorCode, error: *mut FemErrorCode) -> FemStatus {
+    if !error.is_null() {
+        error.write(code.into());
+    }
+    FemStatus::Rejected
+}
+
+/// Open the FEM on the serial port at `path`, waiting up to `timeout_ms` for every response
+///
+/// Returns null if the port couldn't be opened. Close the client with [`fem_close`].
+///
+/// # Safety
+/// `path` must be a valid, nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn fem_open(path: *const c_char, timeout_ms: u32) -> *mut FemClient {
+    if path.is_null() {
+        return std::ptr::null_mut();
+    }
+    let Ok(path) = CStr::from_ptr(path).to_str() else {
+        return std::ptr::null_mut();
+    };
+    let timeout = Duration::from_millis(timeout_ms.into());
+    match serialport::new(path, FEM_BAUD).timeout(timeout).open() {
+        Ok(port) => Box::into_raw(Box::new(FemClient {
+            client: BlockingClient::new(port).with_timeout(timeout),
+            protocol: None,
+        })),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Close a client opened with [`fem_open`]
+///
+/// # Safety
+/// `client` must be null or a client from [`fem_open`] that hasn't been closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn fem_close(client: *mut FemClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// Get the latest monitor data from the FEM
+///
+/// If the FEM reports an error, [`FemStatus::Rejected`] is returned and the reason is
+/// written to `error`.
+///
+/// # Safety
+/// `client` must be an open client, `payload` must be valid for writes and `error` must be
+/// null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fem_monitor(
+    client: *mut FemClient,
+    payload: *mut FemMonitorPayload,
+    error: *mut FemErrorCode,
+) -> FemStatus {
+    let (Some(client), false) = (client.as_mut(), payload.is_null()) else {
+        return FemStatus::NullArgument;
+    };
+    match client.request(Command::Monitor) {
+        Ok(Response::Monitor(p)) => {
+            payload.write(p.into());
+            FemStatus::Ok
+        }
+        Ok(Response::Error(code)) => rejected(code, error),
+        Ok(_) => FemStatus::Unsupported,
+        Err(status) => status,
+    }
+}
+
+/// Perform an action on the FEM
+///
+/// If the FEM reports an error, [`FemStatus::Rejected`] is returned and the reason is
+/// written to `error`. An action with a value out of range isn't sent at all, and fails with
+/// [`FemStatus::OutOfRange`].
+///
+/// # Safety
+/// `client` must be an open client and `error` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fem_control(
+    client: *mut FemClient,
+    action: FemAction,
+    error: *mut FemErrorCode,
+) -> FemStatus {
+    let Some(client) = client.as_mut() else {
+        return FemStatus::NullArgument;
+    };
+    let action = match action.try_into() {
+        Ok(action) => action,
+        Err(e) => return FemStatus::from(e),
+    };
+    match client.request(Command::Control(action)) {
+        Ok(Response::Ack) => FemStatus::Ok,
+        Ok(Response::Error(code)) => rejected(code, error),
+        Ok(_) => FemStatus::Unsupported,
+        Err(status) => status,
+    }
+}
@@ -0,0 +1,444 @@
+//! C interface to the FEM protocol and a blocking client
+//!
+//! The types here are plain C mirrors of the ones in [`transport`], covering the monitor and
+//! control commands. The header is generated into `include/fem.h` on every build.
+
+use std::slice;
+use transport::{
+    wire, Action, Command, ErrorCode, Frame, MonitorPayload, OutOfRange, Power, Response,
+};
+
+pub mod client;
+
+/// Outcome of every call
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FemStatus {
+    Ok,
+    /// A required pointer was null
+    NullArgument,
+    /// The output buffer can't hold the frame
+    BufferTooSmall,
+    /// The frame couldn't be decoded
+    Malformed,
+    /// The frame failed its checksum
+    Checksum,
+    /// The message has no C equivalent
+    Unsupported,
+    /// The serial port couldn't be opened, read or written
+    Io,
+    /// The FEM didn't respond in time
+    NoResponse,
+    /// The FEM refused or failed to carry out the command, see the [`FemErrorCode`]
+    Rejected,
+    /// A value is out of range for its quantity, like a NaN attenuation
+    OutOfRange,
+}
+
+impl From<wire::Error> for FemStatus {
+    fn from(e: wire::Error) -> Self {
+        match e {
+            wire::Error::BufferFull => FemStatus::BufferTooSmall,
+            wire::Error::Checksum => FemStatus::Checksum,
+            wire::Error::Cobs | wire::Error::Deserialize => FemStatus::Malformed,
+        }
+    }
+}
+
+impl From<OutOfRange> for FemStatus {
+    fn from(_: OutOfRange) -> Self {
+        FemStatus::OutOfRange
+    }
+}
+
+/// Voltage and current of a supply rail
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct FemPower {
+    /// Voltage in volts
+    pub voltage: f32,
+    /// Current in amps
+    pub current: f32,
+}
+
+/// Monitor data sent in response to a monitor command
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct FemMonitorPayload {
+    /// IF1 power in dBm
+    pub if1_power: f32,
+    /// IF2 power in dBm
+    pub if2_power: f32,
+    /// RP2040 internal temperature in C
+    pub ic_temp: f32,
+    /// Voltage and current of LNA1
+    pub lna1_power: FemPower,
+    /// Voltage and current of LNA2
+    pub lna2_power: FemPower,
+    /// Voltage and current of the analog rail
+    pub analog_power: FemPower,
+}
+
+/// Actions that can be performed
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FemAction {
+    /// Set the IF "Good" power threshold in dBm
+    SetIfLevel(f32),
+    /// Control the power state of the LNA1 regulator
+    Lna1Power(bool),
+    /// Control the power state of the LNA2 regulator
+    Lna2Power(bool),
+    /// Set attenuation
+    SetAtten(f32),
+}
+
+/// Reasons the FEM could not carry out a command
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FemErrorCode {
+    /// The incoming command could not be deserialized
+    Deserialize,
+    /// A value was outside of the supported (inclusive) range
+    OutOfRange { min: f32, max: f32 },
+    /// Communication with an SPI peripheral failed
+    SpiFault,
+    /// Communication with an I2C peripheral failed
+    I2cFault,
+    /// The command is not supported by this FEM
+    Unsupported,
+    /// The FEM can't handle the command right now
+    Busy,
+    /// The command was corrupted on the way to the FEM
+    Checksum,
+    /// The FEM failed without saying why, only reported by 0.2 firmware
+    Unknown,
+    /// The parameter can't be set
+    ReadOnly,
+    /// The value is the wrong kind for the parameter
+    WrongType,
+    /// A chunk arrived without an upload underway
+    NotStarted,
+}
+
+/// Payloads from MnC software to the FEM
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FemCommand {
+    Monitor,
+    Control(FemAction),
+}
+
+/// Payloads from FEM to MnC software
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FemResponse {
+    /// Previous command was ok, but didn't need a response
+    Ack,
+    /// Previous command failed
+    Error(FemErrorCode),
+    /// Response to monitor request
+    Monitor(FemMonitorPayload),
+}
+
+impl From<Power> for FemPower {
+    fn from(p: Power) -> Self {
+        Self {
+            voltage: p.voltage.into(),
+            current: p.current.into(),
+        }
+    }
+}
+
+impl TryFrom<FemPower> for Power {
+    type Error = OutOfRange;
+
+    fn try_from(p: FemPower) -> Result<Self, OutOfRange> {
+        Ok(Self {
+            voltage: p.voltage.try_into()?,
+            current: p.current.try_into()?,
+        })
+    }
+}
+
+impl From<MonitorPayload> for FemMonitorPayload {
+    fn from(p: MonitorPayload) -> Self {
+        Self {
+            if1_power: p.if1_power.into(),
+            if2_power: p.if2_power.into(),
+            ic_temp: p.ic_temp,
+            lna1_power: p.lna1_power.into(),
+            lna2_power: p.lna2_power.into(),
+            analog_power: p.analog_power.into(),
+        }
+    }
+}
+
+impl TryFrom<FemMonitorPayload> for MonitorPayload {
+    type Error = OutOfRange;
+
+    fn try_from(p: FemMonitorPayload) -> Result<Self, OutOfRange> {
+        Ok(Self {
+            if1_power: p.if1_power.try_into()?,
+            if2_power: p.if2_power.try_into()?,
+            ic_temp: p.ic_temp,
+            lna1_power: p.lna1_power.try_into()?,
+            lna2_power: p.lna2_power.try_into()?,
+            analog_power: p.analog_power.try_into()?,
+        })
+    }
+}
+
+impl From<Action> for FemAction {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::SetIfLevel(level) => FemAction::SetIfLevel(level.into()),
+            Action::Lna1Power(en) => FemAction::Lna1Power(en),
+            Action::Lna2Power(en) => FemAction::Lna2Power(en),
+            Action::SetAtten(atten) => FemAction::SetAtten(atten.into()),
+        }
+    }
+}
+
+impl TryFrom<FemAction> for Action {
+    type Error = OutOfRange;
+
+    fn try_from(action: FemAction) -> Result<Self, OutOfRange> {
+        Ok(match action {
+            FemAction::SetIfLevel(level) => Action::SetIfLevel(level.try_into()?),
+            FemAction::Lna1Power(en) => Action::Lna1Power(en),
+            FemAction::Lna2Power(en) => Action::Lna2Power(en),
+            FemAction::SetAtten(atten) => Action::SetAtten(atten.try_into()?),
+        })
+    }
+}
+
+impl From<ErrorCode> for FemErrorCode {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::Deserialize => FemErrorCode::Deserialize,
+            ErrorCode::OutOfRange { min, max } => FemErrorCode::OutOfRange { min, max },
+            ErrorCode::SpiFault => FemErrorCode::SpiFault,
+            ErrorCode::I2cFault => FemErrorCode::I2cFault,
+            ErrorCode::Unsupported => FemErrorCode::Unsupported,
+            ErrorCode::Busy => FemErrorCode::Busy,
+            ErrorCode::Checksum => FemErrorCode::Checksum,
+            ErrorCode::Unknown => FemErrorCode::Unknown,
+            ErrorCode::ReadOnly => FemErrorCode::ReadOnly,
+            ErrorCode::WrongType => FemErrorCode::WrongType,
+            ErrorCode::NotStarted => FemErrorCode::NotStarted,
+        }
+    }
+}
+
+impl From<FemErrorCode> for ErrorCode {
+    fn from(code: FemErrorCode) -> Self {
+        match code {
+            FemErrorCode::Deserialize => ErrorCode::Deserialize,
+            FemErrorCode::OutOfRange { min, max } => ErrorCode::OutOfRange { min, max },
+            FemErrorCode::SpiFault => ErrorCode::SpiFault,
+            FemErrorCode::I2cFault => ErrorCode::I2cFault,
+            FemErrorCode::Unsupported => ErrorCode::Unsupported,
+            FemErrorCode::Busy => ErrorCode::Busy,
+            FemErrorCode::Checksum => ErrorCode::Checksum,
+            FemErrorCode::Unknown => ErrorCode::Unknown,
+            FemErrorCode::ReadOnly => ErrorCode::ReadOnly,
+            FemErrorCode::WrongType => ErrorCode::WrongType,
+            FemErrorCode::NotStarted => ErrorCode::NotStarted,
+        }
+    }
+}
+
+impl TryFrom<FemCommand> for Command {
+    type Error = OutOfRange;
+
+    fn try_from(cmd: FemCommand) -> Result<Self, OutOfRange> {
+        Ok(match cmd {
+            FemCommand::Monitor => Command::Monitor,
+            FemCommand::Control(action) => Command::Control(action.try_into()?),
+        })
+    }
+}
+
+impl TryFrom<Command> for FemCommand {
+    type Error = FemStatus;
+
+    /// Fails with [`FemStatus::Unsupported`] for commands without a C equivalent
+    fn try_from(cmd: Command) -> Result<Self, FemStatus> {
+        match cmd {
+            Command::Monitor => Ok(FemCommand::Monitor),
+            Command::Control(action) => Ok(FemCommand::Control(action.into())),
+            _ => Err(FemStatus::Unsupported),
+        }
+    }
+}
+
+impl TryFrom<FemResponse> for Response {
+    type Error = OutOfRange;
+
+    fn try_from(resp: FemResponse) -> Result<Self, OutOfRange> {
+        Ok(match resp {
+            FemResponse::Ack => Response::Ack,
+            FemResponse::Error(code) => Response::Error(code.into()),
+            FemResponse::Monitor(payload) => Response::Monitor(payload.try_into()?),
+        })
+    }
+}
+
+impl TryFrom<Response> for FemResponse {
+    type Error = FemStatus;
+
+    /// Fails with [`FemStatus::Unsupported`] for responses without a C equivalent
+    fn try_from(resp: Response) -> Result<Self, FemStatus> {
+        match resp {
+            Response::Ack => Ok(FemResponse::Ack),
+            Response::Error(code) => Ok(FemResponse::Error(code.into())),
+            Response::Monitor(payload) => Ok(FemResponse::Monitor(payload.into())),
+            _ => Err(FemStatus::Unsupported),
+        }
+    }
+}
+
+/// Turn a result into a status, writing the value through `out` if there is one
+///
+/// # Safety
+/// `out` must be null or valid for writes.
+unsafe fn finish<T>(res: Result<T, FemStatus>, out: *mut T) -> FemStatus {
+    match res {
+        Ok(value) => {
+            if !out.is_null() {
+                out.write(value);
+            }
+            FemStatus::Ok
+        }
+        Err(status) => status,
+    }
+}
+
+/// Encode a framed message into a C buffer
+///
+/// # Safety
+/// `buf` must be valid for writes of `len` bytes, `written` must be null or valid for writes.
+unsafe fn encode<T: serde::Serialize>(
+    frame: &Frame<T>,
+    buf: *mut u8,
+    len: usize,
+    written: *mut usize,
+) -> FemStatus {
+    if buf.is_null() {
+        return FemStatus::NullArgument;
+    }
+    let buf = slice::from_raw_parts_mut(buf, len);
+    finish(
+        wire::encode(frame, buf)
+            .map(|bytes| bytes.len())
+            .map_err(FemStatus::from),
+        written,
+    )
+}
+
+/// Decode a framed message from a C buffer, with or without its zero delimiter
+///
+/// # Safety
+/// `frame` must be valid for reads of `len` bytes.
+unsafe fn decode<T: serde::de::DeserializeOwned>(
+    frame: *const u8,
+    len: usize,
+) -> Result<Frame<T>, FemStatus> {
+    if frame.is_null() {
+        return Err(FemStatus::NullArgument);
+    }
+    let mut bytes = slice::from_raw_parts(frame, len).to_vec();
+    if bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    Ok(wire::decode(&mut bytes)?)
+}
+
+/// Encode a command with sequence number `seq` as a complete frame, including the zero
+/// delimiter, writing its length to `written`
+///
+/// Fails with [`FemStatus::OutOfRange`] if a quantity in it is out of range, like a NaN.
+///
+/// # Safety
+/// `cmd` must point to a valid command, `buf` must be valid for writes of `len` bytes and
+/// `written` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fem_encode_command(
+    seq: u16,
+    cmd: *const FemCommand,
+    buf: *mut u8,
+    len: usize,
+    written: *mut usize,
+) -> FemStatus {
+    let Some(cmd) = cmd.as_ref() else {
+        return FemStatus::NullArgument;
+    };
+    match Command::try_from(*cmd) {
+        Ok(cmd) => encode(&Frame::new(seq, cmd), buf, len, written),
+        Err(e) => e.into(),
+    }
+}
+
+/// Decode a command frame, with or without its zero delimiter
+///
+/// # Safety
+/// `frame` must be valid for reads of `len` bytes, `seq` and `cmd` must be null or valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn fem_decode_command(
+    frame: *const u8,
+    len: usize,
+    seq: *mut u16,
+    cmd: *mut FemCommand,
+) -> FemStatus {
+    let res = decode::<Command>(frame, len).and_then(|frame| {
+        finish(Ok(frame.seq), seq);
+        FemCommand::try_from(frame.body)
+    });
+    finish(res, cmd)
+}
+
+/// Encode a response with sequence number `seq` as a complete frame, including the zero
+/// delimiter, writing its length to `written`
+///
+/// Fails with [`FemStatus::OutOfRange`] if a quantity in it is out of range, like a NaN.
+///
+/// # Safety
+/// `resp` must point to a valid response, `buf` must be valid for writes of `len` bytes and
+/// `written` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fem_encode_response(
+    seq: u16,
+    resp: *const FemResponse,
+    buf: *mut u8,
+    len: usize,
+    written: *mut usize,
+) -> FemStatus {
+    let Some(resp) = resp.as_ref() else {
+        return FemStatus::NullArgument;
+    };
+    match Response::try_from(*resp) {
+        Ok(resp) => encode(&Frame::new(seq, resp), buf, len, written),
+        Err(e) => e.into(),
+    }
+}
+
+/// Decode a response frame, with or without its zero delimiter
+///
+/// # Safety
+/// `frame` must be valid for reads of `len` bytes, `seq` and `resp` must be null or valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn fem_decode_response(
+    frame: *const u8,
+    len: usize,
+    seq: *mut u16,
+    resp: *mut FemResponse,
+) -> FemStatus {
+    let res = decode::<Response>(frame, len).and_then(|frame| {
+        finish(Ok(frame.seq), seq);
+        FemResponse::try_from(frame.body)
+    });
+    finish(res, resp)
+}
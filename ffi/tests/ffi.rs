@@ -0,0 +1,131 @@
+use fem::{
+    client::fem_open, fem_decode_command, fem_decode_response, fem_encode_command,
+    fem_encode_response, FemAction, FemCommand, FemErrorCode, FemMonitorPayload, FemPower,
+    FemResponse, FemStatus,
+};
+use std::ptr;
+use transport::{wire, Action, Attenuation, Command, ErrorCode, Frame, Identity, Response};
+
+#[test]
+fn commands_match_transport() {
+    let cmd = FemCommand::Control(FemAction::SetAtten(10.5));
+    let mut buf = [0u8; 64];
+    let mut written = 0;
+    let status = unsafe { fem_encode_command(42, &cmd, buf.as_mut_ptr(), buf.len(), &mut written) };
+    assert_eq!(status, FemStatus::Ok);
+    assert_eq!(buf[written - 1], 0);
+
+    let mut theirs = [0u8; 64];
+    let expected = wire::encode(
+        &Frame::new(
+            42,
+            Command::Control(Action::SetAtten(Attenuation::new(10.5).unwrap())),
+        ),
+        &mut theirs,
+    )
+    .unwrap();
+    assert_eq!(&buf[..written], expected);
+
+    let mut seq = 0;
+    let mut decoded = FemCommand::Monitor;
+    let status = unsafe { fem_decode_command(buf.as_ptr(), written, &mut seq, &mut decoded) };
+    assert_eq!(status, FemStatus::Ok);
+    assert_eq!((seq, decoded), (42, cmd));
+}
+
+#[test]
+fn responses_round_trip() {
+    let responses = [
+        FemResponse::Ack,
+        FemResponse::Error(FemErrorCode::OutOfRange {
+            min: 0.0,
+            max: 31.5,
+        }),
+        FemResponse::Monitor(FemMonitorPayload {
+            if1_power: -30.5,
+            lna1_power: FemPower {
+                voltage: 5.0,
+                current: 0.06,
+            },
+            ..Default::default()
+        }),
+    ];
+    for resp in responses {
+        let mut buf = [0u8; 64];
+        let mut written = 0;
+        let status =
+            unsafe { fem_encode_response(7, &resp, buf.as_mut_ptr(), buf.len(), &mut written) };
+        assert_eq!(status, FemStatus::Ok);
+
+        // Without the delimiter this time
+        let mut seq = 0;
+        let mut decoded = FemResponse::Ack;
+        let status =
+            unsafe { fem_decode_response(buf.as_ptr(), written - 1, &mut seq, &mut decoded) };
+        assert_eq!(status, FemStatus::Ok);
+        assert_eq!((seq, decoded), (7, resp));
+    }
+}
+
+#[test]
+fn decode_errors() {
+    let mut buf = [0u8; 128];
+    let frame = wire::encode(
+        &Frame::new(7, Response::Identity(Identity::default())),
+        &mut buf,
+    )
+    .unwrap()
+    .to_vec();
+    let mut resp = FemResponse::Ack;
+    let status =
+        unsafe { fem_decode_response(frame.as_ptr(), frame.len(), ptr::null_mut(), &mut resp) };
+    assert_eq!(status, FemStatus::Unsupported);
+
+    let mut corrupt = frame.clone();
+    corrupt[3] ^= 0x01;
+    let status =
+        unsafe { fem_decode_response(corrupt.as_ptr(), corrupt.len(), ptr::null_mut(), &mut resp) };
+    assert_eq!(status, FemStatus::Checksum);
+
+    let status = unsafe { fem_decode_response(ptr::null(), 0, ptr::null_mut(), &mut resp) };
+    assert_eq!(status, FemStatus::NullArgument);
+
+    let err = wire::encode(&Frame::new(7, Response::Error(ErrorCode::Busy)), &mut buf)
+        .unwrap()
+        .to_vec();
+    let status =
+        unsafe { fem_decode_response(err.as_ptr(), err.len(), ptr::null_mut(), &mut resp) };
+    assert_eq!(status, FemStatus::Ok);
+    assert_eq!(resp, FemResponse::Error(FemErrorCode::Busy));
+}
+
+#[test]
+fn encode_into_small_buffer() {
+    let mut buf = [0u8; 4];
+    let mut written = 0;
+    let cmd = FemCommand::Monitor;
+    let status =
+        unsafe { fem_encode_command(1234, &cmd, buf.as_mut_ptr(), buf.len(), &mut written) };
+    assert_eq!(status, FemStatus::BufferTooSmall);
+}
+
+#[test]
+fn encode_out_of_range() {
+    let mut buf = [0u8; 64];
+    let mut written = 0;
+    for cmd in [
+        FemCommand::Control(FemAction::SetAtten(f32::NAN)),
+        FemCommand::Control(FemAction::SetAtten(40.0)),
+        FemCommand::Control(FemAction::SetIfLevel(f32::INFINITY)),
+    ] {
+        let status =
+            unsafe { fem_encode_command(1, &cmd, buf.as_mut_ptr(), buf.len(), &mut written) };
+        assert_eq!(status, FemStatus::OutOfRange);
+    }
+}
+
+#[test]
+fn open_missing_port() {
+    let client = unsafe { fem_open(c"/dev/no-such-fem".as_ptr(), 100) };
+    assert!(client.is_null());
+}
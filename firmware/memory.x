@@ -1,7 +1,8 @@
 MEMORY
 {
   BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
-  FLASH : ORIGIN = 0x10000100, LENGTH = 8192K - 0x100
+  /* The last three sectors hold the calibration table and the settings, see src/flash.rs */
+  FLASH : ORIGIN = 0x10000100, LENGTH = 8192K - 0x100 - 12K
   RAM : ORIGIN = 0x20000000, LENGTH = 264K
 }
 
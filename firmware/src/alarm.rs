@@ -0,0 +1,102 @@
+//! Watching the monitor data for conditions the MnC software should hear about
+
+use heapless::Vec;
+use transport::{Dbm, EventKind, MonitorPayloadV2, Power, Rail, Validity};
+
+/// Largest number of events a single check can raise
+pub const MAX_EVENTS: usize = 6;
+
+/// Current limit of the LNA rails in amps
+const LNA_CURRENT_LIMIT: f32 = 0.1;
+/// Current limit of the analog rail in amps
+const ANALOG_CURRENT_LIMIT: f32 = 0.5;
+/// Temperature limit of the RP2040 in C
+const TEMP_LIMIT: f32 = 70.0;
+/// Fraction of a limit a reading has to fall back under to clear its alarm, so a reading
+/// sitting on the limit doesn't flood the link
+const HYSTERESIS: f32 = 0.9;
+
+/// Which alarms are currently raised
+#[derive(Debug, Default)]
+pub struct Alarms {
+    if_lost: [bool; 2],
+    over_current: [bool; 3],
+    over_temp: bool,
+}
+
+impl Alarms {
+    /// Compare the latest monitor data against the limits, returning the alarms that changed
+    ///
+    /// Readings that aren't valid leave their alarm as it was.
+    pub fn check(
+        &mut self,
+        payload: &MonitorPayloadV2,
+        if_good_threshold: Dbm,
+    ) -> Vec<EventKind, MAX_EVENTS> {
+        let mut events = Vec::new();
+        // There's at most one event per alarm, so these pushes can't fail
+        let valid = |field| payload.valid.contains(field);
+        let if_powers = [
+            (payload.if1_power, Validity::IF1_POWER),
+            (payload.if2_power, Validity::IF2_POWER),
+        ];
+        for (i, (lost, (power, field))) in self.if_lost.iter_mut().zip(if_powers).enumerate() {
+            let channel = i as u8 + 1;
+            if !valid(field) {
+                continue;
+            }
+            if !*lost && power < if_good_threshold {
+                *lost = true;
+                let _ = events.push(EventKind::IfLost { channel });
+            } else if *lost && power >= if_good_threshold {
+                *lost = false;
+                let _ = events.push(EventKind::IfRestored { channel });
+            }
+        }
+        let rails = [
+            (
+                Rail::Lna1,
+                &payload.lna1_power,
+                Validity::LNA1_CURRENT,
+                LNA_CURRENT_LIMIT,
+            ),
+            (
+                Rail::Lna2,
+                &payload.lna2_power,
+                Validity::LNA2_CURRENT,
+                LNA_CURRENT_LIMIT,
+            ),
+            (
+                Rail::Analog,
+                &payload.analog_power,
+                Validity::ANALOG_CURRENT,
+                ANALOG_CURRENT_LIMIT,
+            ),
+        ];
+        for (over, (rail, &Power { current: amps, .. }, field, limit)) in
+            self.over_current.iter_mut().zip(rails)
+        {
+            if !valid(field) {
+                continue;
+            }
+            if !*over && amps.amps() > limit {
+                *over = true;
+                let _ = events.push(EventKind::OverCurrent { rail, amps });
+            } else if *over && amps.amps() < limit * HYSTERESIS {
+                *over = false;
+                let _ = events.push(EventKind::CurrentRestored { rail, amps });
+            }
+        }
+        let celsius = payload.ic_temp;
+        if !valid(Validity::IC_TEMP) {
+            // Nothing to go on
+        } else if !self.over_temp && celsius > TEMP_LIMIT {
+            self.over_temp = true;
+            let _ = events.push(EventKind::OverTemp { celsius });
+        } else if self.over_temp && celsius < TEMP_LIMIT * HYSTERESIS {
+            self.over_temp = false;
+            let _ = events.push(EventKind::TempRestored { celsius });
+        }
+        events
+    }
+}
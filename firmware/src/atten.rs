@@ -3,8 +3,14 @@
 use cortex_m::asm::delay;
 use embedded_hal::blocking::spi::Write;
 use embedded_hal::digital::v2::OutputPin;
-#[allow(unused_imports)]
-use micromath::F32Ext;
+use transport::Attenuation;
+
+/// Minimum attenuation in dB
+pub const MIN_ATTEN: f32 = Attenuation::MIN.db();
+/// Maximum attenuation in dB
+pub const MAX_ATTEN: f32 = Attenuation::MAX.db();
+/// Attenuation of one LSB in dB
+pub const ATTEN_STEP: f32 = Attenuation::STEP;
 
 #[derive(Debug)]
 pub enum Error<LEE> {
@@ -12,11 +18,22 @@ pub enum Error<LEE> {
     LeError(LEE),
 }
 
+impl<LEE> From<Error<LEE>> for transport::ErrorCode {
+    fn from(e: Error<LEE>) -> Self {
+        match e {
+            // The latch enables are driven over GPIO, but they're part of the SPI transaction
+            Error::SpiError | Error::LeError(_) => transport::ErrorCode::SpiFault,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct DualHMC624A<LE1, LE2, SPI> {
     le1: LE1,
     le2: LE2,
     spi: SPI,
+    /// Last attenuation latched into both attenuators
+    atten: Attenuation,
 }
 
 impl<LE1, LE2, SPI, LEE> DualHMC624A<LE1, LE2, SPI>
@@ -26,17 +43,23 @@ where
     LE2: OutputPin<Error = LEE>,
 {
     pub fn new(spi: SPI, le1: LE1, le2: LE2) -> Self {
-        Self { le1, le2, spi }
+        Self {
+            le1,
+            le2,
+            spi,
+            atten: Attenuation::MIN,
+        }
+    }
+
+    /// The attenuation that was last latched
+    pub fn attenuation(&self) -> Attenuation {
+        self.atten
     }
 
-    // Set attenuation in dB (steps of 0.5) with ranges from 0 to 31.5
-    pub fn set_attenuation(&mut self, atten: f32) -> Result<(), Error<LEE>> {
-        // Find the closest 0.5 interval
-        let closest = (atten * 2.0).round() / 2.0;
-        // Count how many LSBs that is
-        let half_steps = (closest / 0.5) as u8;
-        // And find distance from the maximum of 63 steps
-        let setting = (63 - half_steps) & 0b111111;
+    // Set attenuation, which is always a whole number of steps within range of the HMC624A
+    pub fn set_attenuation(&mut self, atten: Attenuation) -> Result<(), Error<LEE>> {
+        // Find distance from the maximum of 63 steps
+        let setting = 63 - atten.steps();
         // Set both latch enable pins to low to start clocking in data
         self.le1.set_low().map_err(|e| Error::LeError(e))?;
         self.le2.set_low().map_err(|e| Error::LeError(e))?;
@@ -50,6 +73,7 @@ where
         delay(100);
         self.le1.set_low().map_err(|e| Error::LeError(e))?;
         self.le2.set_low().map_err(|e| Error::LeError(e))?;
+        self.atten = atten;
         // We're done!
         Ok(())
     }
@@ -1,6 +1,13 @@
-use embedded_hal::adc::{Channel, OneShot};
-use hal::{pac::UART1, Adc};
+use embedded_hal::{
+    adc::{Channel, OneShot},
+    digital::v2::OutputPin,
+};
+use hal::{
+    pac::{UART1, WATCHDOG},
+    Adc,
+};
 use rp2040_hal as hal;
+use transport::ResetReason;
 
 // Crystal freq
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
@@ -18,8 +25,14 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_IS25LP080;
 hal::bsp_pins!(
     Gpio20 { name: rf1_stat_led },
     Gpio21 { name: rf2_stat_led },
-    Gpio22 { name: rf1_lna_en },
-    Gpio23 { name: rf2_lna_en },
+    Gpio22 {
+        name: rf1_lna_en,
+        aliases: { FunctionSioOutput, PullDown: Lna1En }
+    },
+    Gpio23 {
+        name: rf2_lna_en,
+        aliases: { FunctionSioOutput, PullDown: Lna2En }
+    },
     Gpio26 { name: rf1_if_pow },
     Gpio27 { name: rf2_if_pow },
     Gpio8 {
@@ -46,33 +59,91 @@ hal::bsp_pins!(
         name: sdo,
         aliases: { FunctionSpi, PullNone: Sdo}
     },
-    Gpio0 { name: atten2_le },
-    Gpio10 { name: atten1_le },
+    Gpio0 {
+        name: atten2_le,
+        aliases: { FunctionSioOutput, PullDown: Atten2Le }
+    },
+    Gpio10 {
+        name: atten1_le,
+        aliases: { FunctionSioOutput, PullDown: Atten1Le }
+    },
+    Gpio11 {
+        name: rs485_de,
+        aliases: { FunctionSioOutput, PullDown: Rs485De }
+    },
 );
 
 // Some type aliases
 pub type UartPins = (Txd, Rxd);
 pub type Uart = hal::uart::UartPeripheral<hal::uart::Enabled, UART1, UartPins>;
 
-/// Get the 0..1 scaled floating point number representing the 12 bit ADC value
-pub fn read_adc<PIN>(adc: &mut Adc, pin: &mut PIN) -> Result<f32, ()>
+/// UART to the MnC software, through a half-duplex RS-485 transceiver
+pub struct Link {
+    pub uart: Uart,
+    /// Driver enable of the transceiver, only high while we're talking
+    de: Rs485De,
+}
+
+impl Link {
+    pub fn new(uart: Uart, mut de: Rs485De) -> Self {
+        de.set_low().unwrap();
+        Self { uart, de }
+    }
+
+    /// Take the bus, write out all of `bytes` and let go of the bus again
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.de.set_high().unwrap();
+        self.uart.write_full_blocking(bytes);
+        // The last byte has to make it out of the shift register before turning around
+        while self.uart.uart_is_busy() {}
+        self.de.set_low().unwrap();
+    }
+}
+
+/// Read the raw 12 bit counts of an ADC channel
+pub fn read_counts<PIN>(adc: &mut Adc, pin: &mut PIN) -> Result<u16, ()>
 where
     PIN: Channel<Adc, ID = u8>,
 {
-    // Read the counts
-    let counts: u16 = adc.read(pin).map_err(|_| ())?;
+    adc.read(pin).map_err(|_| ())
+}
+
+/// Get the 0..1 scaled floating point number representing the 12 bit ADC value
+pub fn scale_counts(counts: u16) -> f32 {
     // Scale raw 12-bit format to 0 .. 1
     let scaled = f32::from(counts) / f32::from(1u16 << 12);
-    Ok(scaled.clamp(0.0, 1.0))
+    scaled.clamp(0.0, 1.0)
 }
 
-/// Read the internal temperature sensor in degrees C
-pub fn read_temp<PIN>(adc: &mut Adc, pin: &mut PIN) -> Result<f32, ()>
-where
-    PIN: Channel<Adc, ID = u8>,
-{
+/// Read the 64-bit unique ID of the flash chip, used as the serial number of the board
+///
+/// This takes the flash out of XIP mode, so it must be called before the second core or
+/// any interrupts that execute from flash are running
+pub fn unique_id() -> u64 {
+    let mut id = [0u8; 8];
+    cortex_m::interrupt::free(|_| {
+        // Safety: interrupts are disabled and core1 is not running
+        unsafe { rp2040_flash::flash::flash_unique_id(&mut id, true) }
+    });
+    u64::from_be_bytes(id)
+}
+
+/// Why the chip last started up, which must be read before the watchdog is set up
+pub fn reset_reason(watchdog: &WATCHDOG) -> ResetReason {
+    let reason = watchdog.reason.read();
+    if reason.force().bit_is_set() {
+        ResetReason::Software
+    } else if reason.timer().bit_is_set() {
+        ResetReason::Watchdog
+    } else {
+        ResetReason::PowerOn
+    }
+}
+
+/// Convert counts read from the internal temperature sensor to degrees C
+pub fn temp_from_counts(counts: u16) -> f32 {
     // Get the raw voltage
-    let v = read_adc(adc, pin)? * ADC_REF_VOLT;
+    let v = scale_counts(counts) * ADC_REF_VOLT;
     // RP2040 Datasheet 4.9.5
-    Ok(27.0 - (v - 0.706) / 0.001721)
+    27.0 - (v - 0.706) / 0.001721
 }
@@ -0,0 +1,133 @@
+//! Control of the LNAs, attenuators and IF "good" threshold
+
+use crate::{
+    atten::DualHMC624A,
+    bsp::{Atten1Le, Atten2Le, Lna1En, Lna2En},
+    mnc::State,
+};
+use defmt::error;
+use embedded_hal::{
+    blocking::spi::Write,
+    digital::v2::{OutputPin, StatefulOutputPin},
+};
+use heapless::Vec;
+use transport::{Action, ActionResult, Attenuation, ControlState, Dbm, ErrorCode, MAX_BATCH};
+
+/// Everything on the FEM that can be changed with an [`Action`]
+pub struct Controls<SPI> {
+    pub lna_1: Lna1En,
+    pub lna_2: Lna2En,
+    pub atten: DualHMC624A<Atten1Le, Atten2Le, SPI>,
+}
+
+/// Snapshot of the settings, used to undo partially applied batches
+struct Settings {
+    lna_1: bool,
+    lna_2: bool,
+    atten: Attenuation,
+    if_good_threshold: Dbm,
+}
+
+impl<SPI> Controls<SPI>
+where
+    SPI: Write<u8>,
+{
+    pub fn new(lna_1: Lna1En, lna_2: Lna2En, atten: DualHMC624A<Atten1Le, Atten2Le, SPI>) -> Self {
+        Self {
+            lna_1,
+            lna_2,
+            atten,
+        }
+    }
+
+    /// Apply a single action
+    pub fn apply(&mut self, action: Action, state: &mut State) -> Result<(), ErrorCode> {
+        match action {
+            Action::SetIfLevel(level) => state.if_good_threshold = level,
+            Action::Lna1Power(en) => {
+                if en {
+                    self.lna_1.set_high().unwrap();
+                } else {
+                    self.lna_1.set_low().unwrap();
+                }
+            }
+            Action::Lna2Power(en) => {
+                if en {
+                    self.lna_2.set_high().unwrap();
+                } else {
+                    self.lna_2.set_low().unwrap();
+                }
+            }
+            Action::SetAtten(a) => self.atten.set_attenuation(a).map_err(|e| {
+                error!("Failed to set attenuation");
+                ErrorCode::from(e)
+            })?,
+        }
+        Ok(())
+    }
+
+    /// Apply every action in order, or none of them if any fail
+    ///
+    /// The values of the actions were checked when they were deserialized, so only the
+    /// hardware can let us down partway, in which case everything is undone.
+    pub fn apply_batch(
+        &mut self,
+        actions: &[Action],
+        state: &mut State,
+    ) -> Vec<ActionResult, MAX_BATCH> {
+        let mut results: Vec<_, MAX_BATCH> =
+            actions.iter().map(|_| ActionResult::Skipped).collect();
+        let before = self.settings(state);
+        for (i, action) in actions.iter().enumerate() {
+            if let Err(e) = self.apply(*action, state) {
+                error!("Batch failed at action {}, rolling back", i);
+                self.restore(&before, state);
+                results.iter_mut().for_each(|r| *r = ActionResult::Skipped);
+                results[i] = ActionResult::Rejected(e);
+                return results;
+            }
+            results[i] = ActionResult::Applied;
+        }
+        results
+    }
+
+    /// Read back the current control state
+    pub fn control_state(&self, state: &State, uptime_ms: u64) -> ControlState {
+        // Both attenuators are always latched together
+        let atten = self.atten.attenuation();
+        ControlState {
+            lna1_enabled: self.lna_1.is_set_high().unwrap(),
+            lna2_enabled: self.lna_2.is_set_high().unwrap(),
+            atten1: atten,
+            atten2: atten,
+            if_good_threshold: state.if_good_threshold,
+            // This board has no calibration tone
+            cal1: None,
+            cal2: None,
+            uptime_ms,
+        }
+    }
+
+    fn settings(&self, state: &State) -> Settings {
+        Settings {
+            lna_1: self.lna_1.is_set_high().unwrap(),
+            lna_2: self.lna_2.is_set_high().unwrap(),
+            atten: self.atten.attenuation(),
+            if_good_threshold: state.if_good_threshold,
+        }
+    }
+
+    fn restore(&mut self, settings: &Settings, state: &mut State) {
+        let actions = [
+            Action::Lna1Power(settings.lna_1),
+            Action::Lna2Power(settings.lna_2),
+            Action::SetAtten(settings.atten),
+            Action::SetIfLevel(settings.if_good_threshold),
+        ];
+        for action in actions {
+            if self.apply(action, state).is_err() {
+                error!("Failed to restore settings");
+            }
+        }
+    }
+}
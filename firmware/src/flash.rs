@@ -0,0 +1,49 @@
+//! Data kept in the flash alongside the program
+//!
+//! The sectors at the end of the flash are left out of the program in `memory.x`. Writing
+//! takes the flash out of XIP mode, so like [`crate::bsp::unique_id`] it must only happen
+//! while the second core isn't running.
+
+/// Size of the flash, as in `memory.x`
+pub const FLASH_SIZE: u32 = 8192 * 1024;
+
+/// Smallest unit the flash can be erased in
+pub const SECTOR_SIZE: u32 = 4096;
+
+/// Smallest unit the flash can be programmed in
+pub const PAGE_SIZE: usize = 256;
+
+/// Where the flash is mapped into memory
+const XIP_BASE: u32 = 0x1000_0000;
+
+/// Read `len` bytes at `offset` from the start of the flash
+pub fn read(offset: u32, len: usize) -> &'static [u8] {
+    // Safety: the flash is mapped by XIP, and only written with it out of XIP mode by the
+    // functions below, which can't run while the slice is in use on this core
+    unsafe { core::slice::from_raw_parts((XIP_BASE + offset) as *const u8, len) }
+}
+
+/// Erase the whole sector at `offset` from the start of the flash
+pub fn erase(offset: u32) {
+    cortex_m::interrupt::free(|_| {
+        // Safety: interrupts are disabled, core1 is not running and the sector is outside
+        // of the program
+        unsafe { rp2040_flash::flash::flash_range_erase(offset, SECTOR_SIZE, true) }
+    });
+}
+
+/// Program a page at `offset` from the start of the flash, which must have been erased
+pub fn program(offset: u32, page: &[u8; PAGE_SIZE]) {
+    cortex_m::interrupt::free(|_| {
+        // Safety: as for `erase`
+        unsafe { rp2040_flash::flash::flash_range_program(offset, page, true) }
+    });
+}
+
+/// Erase the sector at `offset` from the start of the flash and program its first page
+pub fn erase_and_program(offset: u32, page: &[u8; PAGE_SIZE]) {
+    cortex_m::interrupt::free(|_| {
+        // Safety: as for `erase`
+        unsafe { rp2040_flash::flash::flash_range_erase_and_program(offset, page, true) }
+    });
+}
@@ -1,19 +1,27 @@
 #![no_std]
 #![no_main]
 
+mod alarm;
 mod atten;
 mod bsp;
+mod control;
+mod flash;
 mod log_det;
 mod mnc;
+mod objects;
+mod params;
+mod settings;
 
 use bsp::*;
 use defmt::*;
 use defmt_rtt as _;
 use fugit::RateExtU32;
+use heapless::String;
 use panic_probe as _;
-use postcard::{
-    accumulator::{CobsAccumulator, FeedResult},
-    to_slice_cobs,
+use transport::{
+    codec::{self, FrameEncoder},
+    scpi::{Split, Splitter},
+    wire, Attenuation, MAX_COMMAND_FRAME_LEN, MAX_RESPONSE_FRAME_LEN,
 };
 
 // Embedded Hal traits
@@ -24,6 +32,7 @@ use hal::{
     clocks::{init_clocks_and_plls, Clock},
     entry, pac,
     sio::Sio,
+    timer::Timer,
     uart::{DataBits, StopBits, UartConfig, UartPeripheral},
     watchdog::Watchdog,
     I2C,
@@ -36,6 +45,8 @@ fn main() -> ! {
     // Setup peripherals, core clocks, etc.
     let mut pac = pac::Peripherals::take().unwrap();
     let _core = pac::CorePeripherals::take().unwrap();
+    let reset_reason = bsp::reset_reason(&pac.WATCHDOG);
+    info!("Reset reason {}", reset_reason);
     let mut watchdog = Watchdog::new(pac.WATCHDOG);
     let sio = Sio::new(pac.SIO);
 
@@ -59,6 +70,15 @@ fn main() -> ! {
         &mut pac.RESETS,
     );
 
+    // Start the timer, used to keep track of uptime
+    let timer = Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+
+    // Grab the serial number while nothing else is using the flash
+    let serial = bsp::unique_id();
+    info!("Serial number {:016x}", serial);
+    let settings = settings::Settings::load();
+    info!("Bus address {}", settings.addr);
+
     info!("Setting up ADC");
     // Enable the ADC peripheral and internal temperature sensor
     let mut adc = Adc::new(pac.ADC, &mut pac.RESETS);
@@ -77,10 +97,11 @@ fn main() -> ! {
             clocks.peripheral_clock.freq(),
         )
         .unwrap();
+    let mut link = Link::new(uart, pins.rs485_de.into_push_pull_output());
     info!("Setting up GPIO");
     // Set the LNA outputs to ON by default
-    let mut lna_1 = pins.rf1_lna_en.into_push_pull_output();
-    let mut lna_2 = pins.rf2_lna_en.into_push_pull_output();
+    let mut lna_1: Lna1En = pins.rf1_lna_en.into_push_pull_output();
+    let mut lna_2: Lna2En = pins.rf2_lna_en.into_push_pull_output();
     lna_1.set_high().unwrap();
     lna_2.set_high().unwrap();
 
@@ -93,9 +114,9 @@ fn main() -> ! {
     // Setup the SPI pins and initial state of the latch enable pins (high)
     // pins are implicitly used by the SPI driver
     info!("Setting up SPI");
-    let mut atten1_le = pins.atten1_le.into_push_pull_output();
+    let mut atten1_le: Atten1Le = pins.atten1_le.into_push_pull_output();
     atten1_le.set_low().unwrap();
-    let mut atten2_le = pins.atten2_le.into_push_pull_output();
+    let mut atten2_le: Atten2Le = pins.atten2_le.into_push_pull_output();
     atten2_le.set_low().unwrap();
 
     let spi = hal::Spi::<_, _, _, 6>::new(
@@ -112,7 +133,8 @@ fn main() -> ! {
     // And setup the two attenuators
     let mut atten = atten::DualHMC624A::new(spi, atten1_le, atten2_le);
     // and set the initial state to 0
-    atten.set_attenuation(0.0).unwrap();
+    atten.set_attenuation(Attenuation::MIN).unwrap();
+    let mut controls = control::Controls::new(lna_1, lna_2, atten);
 
     info!("Setting up I2C");
     // Setup I2C for the TMP100 and INA3221
@@ -137,90 +159,177 @@ fn main() -> ! {
     };
 
     // Setup state for if good and monitor
-    let mut state = mnc::State::default();
+    let mut state = mnc::State {
+        addr: settings.addr,
+        ..Default::default()
+    };
 
-    // Setup the state for the COBS input message accumulator
-    let mut in_buf = [0u8; 256];
-    let mut out_buf = [0u8; 256];
-    let mut cobs_buf: CobsAccumulator<256> = CobsAccumulator::new();
+    // Setup the state for the incoming and outgoing frames, sized for the largest of them
+    let mut in_buf = [0u8; MAX_COMMAND_FRAME_LEN];
+    let mut encoder: FrameEncoder<MAX_RESPONSE_FRAME_LEN> = FrameEncoder::new();
+    let mut splitter: Splitter<MAX_COMMAND_FRAME_LEN> = Splitter::new();
 
     info!("FEM Booted, starting main thread!");
+    // Let the MnC software know we're back
+    let event = transport::Event {
+        uptime_ms: timer.get_counter().ticks() / 1000,
+        kind: transport::EventKind::Rebooted {
+            reason: reset_reason,
+        },
+    };
+    let frame = transport::Frame::new(transport::NO_SEQ, transport::Response::Event(event))
+        .with_addr(state.addr);
+    link.write(encoder.encode_bounded(&frame));
 
     loop {
         // Update monitor payload in state
         mnc::update_monitor_payload(
             &mut state.last_monitor,
+            timer.get_counter().ticks() / 1000,
             &mut adc,
             &mut rf1_if_pow,
             &mut rf2_if_pow,
             &mut temp_sense,
             &mut ina3221,
         );
-        // If there are bytes for us, push them to the accumulator
-        if uart.uart_is_readable() {
-            while let Ok(n) = uart.read_raw(&mut in_buf) {
-                let buf = &in_buf[..n];
-                let mut window = buf;
-                'cobs: while !window.is_empty() {
-                    window = match cobs_buf.feed::<transport::Command>(window) {
-                        FeedResult::Consumed => break 'cobs,
-                        FeedResult::OverFull(new_wind) => new_wind,
-                        FeedResult::DeserError(new_wind) => new_wind,
-                        FeedResult::Success {
-                            data: cmd,
-                            remaining,
-                        } => {
-                            // Handle command
-                            info!("New incoming payload - {}", cmd);
-
-                            match cmd {
-                                transport::Command::Monitor => {
-                                    // Serialize the last monitor payload
-                                    let resp =
-                                        transport::Response::Monitor(state.last_monitor.clone());
-                                    info!("Sending monitor data - {}", resp);
-                                    let s = to_slice_cobs(&resp, &mut out_buf).unwrap();
-                                    uart.write_full_blocking(s);
-                                }
-                                transport::Command::Control(action) => {
-                                    // Do the control thing
-                                    match action {
-                                        transport::Action::SetIfLevel(level) => {
-                                            state.if_good_threshold = level
-                                        }
-                                        transport::Action::Lna1Power(en) => {
-                                            if en {
-                                                lna_1.set_high().unwrap();
-                                            } else {
-                                                lna_1.set_low().unwrap();
-                                            }
-                                        }
-                                        transport::Action::Lna2Power(en) => {
-                                            if en {
-                                                lna_2.set_high().unwrap();
-                                            } else {
-                                                lna_2.set_low().unwrap();
-                                            }
-                                        }
-                                        transport::Action::SetAtten(a) => {
-                                            match atten.set_attenuation(a) {
-                                                Ok(_) => (),
-                                                Err(_) => error!("Failed to set attenuation"),
+        // Tell the MnC software about any alarms that changed
+        let uptime_ms = timer.get_counter().ticks() / 1000;
+        for kind in state
+            .alarms
+            .check(&state.last_monitor, state.if_good_threshold)
+        {
+            warn!("Alarm changed - {}", kind);
+            let event = transport::Event { uptime_ms, kind };
+            let frame = transport::Frame::new(transport::NO_SEQ, transport::Response::Event(event))
+                .with_addr(state.addr);
+            link.write(encoder.encode_bounded(&frame));
+        }
+        // If there are bytes for us, split them into frames and lines of text
+        if link.uart.uart_is_readable() {
+            while let Ok(n) = link.uart.read_raw(&mut in_buf) {
+                let mut window = &in_buf[..n];
+                while !window.is_empty() {
+                    window = match splitter.feed(window) {
+                        Split::Consumed => break,
+                        Split::OverFull(remaining) => {
+                            error!("Dropped an incoming payload that was too large");
+                            // None of it can be trusted, so the error goes out unsequenced,
+                            // which a host only takes while it's waiting on this FEM
+                            let resp = transport::Response::Error(codec::Error::BufferFull.into());
+                            let frame = transport::Frame::new(transport::NO_SEQ, resp)
+                                .with_addr(state.addr);
+                            link.write(encoder.encode_bounded(&frame));
+                            remaining
+                        }
+                        Split::Frame { frame, remaining } => {
+                            match wire::payload(frame) {
+                                Ok(payload) => match wire::deserialize(payload) {
+                                    Ok(transport::Frame::<transport::Command> {
+                                        seq,
+                                        addr,
+                                        body: cmd,
+                                        ..
+                                    }) if addr == state.addr => {
+                                        // Handle command
+                                        info!("New incoming payload - {} (seq {})", cmd, seq);
+                                        let resp = mnc::handle(
+                                            seq,
+                                            cmd,
+                                            &mut state,
+                                            &mut controls,
+                                            serial,
+                                            &timer,
+                                        );
+                                        info!("Sending response - {} (seq {})", resp, seq);
+                                        // From the address the command came to, even if it
+                                        // just changed
+                                        let frame =
+                                            transport::Frame::new(seq, resp).with_addr(addr);
+                                        link.write(encoder.encode_bounded(&frame));
+                                    }
+                                    Ok(transport::Frame {
+                                        addr: transport::BROADCAST,
+                                        body: transport::Command::SetAddress(_),
+                                        ..
+                                    }) => {
+                                        warn!("Ignoring address change sent to every FEM");
+                                    }
+                                    Ok(transport::Frame {
+                                        seq,
+                                        addr: transport::BROADCAST,
+                                        body: cmd,
+                                        ..
+                                    }) => {
+                                        // Every FEM on the bus would answer at once, so nobody does
+                                        info!("New broadcast payload - {} (seq {})", cmd, seq);
+                                        mnc::handle(
+                                            seq,
+                                            cmd,
+                                            &mut state,
+                                            &mut controls,
+                                            serial,
+                                            &timer,
+                                        );
+                                    }
+                                    // For another FEM on the bus
+                                    Ok(_) => (),
+                                    Err(e) => {
+                                        error!("Failed to decode incoming payload - {}", e);
+                                        // The CRC checked out, so the header can be trusted even
+                                        // if the command can't, like one newer than the firmware
+                                        match wire::deserialize::<transport::Header>(payload) {
+                                            Ok(header) if header.addr == state.addr => {
+                                                let resp = transport::Response::Error(e.into());
+                                                let frame = transport::Frame::new(header.seq, resp)
+                                                    .with_addr(header.addr);
+                                                link.write(encoder.encode_bounded(&frame));
                                             }
+                                            _ => (),
                                         }
                                     }
-                                    // Then send an ack
-                                    let resp = transport::Response::Ack;
-                                    let s = to_slice_cobs(&resp, &mut out_buf).unwrap();
-                                    uart.write_full_blocking(s);
+                                },
+                                Err(e) => {
+                                    if e == codec::Error::Checksum {
+                                        state.crc_errors = state.crc_errors.wrapping_add(1);
+                                        error!(
+                                            "Bad checksum on incoming payload ({} so far)",
+                                            state.crc_errors
+                                        );
+                                    } else {
+                                        error!("Failed to decode incoming payload - {}", e);
+                                    }
+                                    // There's no telling which FEM a broken frame was for, so
+                                    // answering it could talk over another one on the bus
                                 }
                             }
                             remaining
                         }
+                        // Text has no address, so it's only for talking to a FEM on its own
+                        Split::Line { line, remaining } => {
+                            info!("New incoming line - {}", line);
+                            let mut reply: String<128> = String::new();
+                            mnc::handle_line(
+                                line,
+                                &mut state,
+                                &mut controls,
+                                serial,
+                                &timer,
+                                &mut reply,
+                            );
+                            link.write(reply.as_bytes());
+                            // The zero keeps the reply out of the next frame a host reads
+                            link.write(b"\r\n\0");
+                            remaining
+                        }
                     };
                 }
             }
         }
+        // Push telemetry to subscribers when it's due
+        if let Some(resp) = mnc::telemetry(&mut state, timer.get_counter().ticks()) {
+            let frame = transport::Frame::new(transport::NO_SEQ, resp).with_addr(state.addr);
+            link.write(encoder.encode_bounded(&frame));
+        }
         // Set the RF Good LEDs
         if state.last_monitor.if1_power >= state.if_good_threshold {
             rf1_status_led.set_high().unwrap();
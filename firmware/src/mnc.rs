@@ -1,53 +1,315 @@
-use crate::{bsp::read_temp, log_det::read_power};
-use defmt::error;
-use embedded_hal::{adc::Channel, blocking::i2c};
+use crate::{
+    alarm::Alarms,
+    atten::{ATTEN_STEP, MAX_ATTEN, MIN_ATTEN},
+    bsp::{read_counts, temp_from_counts},
+    control::Controls,
+    log_det::power_from_counts,
+    objects::{self, Upload},
+    params,
+    settings::Settings,
+};
+use core::fmt::Write;
+use defmt::{error, warn};
+use embedded_hal::{
+    adc::Channel,
+    blocking::{i2c, spi},
+};
 use ina3221::INA3221;
-use rp2040_hal::{adc::TempSense, Adc};
-use transport::MonitorPayload;
+use rp2040_hal::{adc::TempSense, Adc, Timer};
+use transport::{
+    is_valid_addr, scpi, Actions, Capabilities, Command, Dbm, ErrorCode, Features, Identity,
+    MonitorFields, MonitorPayload, MonitorPayloadV2, Response, Sensors, Validity, ADC_MAX_COUNTS,
+    DEFAULT_ADDR, MAX_ADDR, MIN_SUBSCRIBE_INTERVAL_MS, NO_SEQ, PROTOCOL_VERSION,
+};
+
+// Make sure the build information fits in the identity payload
+const _: () = assert!(env!("CARGO_PKG_VERSION").len() <= 16);
+const _: () = assert!(env!("GIT_HASH").len() <= 20);
 
 #[derive(Debug)]
 pub struct State {
-    pub if_good_threshold: f32,
-    pub last_monitor: MonitorPayload,
+    /// Address on the bus
+    pub addr: u8,
+    pub if_good_threshold: Dbm,
+    pub last_monitor: MonitorPayloadV2,
+    /// Sequence number, command and response of the last control command, to catch retries
+    pub last_control: Option<(u16, Command, Response)>,
+    /// Number of incoming frames dropped due to a bad checksum
+    pub crc_errors: u32,
+    /// Telemetry the MnC software subscribed to
+    pub subscription: Option<Subscription>,
+    /// Alarms raised by the monitor data
+    pub alarms: Alarms,
+    /// Bulk upload underway, or the last one to finish
+    pub upload: Option<Upload>,
+}
+
+/// Periodic telemetry requested with [`Command::Subscribe`]
+#[derive(Debug)]
+pub struct Subscription {
+    pub interval_us: u64,
+    pub fields: MonitorFields,
+    /// Timer ticks (in us) when the next frame is due
+    pub next_us: u64,
 }
 
 impl Default for State {
     fn default() -> Self {
         Self {
-            if_good_threshold: -10.0,
-            last_monitor: MonitorPayload::default(),
+            addr: DEFAULT_ADDR,
+            if_good_threshold: Dbm::new(-10.0).unwrap(),
+            last_monitor: MonitorPayloadV2::default(),
+            last_control: None,
+            crc_errors: 0,
+            subscription: None,
+            alarms: Alarms::default(),
+            upload: None,
+        }
+    }
+}
+
+/// Build the identity of this FEM from the build information and its serial number
+pub fn identity(serial: u64) -> Identity {
+    Identity {
+        protocol_version: PROTOCOL_VERSION,
+        firmware_version: env!("CARGO_PKG_VERSION").into(),
+        git_hash: env!("GIT_HASH").into(),
+        build_timestamp: env!("BUILD_TIMESTAMP").parse().unwrap_or_default(),
+        serial,
+    }
+}
+
+/// What this board can do
+///
+/// The cal tones and TMP100 aren't driven by this firmware, so they're left out even on boards
+/// that have them.
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        actions: Actions::ALL,
+        channels: 2,
+        atten_min: MIN_ATTEN,
+        atten_max: MAX_ATTEN,
+        atten_step: ATTEN_STEP,
+        sensors: Sensors::IF_POWER | Sensors::IC_TEMP | Sensors::RAIL_POWER,
+        features: Features::NONE,
+    }
+}
+
+/// Carry out a command from the MnC software and build the response
+pub fn handle<SPI>(
+    seq: u16,
+    cmd: Command,
+    state: &mut State,
+    controls: &mut Controls<SPI>,
+    serial: u64,
+    timer: &Timer,
+) -> Response
+where
+    SPI: spi::Write<u8>,
+{
+    // Control commands change things, so make sure retries are only applied once. Text
+    // commands have no sequence number to spot retries by.
+    let is_control = seq != NO_SEQ
+        && matches!(
+            cmd,
+            Command::Control(_) | Command::Batch(_) | Command::Set(..)
+        );
+    if is_control {
+        if let Some((last_seq, last_cmd, last_resp)) = &state.last_control {
+            if *last_seq == seq && *last_cmd == cmd {
+                // We already did this, so just repeat what we said
+                warn!("Retried control (seq {}), skipping", seq);
+                return last_resp.clone();
+            }
+        }
+    }
+    let resp = match &cmd {
+        // Send the last monitor payload
+        Command::Monitor => Response::Monitor(state.last_monitor.clone().into()),
+        Command::MonitorV2 => Response::MonitorV2(state.last_monitor.clone()),
+        Command::Identify => Response::Identity(identity(serial)),
+        Command::Control(action) => match controls.apply(*action, state) {
+            Ok(()) => Response::Ack,
+            Err(e) => Response::Error(e),
+        },
+        Command::Batch(actions) => Response::Batch(controls.apply_batch(actions, state)),
+        Command::GetState => {
+            let uptime_ms = timer.get_counter().ticks() / 1000;
+            Response::State(controls.control_state(state, uptime_ms))
+        }
+        Command::Subscribe {
+            interval_ms,
+            fields,
+        } => {
+            if *interval_ms < MIN_SUBSCRIBE_INTERVAL_MS {
+                Response::Error(ErrorCode::OutOfRange {
+                    min: MIN_SUBSCRIBE_INTERVAL_MS as f32,
+                    max: u32::MAX as f32,
+                })
+            } else {
+                // First frame goes out straight away
+                state.subscription = Some(Subscription {
+                    interval_us: *interval_ms as u64 * 1000,
+                    fields: *fields,
+                    next_us: timer.get_counter().ticks(),
+                });
+                Response::Ack
+            }
+        }
+        Command::Unsubscribe => {
+            state.subscription = None;
+            Response::Ack
+        }
+        Command::SetAddress(addr) => match set_address(*addr, state) {
+            Ok(()) => Response::Ack,
+            Err(e) => Response::Error(e),
+        },
+        Command::Capabilities => Response::Capabilities(capabilities()),
+        Command::Get(id) => match params::get(*id, state, controls) {
+            Ok(value) => Response::Value(value),
+            Err(e) => Response::Error(e),
+        },
+        Command::Set(id, value) => match params::set(*id, *value, state, controls) {
+            Ok(()) => Response::Ack,
+            Err(e) => Response::Error(e),
+        },
+        Command::ListParams => Response::Params(params::table()),
+        Command::UploadStart { object, len, crc } => {
+            objects::upload_start(&mut state.upload, *object, *len, *crc)
+                .unwrap_or_else(Response::Error)
         }
+        Command::UploadChunk(chunk) => {
+            objects::upload_chunk(&mut state.upload, chunk).unwrap_or_else(Response::Error)
+        }
+        Command::Download { object, offset } => {
+            objects::download(*object, *offset).unwrap_or_else(Response::Error)
+        }
+    };
+    if is_control {
+        state.last_control = Some((seq, cmd, resp.clone()));
+    }
+    resp
+}
+
+/// Give the FEM a new bus address and keep it for the next boot
+pub fn set_address(addr: u8, state: &mut State) -> Result<(), ErrorCode> {
+    if !is_valid_addr(addr) {
+        return Err(ErrorCode::OutOfRange {
+            min: 1.0,
+            max: MAX_ADDR as f32,
+        });
     }
+    Settings { addr }.store();
+    state.addr = addr;
+    Ok(())
 }
 
-fn bus_volt_log<I2C, E>(ina: &mut INA3221<I2C>, ch: ina3221::Channel) -> f32
+/// Build the next telemetry frame of the subscription, if one is due
+pub fn telemetry(state: &mut State, now_us: u64) -> Option<Response> {
+    let sub = state.subscription.as_mut()?;
+    if now_us < sub.next_us {
+        return None;
+    }
+    // Don't try to catch up on frames we were too busy to send
+    sub.next_us += sub.interval_us;
+    if sub.next_us <= now_us {
+        sub.next_us = now_us + sub.interval_us;
+    }
+    let payload = MonitorPayload::from(state.last_monitor.clone());
+    Some(Response::Telemetry(payload.select(sub.fields)))
+}
+
+/// Carry out a line of text from a terminal, writing the reply (without a line ending)
+pub fn handle_line<SPI, W>(
+    line: &str,
+    state: &mut State,
+    controls: &mut Controls<SPI>,
+    serial: u64,
+    timer: &Timer,
+    out: &mut W,
+) where
+    SPI: spi::Write<u8>,
+    W: Write,
+{
+    let res = match scpi::parse(line) {
+        Ok(req) => {
+            let resp = handle(NO_SEQ, req.command(), state, controls, serial, timer);
+            req.reply(&resp, out)
+        }
+        Err(e) => scpi::write_error(out, &e),
+    };
+    if res.is_err() {
+        warn!("Reply to the line didn't fit");
+    }
+}
+
+fn bus_volt_log<I2C, E>(ina: &mut INA3221<I2C>, ch: ina3221::Channel) -> Option<f32>
 where
     I2C: i2c::Read<Error = E> + i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
 {
     match ina.bus_voltage(ch) {
-        Ok(v) => v,
+        Ok(v) => Some(v),
         Err(_) => {
             error!("Error getting bus voltage");
-            Default::default()
+            None
         }
     }
 }
 
-fn shunt_volt_log<I2C, E>(ina: &mut INA3221<I2C>, ch: ina3221::Channel) -> f32
+fn shunt_volt_log<I2C, E>(ina: &mut INA3221<I2C>, ch: ina3221::Channel) -> Option<f32>
 where
     I2C: i2c::Read<Error = E> + i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
 {
     match ina.shunt_voltage(ch) {
-        Ok(v) => v,
+        Ok(v) => Some(v),
         Err(_) => {
             error!("Error getting shunt voltage");
-            Default::default()
+            None
+        }
+    }
+}
+
+/// Convert an ADC reading, which is only valid if it worked, the ADC wasn't saturated and the
+/// conversion made sense
+fn adc_reading<Q: TryFrom<f32> + Default>(
+    counts: Result<u16, ()>,
+    convert: fn(u16) -> f32,
+    valid: &mut Validity,
+    field: Validity,
+) -> (u16, Q) {
+    match counts {
+        Ok(counts) => match Q::try_from(convert(counts)) {
+            Ok(reading) => {
+                valid.set(field, counts < ADC_MAX_COUNTS);
+                (counts, reading)
+            }
+            Err(_) => {
+                valid.set(field, false);
+                (counts, Q::default())
+            }
+        },
+        Err(()) => {
+            error!("Error reading the ADC");
+            valid.set(field, false);
+            (0, Q::default())
         }
     }
 }
 
+/// Record an INA3221 reading, which failed if there's nothing (sensible) to record
+fn ina_reading<Q: TryFrom<f32> + Default>(
+    reading: Option<f32>,
+    valid: &mut Validity,
+    field: Validity,
+) -> Q {
+    let reading = reading.and_then(|x| Q::try_from(x).ok());
+    valid.set(field, reading.is_some());
+    reading.unwrap_or_default()
+}
+
 pub fn update_monitor_payload<I2C, E, PIN1, PIN2>(
-    payload: &mut MonitorPayload,
+    payload: &mut MonitorPayloadV2,
+    uptime_ms: u64,
     adc: &mut Adc,
     rf1_if_pow: &mut PIN1,
     rf2_if_pow: &mut PIN2,
@@ -58,16 +320,46 @@ pub fn update_monitor_payload<I2C, E, PIN1, PIN2>(
     PIN1: Channel<Adc, ID = u8>,
     PIN2: Channel<Adc, ID = u8>,
 {
+    payload.uptime_ms = uptime_ms;
+    payload.sample_seq = payload.sample_seq.wrapping_add(1);
+    let valid = &mut payload.valid;
     // Update IF Powers
-    payload.if1_power = read_power(adc, rf1_if_pow).unwrap();
-    payload.if2_power = read_power(adc, rf2_if_pow).unwrap();
+    (payload.if1_counts, payload.if1_power) = adc_reading(
+        read_counts(adc, rf1_if_pow),
+        power_from_counts,
+        valid,
+        Validity::IF1_POWER,
+    );
+    (payload.if2_counts, payload.if2_power) = adc_reading(
+        read_counts(adc, rf2_if_pow),
+        power_from_counts,
+        valid,
+        Validity::IF2_POWER,
+    );
     // Update internal temp
-    payload.ic_temp = read_temp(adc, internal_temp).unwrap();
+    (payload.ic_temp_counts, payload.ic_temp) = adc_reading(
+        read_counts(adc, internal_temp),
+        temp_from_counts,
+        valid,
+        Validity::IC_TEMP,
+    );
     // Voltages and currents - LNAs have Rsense of 1, Analog has Rsense of 0.2
-    payload.lna1_power.voltage = bus_volt_log(ina3221, ina3221::Channel::Ch1);
-    payload.lna1_power.current = shunt_volt_log(ina3221, ina3221::Channel::Ch1);
-    payload.lna2_power.voltage = bus_volt_log(ina3221, ina3221::Channel::Ch2);
-    payload.lna2_power.current = shunt_volt_log(ina3221, ina3221::Channel::Ch2);
-    payload.analog_power.voltage = bus_volt_log(ina3221, ina3221::Channel::Ch3);
-    payload.analog_power.current = shunt_volt_log(ina3221, ina3221::Channel::Ch3) / 0.2;
+    let ch1 = ina3221::Channel::Ch1;
+    let ch2 = ina3221::Channel::Ch2;
+    let ch3 = ina3221::Channel::Ch3;
+    payload.lna1_power.voltage =
+        ina_reading(bus_volt_log(ina3221, ch1), valid, Validity::LNA1_VOLTAGE);
+    payload.lna1_power.current =
+        ina_reading(shunt_volt_log(ina3221, ch1), valid, Validity::LNA1_CURRENT);
+    payload.lna2_power.voltage =
+        ina_reading(bus_volt_log(ina3221, ch2), valid, Validity::LNA2_VOLTAGE);
+    payload.lna2_power.current =
+        ina_reading(shunt_volt_log(ina3221, ch2), valid, Validity::LNA2_CURRENT);
+    payload.analog_power.voltage =
+        ina_reading(bus_volt_log(ina3221, ch3), valid, Validity::ANALOG_VOLTAGE);
+    payload.analog_power.current = ina_reading(
+        shunt_volt_log(ina3221, ch3).map(|v| v / 0.2),
+        valid,
+        Validity::ANALOG_CURRENT,
+    );
 }
@@ -0,0 +1,202 @@
+//! Objects too large for a frame, moved in chunks by the bulk transfers of
+//! [`transport::bulk`]
+//!
+//! The only one so far is the calibration table, which the FEM keeps for the MnC software
+//! without looking inside. It takes turns between the two sectors before the settings, with a
+//! header in the first page and the table itself after that. An upload goes into the sector
+//! the stored table isn't in, and its header is only written once the whole table is in and
+//! checked, so an interrupted or broken upload leaves the stored table as it was.
+
+use crate::flash::{self, FLASH_SIZE, PAGE_SIZE, SECTOR_SIZE};
+use defmt::{info, warn};
+use transport::{bulk::OBJECT_CRC, Chunk, ErrorCode, ObjectId, Response};
+
+/// Offsets of the calibration table sectors from the start of the flash
+const CAL_SLOTS: [u32; 2] = [FLASH_SIZE - 3 * SECTOR_SIZE, FLASH_SIZE - 2 * SECTOR_SIZE];
+
+/// Largest calibration table, with the first page of the sector taken by the header
+const CAL_CAPACITY: u32 = SECTOR_SIZE - PAGE_SIZE as u32;
+
+/// Marks the header of a calibration table that was stored in full
+const MAGIC: [u8; 4] = *b"FEMC";
+
+/// Upload underway (or just finished), kept to tell a resumed upload from a new one
+#[derive(Debug)]
+pub struct Upload {
+    object: ObjectId,
+    len: u32,
+    crc: u32,
+    received: u32,
+    /// Sector the table is going into
+    slot: u32,
+    /// Generation the table is stored as, one past that of the stored table
+    generation: u32,
+    /// Page being filled, programmed once it's full or the upload is done
+    page: [u8; PAGE_SIZE],
+}
+
+/// The generation and calibration table stored in full in the sector at `slot`, if any
+fn stored(slot: u32) -> Option<(u32, &'static [u8])> {
+    let header = flash::read(slot, 16);
+    let word = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+    let (len, crc, generation) = (word(4), word(8), word(12));
+    if header[..4] != MAGIC || len > CAL_CAPACITY {
+        return None;
+    }
+    let table = flash::read(slot + PAGE_SIZE as u32, len as usize);
+    if OBJECT_CRC.checksum(table) != crc {
+        warn!("Stored calibration table is corrupt");
+        return None;
+    }
+    // Tables from before there were two sectors have no generation, just erased flash
+    let generation = if generation == u32::MAX {
+        0
+    } else {
+        generation
+    };
+    Some((generation, table))
+}
+
+/// The sector and generation of the latest calibration table, and the table itself
+fn latest() -> Option<(u32, u32, &'static [u8])> {
+    CAL_SLOTS
+        .into_iter()
+        .filter_map(|slot| stored(slot).map(|(generation, table)| (slot, generation, table)))
+        .max_by_key(|&(_, generation, _)| generation)
+}
+
+/// The stored calibration table, empty if there isn't one
+fn cal_table() -> &'static [u8] {
+    latest().map_or(&[], |(_, _, table)| table)
+}
+
+/// Start an upload, or resume it if it's the one already underway
+pub fn upload_start(
+    upload: &mut Option<Upload>,
+    object: ObjectId,
+    len: u32,
+    crc: u32,
+) -> Result<Response, ErrorCode> {
+    if object != ObjectId::CAL_TABLE {
+        return Err(ErrorCode::Unsupported);
+    }
+    if len > CAL_CAPACITY {
+        return Err(ErrorCode::OutOfRange {
+            min: 0.0,
+            max: CAL_CAPACITY as f32,
+        });
+    }
+    match upload {
+        Some(up) if (up.object, up.len, up.crc) == (object, len, crc) => {
+            info!("Resuming upload at {} of {} bytes", up.received, len);
+        }
+        _ => {
+            info!("Starting upload of {} bytes", len);
+            let (slot, generation) = match latest() {
+                Some((slot, generation, _)) => {
+                    let other = CAL_SLOTS.into_iter().find(|&s| s != slot).unwrap();
+                    (other, generation + 1)
+                }
+                None => (CAL_SLOTS[0], 0),
+            };
+            flash::erase(slot);
+            let up = upload.insert(Upload {
+                object,
+                len,
+                crc,
+                received: 0,
+                slot,
+                generation,
+                page: [0xff; PAGE_SIZE],
+            });
+            if len == 0 {
+                finish(up)?;
+            }
+        }
+    }
+    Ok(progress(upload))
+}
+
+/// Take in the next chunk of the upload underway
+pub fn upload_chunk(upload: &mut Option<Upload>, chunk: &Chunk) -> Result<Response, ErrorCode> {
+    let Some(up) = upload.as_mut() else {
+        return Err(ErrorCode::NotStarted);
+    };
+    if !chunk.is_intact() {
+        return Err(ErrorCode::Checksum);
+    }
+    // Chunks we already have, or can't have yet, only get told where to pick up
+    if chunk.offset != up.received || chunk.data.is_empty() {
+        return Ok(progress(upload));
+    }
+    if chunk.end() > up.len {
+        return Err(ErrorCode::OutOfRange {
+            min: 0.0,
+            max: up.len as f32,
+        });
+    }
+    let mut data = &chunk.data[..];
+    while !data.is_empty() {
+        let at = up.received as usize % PAGE_SIZE;
+        let n = data.len().min(PAGE_SIZE - at);
+        up.page[at..at + n].copy_from_slice(&data[..n]);
+        up.received += n as u32;
+        data = &data[n..];
+        if at + n == PAGE_SIZE || up.received == up.len {
+            // Pages after the header, counting from the one that was just filled
+            let page = (up.received - 1) / PAGE_SIZE as u32 + 1;
+            flash::program(up.slot + page * PAGE_SIZE as u32, &up.page);
+            up.page = [0xff; PAGE_SIZE];
+        }
+    }
+    if up.received == up.len {
+        if let Err(e) = finish(up) {
+            *upload = None;
+            return Err(e);
+        }
+    }
+    Ok(progress(upload))
+}
+
+/// Check the uploaded table made it to the flash intact, then mark it as stored, taking over
+/// from the one before it
+fn finish(up: &Upload) -> Result<(), ErrorCode> {
+    let table = flash::read(up.slot + PAGE_SIZE as u32, up.len as usize);
+    if OBJECT_CRC.checksum(table) != up.crc {
+        warn!("Uploaded calibration table doesn't match its CRC");
+        return Err(ErrorCode::Checksum);
+    }
+    let mut header = [0xffu8; PAGE_SIZE];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4..8].copy_from_slice(&up.len.to_le_bytes());
+    header[8..12].copy_from_slice(&up.crc.to_le_bytes());
+    header[12..16].copy_from_slice(&up.generation.to_le_bytes());
+    flash::program(up.slot, &header);
+    info!("Stored calibration table of {} bytes", up.len);
+    Ok(())
+}
+
+fn progress(upload: &Option<Upload>) -> Response {
+    Response::UploadProgress {
+        received: upload.as_ref().map_or(0, |up| up.received),
+    }
+}
+
+/// Send the chunk of an object starting at `offset`
+pub fn download(object: ObjectId, offset: u32) -> Result<Response, ErrorCode> {
+    if object != ObjectId::CAL_TABLE {
+        return Err(ErrorCode::Unsupported);
+    }
+    let table = cal_table();
+    let len = table.len() as u32;
+    if offset > len {
+        return Err(ErrorCode::OutOfRange {
+            min: 0.0,
+            max: len as f32,
+        });
+    }
+    Ok(Response::Chunk {
+        len,
+        chunk: Chunk::new(offset, &table[offset as usize..]),
+    })
+}
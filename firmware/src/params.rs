@@ -0,0 +1,113 @@
+//! Table of the parameters that can be read and written with [`Command::Get`] and
+//! [`Command::Set`]
+//!
+//! [`Command::Get`]: transport::Command::Get
+//! [`Command::Set`]: transport::Command::Set
+
+use crate::{
+    atten::{MAX_ATTEN, MIN_ATTEN},
+    control::Controls,
+    mnc::{self, State},
+};
+use embedded_hal::{blocking::spi, digital::v2::StatefulOutputPin};
+use heapless::Vec;
+use transport::{Action, ErrorCode, ParamId, ParamInfo, Value, ValueKind, MAX_ADDR, MAX_PARAMS};
+
+/// Every parameter of this board
+pub fn table() -> Vec<ParamInfo, MAX_PARAMS> {
+    let param = |id, name: &str, unit: &str, kind, min, max| ParamInfo {
+        id,
+        name: name.into(),
+        unit: unit.into(),
+        kind,
+        min,
+        max,
+        read_only: false,
+        persisted: false,
+    };
+    let switch = |id, name| param(id, name, "", ValueKind::Bool, 0.0, 1.0);
+    [
+        switch(ParamId::LNA1_POWER, "lna1_power"),
+        switch(ParamId::LNA2_POWER, "lna2_power"),
+        param(
+            ParamId::ATTEN,
+            "atten",
+            "dB",
+            ValueKind::F32,
+            MIN_ATTEN,
+            MAX_ATTEN,
+        ),
+        param(
+            ParamId::IF_THRESHOLD,
+            "if_threshold",
+            "dBm",
+            ValueKind::F32,
+            // Any finite level, like `Dbm` takes
+            f32::MIN,
+            f32::MAX,
+        ),
+        ParamInfo {
+            persisted: true,
+            ..param(
+                ParamId::ADDRESS,
+                "address",
+                "",
+                ValueKind::U32,
+                1.0,
+                MAX_ADDR as f32,
+            )
+        },
+        ParamInfo {
+            read_only: true,
+            ..param(
+                ParamId::CRC_ERRORS,
+                "crc_errors",
+                "",
+                ValueKind::U32,
+                0.0,
+                u32::MAX as f32,
+            )
+        },
+    ]
+    .into_iter()
+    .collect()
+}
+
+/// Read the current value of a parameter
+pub fn get<SPI>(id: ParamId, state: &State, controls: &Controls<SPI>) -> Result<Value, ErrorCode> {
+    match id {
+        ParamId::LNA1_POWER => Ok(Value::Bool(controls.lna_1.is_set_high().unwrap())),
+        ParamId::LNA2_POWER => Ok(Value::Bool(controls.lna_2.is_set_high().unwrap())),
+        ParamId::ATTEN => Ok(Value::F32(controls.atten.attenuation().into())),
+        ParamId::IF_THRESHOLD => Ok(Value::F32(state.if_good_threshold.into())),
+        ParamId::ADDRESS => Ok(Value::U32(state.addr.into())),
+        ParamId::CRC_ERRORS => Ok(Value::U32(state.crc_errors)),
+        _ => Err(ErrorCode::Unsupported),
+    }
+}
+
+/// Change the value of a parameter, checking it against the table first
+pub fn set<SPI>(
+    id: ParamId,
+    value: Value,
+    state: &mut State,
+    controls: &mut Controls<SPI>,
+) -> Result<(), ErrorCode>
+where
+    SPI: spi::Write<u8>,
+{
+    let info = table()
+        .into_iter()
+        .find(|info| info.id == id)
+        .ok_or(ErrorCode::Unsupported)?;
+    info.check(&value)?;
+    // The controls that were actions first still go through them
+    if let Some(action) = Action::from_param(id, value)? {
+        return controls.apply(action, state);
+    }
+    match (id, value) {
+        // The range in the table keeps this in a u8
+        (ParamId::ADDRESS, Value::U32(addr)) => mnc::set_address(addr as u8, state),
+        _ => Err(ErrorCode::Unsupported),
+    }
+}
@@ -0,0 +1,46 @@
+//! Settings kept across power cycles, in the last sector of the flash
+
+use crate::flash::{self, FLASH_SIZE, PAGE_SIZE, SECTOR_SIZE};
+use defmt::Format;
+use transport::{is_valid_addr, DEFAULT_ADDR};
+
+/// Offset of the settings from the start of the flash
+const SETTINGS_OFFSET: u32 = FLASH_SIZE - SECTOR_SIZE;
+
+/// Marks the sector as holding settings, rather than being erased or holding something else
+const MAGIC: [u8; 4] = *b"FEMS";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct Settings {
+    /// Address of the FEM on the bus
+    pub addr: u8,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self { addr: DEFAULT_ADDR }
+    }
+}
+
+impl Settings {
+    /// Read the stored settings, falling back to the defaults if there aren't any
+    pub fn load() -> Self {
+        match flash::read(SETTINGS_OFFSET, 6) {
+            [m0, m1, m2, m3, addr, check]
+                if [*m0, *m1, *m2, *m3] == MAGIC && *check == !*addr && is_valid_addr(*addr) =>
+            {
+                Self { addr: *addr }
+            }
+            _ => Self::default(),
+        }
+    }
+
+    /// Write the settings to the flash
+    pub fn store(&self) {
+        let mut page = [0xffu8; PAGE_SIZE];
+        page[..4].copy_from_slice(&MAGIC);
+        page[4] = self.addr;
+        page[5] = !self.addr;
+        flash::erase_and_program(SETTINGS_OFFSET, &page);
+    }
+}
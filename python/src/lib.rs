@@ -0,0 +1,275 @@
+//! Python bindings for monitoring and controlling the FEM
+//!
+//! ```python
+//! from grex_fem import Fem
+//!
+//! fem = Fem("/dev/ttyACM0")
+//! fem.set_lna_power(1, True)
+//! fem.set_attenuation(10.5)
+//! print(fem.monitor().if1_power)
+//! ```
+
+use pyo3::{create_exception, exceptions::PyException, exceptions::PyValueError, prelude::*};
+use std::{
+    io::{Read, Write},
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+use transport::{
+    io::{BlockingClient, Protocol},
+    v0_2, Action, Attenuation, Command, Dbm, Response,
+};
+
+pub mod sim;
+
+/// Baud rate of the FEM's serial port
+const FEM_BAUD: u32 = 115_200;
+
+create_exception!(
+    grex_fem,
+    FemError,
+    PyException,
+    "Base of all errors talking to the FEM."
+);
+create_exception!(
+    grex_fem,
+    NoResponse,
+    FemError,
+    "The FEM didn't respond in time."
+);
+create_exception!(
+    grex_fem,
+    PortError,
+    FemError,
+    "The serial port couldn't be opened, read or written."
+);
+create_exception!(
+    grex_fem,
+    CommandError,
+    FemError,
+    "The FEM refused or failed to carry out a command."
+);
+
+fn client_error(e: transport::io::Error) -> PyErr {
+    match e {
+        transport::io::Error::Timeout => NoResponse::new_err(e.to_string()),
+        transport::io::Error::Io(_) | transport::io::Error::Closed => {
+            PortError::new_err(e.to_string())
+        }
+        transport::io::Error::Encode(_)
+        | transport::io::Error::Unexpected
+        | transport::io::Error::Unaddressable => FemError::new_err(e.to_string()),
+        transport::io::Error::Rejected(_) => CommandError::new_err(e.to_string()),
+    }
+}
+
+/// Anything the client can talk to the FEM over
+trait Link: Read + Write + Send {}
+
+impl<T> Link for T where T: Read + Write + Send {}
+
+/// Voltage and current of a supply rail
+#[pyclass(frozen, get_all, eq, module = "grex_fem")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Power {
+    /// Voltage in volts
+    voltage: f32,
+    /// Current in amps
+    current: f32,
+}
+
+#[pymethods]
+impl Power {
+    fn __repr__(&self) -> String {
+        format!("Power(voltage={}, current={})", self.voltage, self.current)
+    }
+}
+
+impl From<transport::Power> for Power {
+    fn from(p: transport::Power) -> Self {
+        Self {
+            voltage: p.voltage.into(),
+            current: p.current.into(),
+        }
+    }
+}
+
+/// Monitor data of the FEM
+#[pyclass(frozen, get_all, eq, module = "grex_fem")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Monitor {
+    /// IF1 power in dBm
+    if1_power: f32,
+    /// IF2 power in dBm
+    if2_power: f32,
+    /// RP2040 internal temperature in C
+    ic_temp: f32,
+    /// Voltage and current of LNA1
+    lna1_power: Power,
+    /// Voltage and current of LNA2
+    lna2_power: Power,
+    /// Voltage and current of the analog rail
+    analog_power: Power,
+}
+
+#[pymethods]
+impl Monitor {
+    fn __repr__(&self) -> String {
+        format!(
+            "Monitor(if1_power={}, if2_power={}, ic_temp={}, lna1_power={}, lna2_power={}, analog_power={})",
+            self.if1_power,
+            self.if2_power,
+            self.ic_temp,
+            self.lna1_power.__repr__(),
+            self.lna2_power.__repr__(),
+            self.analog_power.__repr__()
+        )
+    }
+}
+
+impl From<transport::MonitorPayload> for Monitor {
+    fn from(p: transport::MonitorPayload) -> Self {
+        Self {
+            if1_power: p.if1_power.into(),
+            if2_power: p.if2_power.into(),
+            ic_temp: p.ic_temp,
+            lna1_power: p.lna1_power.into(),
+            lna2_power: p.lna2_power.into(),
+            analog_power: p.analog_power.into(),
+        }
+    }
+}
+
+/// Client of a FEM, along with the protocol the FEM speaks once that's been found out
+struct Connection {
+    client: BlockingClient<Box<dyn Link>>,
+    protocol: Option<Protocol>,
+}
+
+impl Connection {
+    fn new(client: BlockingClient<Box<dyn Link>>) -> Self {
+        Self {
+            client,
+            protocol: None,
+        }
+    }
+
+    /// Send a command and wait for its response, in whichever protocol the FEM speaks
+    fn request(&mut self, cmd: Command) -> Result<Response, transport::io::Error> {
+        let protocol = match self.protocol {
+            Some(protocol) => protocol,
+            None => *self.protocol.insert(self.client.detect()?),
+        };
+        match protocol {
+            Protocol::Framed => self.client.request(cmd),
+            Protocol::V0_2 => match v0_2::Command::try_from(cmd) {
+                Ok(cmd) => self.client.request_v0_2(cmd).map(Response::from),
+                Err(code) => Ok(Response::Error(code)),
+            },
+        }
+    }
+}
+
+/// Connection to a FEM
+#[pyclass(frozen, module = "grex_fem")]
+pub struct Fem {
+    conn: Mutex<Connection>,
+}
+
+impl Fem {
+    /// Send a command and wait for its response
+    ///
+    /// The GIL is released while waiting on the FEM.
+    fn request(&self, py: Python<'_>, cmd: Command) -> PyResult<Response> {
+        // Take the lock without the GIL, so other threads waiting on it don't block ours
+        let resp = py.allow_threads(|| {
+            let mut conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+            conn.request(cmd)
+        });
+        match resp.map_err(client_error)? {
+            Response::Error(code) => Err(CommandError::new_err(code.to_string())),
+            resp => Ok(resp),
+        }
+    }
+
+    /// Apply a control action, expecting a plain acknowledgement
+    fn control(&self, py: Python<'_>, action: Action) -> PyResult<()> {
+        match self.request(py, Command::Control(action))? {
+            Response::Ack => Ok(()),
+            resp => Err(FemError::new_err(format!("unexpected response {resp:?}"))),
+        }
+    }
+}
+
+#[pymethods]
+impl Fem {
+    /// Open the FEM on a serial port, waiting up to `timeout` seconds for every response
+    #[new]
+    #[pyo3(signature = (port, timeout = 1.0))]
+    fn new(port: &str, timeout: f64) -> PyResult<Self> {
+        let timeout = Duration::try_from_secs_f64(timeout)
+            .map_err(|e| PyValueError::new_err(format!("invalid timeout: {e}")))?;
+        let port = serialport::new(port, FEM_BAUD)
+            .timeout(timeout)
+            .open()
+            .map_err(|e| PortError::new_err(e.to_string()))?;
+        let client = BlockingClient::new(Box::new(port) as Box<dyn Link>).with_timeout(timeout);
+        Ok(Self {
+            conn: Mutex::new(Connection::new(client)),
+        })
+    }
+
+    /// Connect to a simulated FEM instead of a real one
+    #[staticmethod]
+    fn simulated() -> Self {
+        let client = BlockingClient::new(Box::new(sim::SimulatedFem::new()) as Box<dyn Link>);
+        Self {
+            conn: Mutex::new(Connection::new(client)),
+        }
+    }
+
+    /// Get the latest monitor data
+    fn monitor(&self, py: Python<'_>) -> PyResult<Monitor> {
+        match self.request(py, Command::Monitor)? {
+            Response::Monitor(payload) => Ok(payload.into()),
+            resp => Err(FemError::new_err(format!("unexpected response {resp:?}"))),
+        }
+    }
+
+    /// Turn the power of LNA `channel` (1 or 2) on or off
+    fn set_lna_power(&self, py: Python<'_>, channel: u8, enabled: bool) -> PyResult<()> {
+        let action = match channel {
+            1 => Action::Lna1Power(enabled),
+            2 => Action::Lna2Power(enabled),
+            _ => return Err(PyValueError::new_err("LNA channel must be 1 or 2")),
+        };
+        self.control(py, action)
+    }
+
+    /// Set the attenuation of both channels in dB, rounded to the nearest step
+    fn set_attenuation(&self, py: Python<'_>, db: f32) -> PyResult<()> {
+        let atten = Attenuation::new(db)
+            .map_err(|e| PyValueError::new_err(format!("attenuation {e} dB")))?;
+        self.control(py, Action::SetAtten(atten))
+    }
+
+    /// Set the IF "power good" threshold in dBm
+    fn set_if_threshold(&self, py: Python<'_>, dbm: f32) -> PyResult<()> {
+        let dbm = Dbm::new(dbm)
+            .map_err(|_| PyValueError::new_err("IF threshold must be a finite number"))?;
+        self.control(py, Action::SetIfLevel(dbm))
+    }
+}
+
+#[pymodule]
+fn grex_fem(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add_class::<Fem>()?;
+    m.add_class::<Monitor>()?;
+    m.add_class::<Power>()?;
+    m.add("FemError", py.get_type::<FemError>())?;
+    m.add("NoResponse", py.get_type::<NoResponse>())?;
+    m.add("PortError", py.get_type::<PortError>())?;
+    m.add("CommandError", py.get_type::<CommandError>())?;
+    Ok(())
+}
@@ -0,0 +1,176 @@
+//! A FEM that lives in memory, for trying out scripts without the hardware
+
+use std::io::{self, Read, Write};
+use transport::{
+    codec::{FrameDecoder, FrameEncoder},
+    is_valid_addr, Action, Actions, Amps, Attenuation, Capabilities, Command, ControlState, Dbm,
+    ErrorCode, Features, Frame, Identity, MonitorPayload, Power, Response, Sensors, Volts,
+    BROADCAST, DEFAULT_ADDR, MAX_ADDR, MAX_COMMAND_FRAME_LEN, MAX_RESPONSE_FRAME_LEN,
+    PROTOCOL_VERSION,
+};
+
+/// Voltage and current of a supply rail, from constants that are known to be finite
+fn power(voltage: f32, current: f32) -> Power {
+    Power {
+        voltage: Volts::new(voltage).unwrap(),
+        current: Amps::new(current).unwrap(),
+    }
+}
+
+/// Power at an IF output with `atten` of attenuation
+fn if_power(atten: Attenuation) -> Dbm {
+    Dbm::new(-30.0 - atten.db()).unwrap()
+}
+
+/// Simulated FEM, answering the requests written to it like the firmware would
+///
+/// Reads time out straight away when there's nothing left to read, like a serial port with
+/// nothing on the other end.
+pub struct SimulatedFem {
+    decoder: FrameDecoder<Frame<Command>, MAX_COMMAND_FRAME_LEN>,
+    encoder: FrameEncoder<MAX_RESPONSE_FRAME_LEN>,
+    outbox: Vec<u8>,
+    addr: u8,
+    state: ControlState,
+}
+
+impl Default for SimulatedFem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedFem {
+    pub fn new() -> Self {
+        Self {
+            decoder: FrameDecoder::new(),
+            encoder: FrameEncoder::new(),
+            outbox: vec![],
+            addr: DEFAULT_ADDR,
+            state: ControlState {
+                if_good_threshold: Dbm::new(-20.0).unwrap(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn monitor(&self) -> MonitorPayload {
+        let lna = |enabled| {
+            if enabled {
+                power(5.0, 0.06)
+            } else {
+                Power::default()
+            }
+        };
+        MonitorPayload {
+            if1_power: if_power(self.state.atten1),
+            if2_power: if_power(self.state.atten2),
+            ic_temp: 30.0,
+            lna1_power: lna(self.state.lna1_enabled),
+            lna2_power: lna(self.state.lna2_enabled),
+            analog_power: power(5.0, 0.3),
+        }
+    }
+
+    fn control(&mut self, action: Action) -> Response {
+        match action {
+            Action::SetIfLevel(level) => self.state.if_good_threshold = level,
+            Action::Lna1Power(en) => self.state.lna1_enabled = en,
+            Action::Lna2Power(en) => self.state.lna2_enabled = en,
+            // The range of the attenuators is the whole range of an attenuation
+            Action::SetAtten(atten) => {
+                self.state.atten1 = atten;
+                self.state.atten2 = atten;
+            }
+        }
+        Response::Ack
+    }
+
+    fn handle(&mut self, cmd: Command) -> Response {
+        match cmd {
+            Command::Monitor => Response::Monitor(self.monitor()),
+            Command::Control(action) => self.control(action),
+            Command::Identify => Response::Identity(Identity {
+                protocol_version: PROTOCOL_VERSION,
+                firmware_version: "simulated".into(),
+                ..Default::default()
+            }),
+            Command::GetState => Response::State(self.state.clone()),
+            Command::Capabilities => Response::Capabilities(Capabilities {
+                actions: Actions::ALL,
+                channels: 2,
+                atten_min: Attenuation::MIN.db(),
+                atten_max: Attenuation::MAX.db(),
+                atten_step: Attenuation::STEP,
+                sensors: Sensors::IF_POWER | Sensors::IC_TEMP | Sensors::RAIL_POWER,
+                features: Features::NONE,
+            }),
+            Command::SetAddress(addr) if is_valid_addr(addr) => {
+                self.addr = addr;
+                Response::Ack
+            }
+            Command::SetAddress(_) => Response::Error(ErrorCode::OutOfRange {
+                min: 1.0,
+                max: MAX_ADDR.into(),
+            }),
+            _ => Response::Error(ErrorCode::Unsupported),
+        }
+    }
+
+    fn reply(&mut self, frame: &Frame<Response>) {
+        let bytes = self.encoder.encode_bounded(frame);
+        self.outbox.extend_from_slice(bytes);
+    }
+}
+
+impl Write for SimulatedFem {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let frames: Vec<_> = self.decoder.feed(buf).collect();
+        for frame in frames {
+            let addr = self.addr;
+            match frame {
+                // Every FEM would end up with the same address
+                Ok(Frame {
+                    addr: BROADCAST,
+                    body: Command::SetAddress(_),
+                    ..
+                }) => (),
+                Ok(Frame {
+                    addr: BROADCAST,
+                    body,
+                    ..
+                }) => {
+                    self.handle(body);
+                }
+                Ok(Frame {
+                    seq,
+                    addr: to,
+                    body,
+                    ..
+                }) if to == addr => {
+                    let resp = self.handle(body);
+                    self.reply(&Frame::new(seq, resp).with_addr(addr));
+                }
+                // There's no telling who a broken frame was for
+                Ok(_) | Err(_) => (),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for SimulatedFem {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.outbox.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let n = buf.len().min(self.outbox.len());
+        buf[..n].copy_from_slice(&self.outbox[..n]);
+        self.outbox.drain(..n);
+        Ok(n)
+    }
+}
@@ -0,0 +1,55 @@
+import pytest
+
+from grex_fem import Fem, FemError, Monitor, PortError, Power
+
+
+@pytest.fixture
+def fem():
+    return Fem.simulated()
+
+
+def test_monitor(fem):
+    mon = fem.monitor()
+    assert isinstance(mon, Monitor)
+    assert isinstance(mon.lna1_power, Power)
+    assert mon.analog_power.voltage > 0
+    assert mon == fem.monitor()
+    assert repr(mon).startswith("Monitor(if1_power=")
+
+
+def test_lna_power(fem):
+    assert fem.monitor().lna1_power.current == 0
+    fem.set_lna_power(1, True)
+    mon = fem.monitor()
+    assert mon.lna1_power.current > 0
+    assert mon.lna2_power.current == 0
+    fem.set_lna_power(1, False)
+    assert fem.monitor().lna1_power.current == 0
+
+
+def test_bad_lna_channel(fem):
+    with pytest.raises(ValueError):
+        fem.set_lna_power(3, True)
+
+
+def test_attenuation(fem):
+    before = fem.monitor().if1_power
+    fem.set_attenuation(10.0)
+    assert fem.monitor().if1_power == pytest.approx(before - 10.0)
+
+
+def test_out_of_range_attenuation(fem):
+    with pytest.raises(ValueError, match="between 0 and 31.5"):
+        fem.set_attenuation(40.0)
+    with pytest.raises(ValueError):
+        fem.set_attenuation(float("nan"))
+
+
+def test_if_threshold(fem):
+    fem.set_if_threshold(-15.0)
+
+
+def test_missing_port():
+    with pytest.raises(PortError):
+        Fem("/dev/no-such-fem")
+    assert issubclass(PortError, FemError)
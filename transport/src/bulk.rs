@@ -0,0 +1,52 @@
+//! Moving objects too large for a frame, like calibration tables, in [`Chunk`]s
+//!
+//! An upload starts with [`Command::UploadStart`], giving the length and CRC of the whole
+//! object, followed by a [`Command::UploadChunk`] for every [`CHUNK_SIZE`] bytes of it. The
+//! FEM acknowledges every command with the number of bytes it has so far, which is where the
+//! next chunk should start. Starting the same upload again picks up where it left off, so an
+//! interrupted upload can be resumed.
+//!
+//! A download is a [`Command::Download`] for every chunk, each response giving the length of
+//! the whole object. The FEM doesn't keep track of downloads, so they can be resumed from any
+//! offset.
+//!
+//! Every chunk carries its own CRC on top of the one of the frame, and the FEM checks the
+//! whole object against its CRC before keeping it.
+//!
+//! [`Command::UploadStart`]: crate::Command::UploadStart
+//! [`Command::UploadChunk`]: crate::Command::UploadChunk
+//! [`Command::Download`]: crate::Command::Download
+
+use crate::{wire, Chunk, CHUNK_SIZE};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use heapless::Vec;
+
+/// CRC of a whole object
+pub const OBJECT_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+impl Chunk {
+    /// Chunk of `data` starting at `offset`, of which only the first [`CHUNK_SIZE`] bytes
+    /// are taken
+    pub fn new(offset: u32, data: &[u8]) -> Self {
+        let data = Vec::from_slice(&data[..data.len().min(CHUNK_SIZE)]).unwrap();
+        let crc = Self::checksum(offset, &data);
+        Self { offset, data, crc }
+    }
+
+    /// Whether the chunk matches its CRC
+    pub fn is_intact(&self) -> bool {
+        Self::checksum(self.offset, &self.data) == self.crc
+    }
+
+    /// Offset past the end of the chunk
+    pub fn end(&self) -> u32 {
+        self.offset + self.data.len() as u32
+    }
+
+    fn checksum(offset: u32, data: &[u8]) -> u16 {
+        let mut digest = wire::CRC.digest();
+        digest.update(&offset.to_le_bytes());
+        digest.update(data);
+        digest.finalize()
+    }
+}
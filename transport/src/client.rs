@@ -0,0 +1,206 @@
+//! Request/response state machine for the MnC side of the link, independent of any IO
+//!
+//! The [`Client`] turns commands into bytes to write and bytes read back into responses,
+//! leaving the actual reading, writing and timing out to the caller. See [`crate::io`] for a
+//! blocking adapter over [`std::io`].
+
+use crate::{
+    codec::{Error, FrameDecoder, FrameEncoder},
+    is_reserved_seq, Command, Event, Frame, Response, TelemetryPayload, BROADCAST, DEFAULT_ADDR,
+    MAX_FRAME_LEN, NO_SEQ,
+};
+use heapless::Deque;
+
+/// Size of the frame buffers of the client, enough for any frame either way
+pub const BUF_SIZE: usize = MAX_FRAME_LEN;
+
+/// Number of telemetry frames the client holds on to, older ones are dropped
+pub const TELEMETRY_DEPTH: usize = 4;
+
+/// Number of events the client holds on to, older ones are dropped
+pub const EVENT_DEPTH: usize = 8;
+
+/// Counts of frames the client had to throw away
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Stats {
+    /// Frames with a bad checksum
+    pub checksum_errors: u32,
+    /// Frames that were otherwise malformed or too large
+    pub decode_errors: u32,
+    /// Responses to a request other than the pending one
+    pub stale_responses: u32,
+}
+
+/// Sans-IO request/response client
+pub struct Client {
+    encoder: FrameEncoder<BUF_SIZE>,
+    decoder: FrameDecoder<Frame<Response>, BUF_SIZE>,
+    next_seq: u16,
+    addr: u8,
+    pending: Option<Frame<Command>>,
+    response: Option<Response>,
+    telemetry: Deque<TelemetryPayload, TELEMETRY_DEPTH>,
+    events: Deque<Event, EVENT_DEPTH>,
+    stats: Stats,
+}
+
+impl Client {
+    /// Create a client, numbering requests starting from `seq`
+    ///
+    /// The FEM drops control commands that repeat the sequence number of the last one, so
+    /// clients that don't live for long should pick a different starting point every time.
+    pub fn new(seq: u16) -> Self {
+        Self {
+            encoder: FrameEncoder::new(),
+            decoder: FrameDecoder::new(),
+            next_seq: seq,
+            addr: DEFAULT_ADDR,
+            pending: None,
+            response: None,
+            telemetry: Deque::new(),
+            events: Deque::new(),
+            stats: Stats::default(),
+        }
+    }
+
+    /// Talk to the FEM at `addr` on the bus, rather than the one at [`DEFAULT_ADDR`]
+    ///
+    /// Frames from any other FEM are ignored.
+    pub fn set_addr(&mut self, addr: u8) {
+        self.addr = addr;
+    }
+
+    /// Address of the FEM the client talks to
+    pub fn addr(&self) -> u8 {
+        self.addr
+    }
+
+    /// Start a new request, returning the bytes to write
+    ///
+    /// Any request still waiting for a response is abandoned.
+    pub fn request(&mut self, cmd: Command) -> Result<&[u8], Error> {
+        let frame = Frame::new(self.seq(), cmd).with_addr(self.addr);
+        self.response = None;
+        self.encoder.encode(&frame)?;
+        self.pending = Some(frame);
+        self.retry()
+    }
+
+    /// Send a command to every FEM on the bus, returning the bytes to write
+    ///
+    /// None of them respond, so nothing is left waiting for a response.
+    pub fn broadcast(&mut self, cmd: Command) -> Result<&[u8], Error> {
+        let frame = Frame::new(self.seq(), cmd).with_addr(BROADCAST);
+        self.encoder.encode(&frame)
+    }
+
+    fn seq(&mut self) -> u16 {
+        while is_reserved_seq(self.next_seq) {
+            self.next_seq = self.next_seq.wrapping_add(1);
+        }
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        seq
+    }
+
+    /// The bytes of the pending request again, with the same sequence number
+    ///
+    /// The FEM will not apply a retried control command twice.
+    pub fn retry(&mut self) -> Result<&[u8], Error> {
+        match &self.pending {
+            Some(frame) => self.encoder.encode(frame),
+            None => Ok(&[]),
+        }
+    }
+
+    /// Sequence number of the request waiting for a response
+    pub fn pending(&self) -> Option<u16> {
+        self.pending.as_ref().map(|f| f.seq)
+    }
+
+    /// Handle bytes read off the link
+    pub fn receive(&mut self, bytes: &[u8]) {
+        for frame in self.decoder.feed(bytes) {
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(Error::Checksum) => {
+                    self.stats.checksum_errors += 1;
+                    continue;
+                }
+                Err(_) => {
+                    self.stats.decode_errors += 1;
+                    continue;
+                }
+            };
+            // Other FEMs on the bus are none of our business
+            if frame.addr != self.addr {
+                continue;
+            }
+            // Telemetry and events aren't an answer to anything
+            match frame {
+                Frame {
+                    seq: NO_SEQ,
+                    body: Response::Telemetry(payload),
+                    ..
+                } => {
+                    push_dropping_oldest(&mut self.telemetry, payload);
+                    continue;
+                }
+                Frame {
+                    seq: NO_SEQ,
+                    body: Response::Event(event),
+                    ..
+                } => {
+                    push_dropping_oldest(&mut self.events, event);
+                    continue;
+                }
+                _ => (),
+            }
+            let Some(seq) = self.pending.as_ref().map(|f| f.seq) else {
+                self.stats.stale_responses += 1;
+                continue;
+            };
+            match frame {
+                Frame { seq: s, body, .. } if s == seq => self.response = Some(body),
+                // The FEM couldn't read our sequence number
+                Frame {
+                    seq: NO_SEQ,
+                    body: body @ Response::Error(_),
+                    ..
+                } => self.response = Some(body),
+                _ => self.stats.stale_responses += 1,
+            }
+            if self.response.is_some() {
+                self.pending = None;
+            }
+        }
+    }
+
+    /// Take the response to the last request, if it has arrived
+    pub fn poll_response(&mut self) -> Option<Response> {
+        self.response.take()
+    }
+
+    /// Take the oldest telemetry frame sent since [`Command::Subscribe`], if any
+    pub fn poll_telemetry(&mut self) -> Option<TelemetryPayload> {
+        self.telemetry.pop_front()
+    }
+
+    /// Take the oldest event reported by the FEM, if any
+    pub fn poll_event(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+
+    /// Counts of frames thrown away so far
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+}
+
+fn push_dropping_oldest<T, const N: usize>(queue: &mut Deque<T, N>, item: T) {
+    if queue.is_full() {
+        queue.pop_front();
+    }
+    // There's room, we just made it
+    let _ = queue.push_back(item);
+}
@@ -0,0 +1,120 @@
+//! Typed encoding and decoding of frames, independent of any IO
+//!
+//! Bytes read off the link are fed into a [`FrameDecoder`], which yields every complete
+//! message along with any errors in the frames it threw away.
+
+use crate::wire::{self, Accumulator, FeedResult};
+use core::marker::PhantomData;
+use postcard::experimental::max_size::MaxSize;
+use serde::{de::DeserializeOwned, Serialize};
+
+pub use crate::wire::Error;
+
+/// Decodes a stream of bytes into messages of type `T`, holding frames of up to `N` bytes
+pub struct FrameDecoder<T, const N: usize> {
+    acc: Accumulator<N>,
+    _msg: PhantomData<T>,
+}
+
+impl<T, const N: usize> Default for FrameDecoder<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> FrameDecoder<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            acc: Accumulator::new(),
+            _msg: PhantomData,
+        }
+    }
+
+    /// Feed bytes into the decoder, yielding every frame they complete
+    ///
+    /// Bytes of an incomplete frame are kept until the next call.
+    pub fn feed<'d, 'i>(&'d mut self, input: &'i [u8]) -> Decoded<'d, 'i, T, N> {
+        Decoded {
+            decoder: self,
+            window: input,
+        }
+    }
+}
+
+/// Iterator over the frames completed by a call to [`FrameDecoder::feed`]
+pub struct Decoded<'d, 'i, T, const N: usize> {
+    decoder: &'d mut FrameDecoder<T, N>,
+    window: &'i [u8],
+}
+
+impl<T, const N: usize> Iterator for Decoded<'_, '_, T, N>
+where
+    T: DeserializeOwned,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.window.is_empty() {
+            return None;
+        }
+        match self.decoder.acc.feed(self.window) {
+            FeedResult::Consumed => {
+                self.window = &[];
+                None
+            }
+            FeedResult::OverFull(remaining) => {
+                self.window = remaining;
+                Some(Err(Error::BufferFull))
+            }
+            FeedResult::Frame { frame, remaining } => {
+                self.window = remaining;
+                Some(wire::decode(frame))
+            }
+        }
+    }
+}
+
+/// Encodes messages into frames of up to `N` bytes
+pub struct FrameEncoder<const N: usize> {
+    buf: [u8; N],
+}
+
+impl<const N: usize> Default for FrameEncoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> FrameEncoder<N> {
+    pub const fn new() -> Self {
+        Self { buf: [0; N] }
+    }
+
+    /// Encode a message, returning the bytes to put on the wire
+    pub fn encode<T>(&mut self, msg: &T) -> Result<&[u8], Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        wire::encode(msg, &mut self.buf).map(|s| &*s)
+    }
+
+    /// Encode a message that always fits, returning the bytes to put on the wire
+    ///
+    /// Whether the largest `T` fits in `N` bytes is checked when the program is built, so
+    /// this can't fail at run time.
+    pub fn encode_bounded<T>(&mut self, msg: &T) -> &[u8]
+    where
+        T: Serialize + MaxSize,
+    {
+        const {
+            assert!(
+                wire::max_frame_len(T::POSTCARD_MAX_SIZE) <= N,
+                "the largest message doesn't fit the encoder"
+            )
+        };
+        match wire::encode(msg, &mut self.buf) {
+            Ok(bytes) => bytes,
+            Err(_) => unreachable!("checked against the size of the buffer above"),
+        }
+    }
+}
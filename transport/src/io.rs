@@ -0,0 +1,433 @@
+//! Blocking adapter of the [`Client`] over [`std::io`]
+
+use crate::{
+    bulk::OBJECT_CRC,
+    client::{Client, BUF_SIZE},
+    codec, v0_2,
+    wire::{Accumulator, FeedResult},
+    Chunk, Command, ErrorCode, Event, Identity, ObjectId, Response, TelemetryPayload, BROADCAST,
+    DEFAULT_ADDR, MAX_ADDR,
+};
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub use crate::client::Stats;
+
+/// Errors talking to the FEM
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing the port failed
+    Io(io::Error),
+    /// The FEM didn't respond in time
+    Timeout,
+    /// The command couldn't be encoded
+    Encode(codec::Error),
+    /// The link to the FEM was closed
+    Closed,
+    /// The FEM refused a step of a bulk transfer
+    Rejected(ErrorCode),
+    /// The FEM responded to a step of a bulk transfer with something else
+    Unexpected,
+    /// 0.2 firmware has no bus address, so it can't be picked out from other FEMs
+    Unaddressable,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "serial port error: {e}"),
+            Error::Timeout => write!(f, "no response from the FEM"),
+            Error::Encode(e) => write!(f, "failed to encode command: {e}"),
+            Error::Closed => write!(f, "link to the FEM was closed"),
+            Error::Rejected(code) => write!(f, "{code}"),
+            Error::Unexpected => write!(f, "unexpected response from the FEM"),
+            Error::Unaddressable => write!(
+                f,
+                "0.2 firmware can only be talked to at address {DEFAULT_ADDR}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Pick a starting sequence number that's unlikely to match the last one sent to the FEM
+///
+/// Comes from the OS's random source, or the clock on the rare host without one.
+pub fn random_seq() -> u16 {
+    getrandom::u32().unwrap_or_else(|_| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() >> 10)
+            .unwrap_or_default()
+    }) as u16
+}
+
+/// Versions of the protocol a FEM can speak, see [`BlockingClient::detect`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Protocol {
+    /// Bare frames, see [`crate::v0_2`]
+    V0_2,
+    /// Frames with a sequence number, address and CRC, see [`crate::Frame`]
+    Framed,
+}
+
+/// Blocking client over anything that can be read from and written to, like a serial port
+///
+/// Reads on the port should time out (like `serialport`'s do) rather than block forever.
+pub struct BlockingClient<P> {
+    port: P,
+    client: Client,
+    timeout: Duration,
+}
+
+impl<P> BlockingClient<P>
+where
+    P: Read + Write,
+{
+    /// Default time to wait for a response
+    pub const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Number of times a step of a bulk transfer is tried again before giving up
+    pub const TRANSFER_RETRIES: usize = 3;
+
+    pub fn new(port: P) -> Self {
+        Self {
+            port,
+            client: Client::new(random_seq()),
+            timeout: Self::TIMEOUT,
+        }
+    }
+
+    /// Set the time to wait for a response
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Talk to the FEM at `addr` on the bus, rather than the one at [`crate::DEFAULT_ADDR`]
+    pub fn with_addr(mut self, addr: u8) -> Self {
+        self.client.set_addr(addr);
+        self
+    }
+
+    /// Send a command and wait for its response
+    pub fn request(&mut self, cmd: Command) -> Result<Response, Error> {
+        let bytes = self.client.request(cmd).map_err(Error::Encode)?;
+        self.port.write_all(bytes)?;
+        self.port.flush()?;
+        self.wait()
+    }
+
+    /// Send a command, resending it up to `retries` times if there's no response
+    ///
+    /// Retries carry the same sequence number, so control commands are only applied once.
+    pub fn request_with_retries(
+        &mut self,
+        cmd: Command,
+        retries: usize,
+    ) -> Result<Response, Error> {
+        match self.request(cmd) {
+            Err(Error::Timeout) => (),
+            res => return res,
+        }
+        for _ in 0..retries {
+            let bytes = self.client.retry().map_err(Error::Encode)?;
+            self.port.write_all(bytes)?;
+            self.port.flush()?;
+            match self.wait() {
+                Err(Error::Timeout) => (),
+                res => return res,
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Send a command to every FEM on the bus, none of which respond
+    pub fn broadcast(&mut self, cmd: Command) -> Result<(), Error> {
+        let bytes = self.client.broadcast(cmd).map_err(Error::Encode)?;
+        self.port.write_all(bytes)?;
+        self.port.flush()?;
+        Ok(())
+    }
+
+    /// Find every FEM on the bus by asking each address for its [`Identity`] in turn
+    ///
+    /// Every address is waited on for up to the timeout of the client, so it's best set to
+    /// not much more than the round trip time of the link.
+    pub fn enumerate(&mut self) -> Result<Vec<(u8, Identity)>, Error> {
+        let addr = self.client.addr();
+        let mut found = vec![];
+        let mut res = Ok(());
+        for probe in BROADCAST + 1..=MAX_ADDR {
+            self.client.set_addr(probe);
+            match self.request(Command::Identify) {
+                Ok(Response::Identity(identity)) => found.push((probe, identity)),
+                // Nothing there, or something that isn't a FEM
+                Ok(_) | Err(Error::Timeout) => (),
+                Err(e) => {
+                    res = Err(e);
+                    break;
+                }
+            }
+        }
+        self.client.set_addr(addr);
+        res.map(|()| found)
+    }
+
+    /// Upload an object to the FEM in chunks (see [`crate::bulk`])
+    ///
+    /// If an upload of the same data was interrupted, it's resumed where it left off.
+    pub fn upload(&mut self, object: ObjectId, data: &[u8]) -> Result<(), Error> {
+        // Offsets on the wire only go up to 4 GiB
+        let len = u32::try_from(data.len()).map_err(|_| Error::Encode(codec::Error::BufferFull))?;
+        let start = Command::UploadStart {
+            object,
+            len,
+            crc: OBJECT_CRC.checksum(data),
+        };
+        let mut received = self.upload_step(start.clone())?;
+        let mut restarts = 0;
+        while received < len {
+            let chunk = Chunk::new(received, &data[received as usize..]);
+            received = match self.upload_step(Command::UploadChunk(chunk)) {
+                // The chunk got mangled, or the FEM lost track of the upload, so pick up from
+                // wherever it's at now
+                Err(Error::Rejected(ErrorCode::Checksum | ErrorCode::NotStarted))
+                    if restarts < Self::TRANSFER_RETRIES =>
+                {
+                    restarts += 1;
+                    self.upload_step(start.clone())?
+                }
+                res => res?,
+            };
+        }
+        Ok(())
+    }
+
+    /// Send a command of an upload, returning the number of bytes the FEM has so far
+    fn upload_step(&mut self, cmd: Command) -> Result<u32, Error> {
+        match self.request_with_retries(cmd, Self::TRANSFER_RETRIES)? {
+            Response::UploadProgress { received } => Ok(received),
+            Response::Error(code) => Err(Error::Rejected(code)),
+            _ => Err(Error::Unexpected),
+        }
+    }
+
+    /// Download an object from the FEM in chunks (see [`crate::bulk`])
+    pub fn download(&mut self, object: ObjectId) -> Result<Vec<u8>, Error> {
+        let mut data = vec![];
+        let mut retries = 0;
+        loop {
+            let offset = data.len() as u32;
+            let cmd = Command::Download { object, offset };
+            let (len, chunk) = match self.request_with_retries(cmd, Self::TRANSFER_RETRIES)? {
+                Response::Chunk { len, chunk } => (len, chunk),
+                Response::Error(code) => return Err(Error::Rejected(code)),
+                _ => return Err(Error::Unexpected),
+            };
+            if !chunk.is_intact() || chunk.offset != offset {
+                retries += 1;
+                if retries > Self::TRANSFER_RETRIES {
+                    return Err(Error::Rejected(ErrorCode::Checksum));
+                }
+                continue;
+            }
+            let keep = (len.saturating_sub(offset) as usize).min(chunk.data.len());
+            data.extend_from_slice(&chunk.data[..keep]);
+            if data.len() as u32 >= len {
+                return Ok(data);
+            }
+            // Anything short of the end has to move us along
+            if keep == 0 {
+                return Err(Error::Unexpected);
+            }
+        }
+    }
+
+    fn wait(&mut self) -> Result<Response, Error> {
+        let deadline = Instant::now() + self.timeout;
+        while Instant::now() < deadline {
+            let read = self.fill()?;
+            if let Some(resp) = self.client.poll_response() {
+                return Ok(resp);
+            }
+            if !read {
+                break;
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Read whatever has arrived into the client, returning false if the read timed out
+    fn fill(&mut self) -> Result<bool, Error> {
+        let mut buf = [0u8; BUF_SIZE];
+        loop {
+            return match self.port.read(&mut buf) {
+                Ok(0) => Ok(false),
+                Ok(n) => {
+                    self.client.receive(&buf[..n]);
+                    Ok(true)
+                }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => Ok(false),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e.into()),
+            };
+        }
+    }
+
+    /// Iterate over the telemetry frames sent by the FEM after a [`Command::Subscribe`]
+    ///
+    /// Every frame is waited on for up to the timeout of the client, so it should be set
+    /// longer than the interval of the subscription.
+    pub fn telemetry(&mut self) -> Telemetry<'_, P> {
+        Telemetry { client: self }
+    }
+
+    /// Iterate over the events reported by the FEM, including any that arrived earlier
+    ///
+    /// Every event is waited on for up to the timeout of the client, yielding
+    /// [`Error::Timeout`] if there wasn't one.
+    pub fn events(&mut self) -> Events<'_, P> {
+        Events { client: self }
+    }
+
+    /// Wait for something unsolicited to arrive from the FEM
+    fn wait_for<T>(&mut self, mut poll: impl FnMut(&mut Client) -> Option<T>) -> Result<T, Error> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(item) = poll(&mut self.client) {
+                return Ok(item);
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::Timeout);
+            }
+            self.fill()?;
+        }
+    }
+
+    /// Find out which protocol the FEM speaks, asking for its [`Identity`] and then, if that
+    /// goes unanswered, for 0.2 monitor data
+    ///
+    /// Do it once, when the port is opened, and stick to the answer. Falling back to 0.2
+    /// whenever a request times out would take whatever the FEM sends next, like telemetry,
+    /// as the response, and could apply a control twice.
+    pub fn detect(&mut self) -> Result<Protocol, Error> {
+        match self.request(Command::Identify) {
+            Ok(_) => return Ok(Protocol::Framed),
+            // Only the FEM at the default address could be running 0.2
+            Err(Error::Timeout) if self.client.addr() == DEFAULT_ADDR => (),
+            Err(e) => return Err(e),
+        }
+        match self.request_v0_2(v0_2::Command::Monitor)? {
+            v0_2::Response::Monitor(_) => Ok(Protocol::V0_2),
+            _ => Err(Error::Unexpected),
+        }
+    }
+
+    /// Send a command to a FEM running 0.2 firmware and wait for its response
+    ///
+    /// 0.2 frames aren't numbered, so there's no telling a late response to an earlier
+    /// request apart from the response to this one. Nor are they addressed, so it fails with
+    /// [`Error::Unaddressable`] unless the client talks to [`DEFAULT_ADDR`].
+    pub fn request_v0_2(&mut self, cmd: v0_2::Command) -> Result<v0_2::Response, Error> {
+        if self.client.addr() != DEFAULT_ADDR {
+            return Err(Error::Unaddressable);
+        }
+        let mut buf = [0u8; BUF_SIZE];
+        let bytes = v0_2::encode(&cmd, &mut buf).map_err(Error::Encode)?;
+        self.port.write_all(bytes)?;
+        self.port.flush()?;
+
+        let deadline = Instant::now() + self.timeout;
+        let mut acc = Accumulator::<BUF_SIZE>::new();
+        while Instant::now() < deadline {
+            let n = match self.port.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let mut window = &buf[..n];
+            while !window.is_empty() {
+                window = match acc.feed(window) {
+                    FeedResult::Consumed => break,
+                    FeedResult::OverFull(remaining) => remaining,
+                    FeedResult::Frame { frame, remaining } => match v0_2::decode(frame) {
+                        Ok(resp) => return Ok(resp),
+                        Err(_) => remaining,
+                    },
+                };
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Counts of frames thrown away so far
+    pub fn stats(&self) -> Stats {
+        self.client.stats()
+    }
+
+    /// Address of the FEM being talked to
+    pub fn addr(&self) -> u8 {
+        self.client.addr()
+    }
+
+    /// Borrow the underlying port
+    pub fn get_ref(&self) -> &P {
+        &self.port
+    }
+
+    /// Get back the underlying port
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+}
+
+/// Iterator over telemetry frames, see [`BlockingClient::telemetry`]
+pub struct Telemetry<'a, P> {
+    client: &'a mut BlockingClient<P>,
+}
+
+impl<P> Iterator for Telemetry<'_, P>
+where
+    P: Read + Write,
+{
+    type Item = Result<TelemetryPayload, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.client.wait_for(Client::poll_telemetry))
+    }
+}
+
+/// Iterator over events, see [`BlockingClient::events`]
+pub struct Events<'a, P> {
+    client: &'a mut BlockingClient<P>,
+}
+
+impl<P> Iterator for Events<'_, P>
+where
+    P: Read + Write,
+{
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.client.wait_for(Client::poll_event))
+    }
+}
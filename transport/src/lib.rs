@@ -1,65 +1,32 @@
 //! Types that facilitate transport between the FEM firmware and MnC software
-#![no_std]
+//!
+//! The messages of every version of the protocol live in their own module, and the current
+//! version ([`v2`]) is re-exported from the root of the crate.
+#![cfg_attr(not(feature = "std"), no_std)]
 
-use serde::{Deserialize, Serialize};
+pub mod bulk;
+pub mod client;
+pub mod codec;
+#[cfg(feature = "std")]
+pub mod io;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod scpi;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+pub mod units;
+pub mod v0_2;
+pub mod v1;
+pub mod v2;
+pub mod wire;
 
-/// Actions that can be performed
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
-pub enum Action {
-    /// Set the IF "Good" power threshold in dBm
-    SetIfLevel(f32),
-    /// Control the power state of the LNA1 regulator
-    Lna1Power(bool),
-    /// Control the power state of the LNA2 regulator
-    Lna2Power(bool),
-    // Set attenuation
-    SetAtten(f32),
-}
-
-/// Monitor data sent in response to a [`Command::Monitor`] call
-#[derive(Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
-#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
-pub struct MonitorPayload {
-    /// IF1 power in dBm
-    pub if1_power: f32,
-    /// IF2 power in dBm
-    pub if2_power: f32,
-    /// RP2040 internal temperature in C
-    pub ic_temp: f32,
-    /// Voltage and current of LNA1
-    pub lna1_power: Power,
-    /// Voltage and current of LNA2
-    pub lna2_power: Power,
-    /// Voltage and current of the analog rail
-    pub analog_power: Power,
-}
-
-#[derive(Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
-#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
-pub struct Power {
-    /// Votlage in volts
-    pub voltage: f32,
-    /// Current in amps
-    pub current: f32,
-}
-
-/// Payloads from MnC software to the FEM
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
-pub enum Command {
-    Monitor,
-    Control(Action),
-}
+pub use v2::*;
 
-/// Payloads from FEM to MnC software
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
-pub enum Response {
-    /// Previous command was ok, but didn't need a response
-    Ack,
-    /// Error
-    Error,
-    /// Response to monitor request
-    Monitor(MonitorPayload),
+/// Whether a sequence number must not be used for a request
+///
+/// Besides [`NO_SEQ`], 0.2 firmware reads the sequence number at the start of a frame as the
+/// index of a [`v0_2::Command`], so a request numbered 1 could be mistaken for a valid command
+/// by a fielded board. Anything higher is rejected by it.
+pub const fn is_reserved_seq(seq: u16) -> bool {
+    seq <= 1
 }
@@ -0,0 +1,36 @@
+//! JSON Schema of the messages, for tools that keep or exchange them as JSON
+//!
+//! The JSON form of a message is the one its `serde` derives give, the same ones postcard
+//! encodes it with, so a message read back from JSON encodes to the same bytes as the original:
+//!
+//! - Enums are externally tagged. A unit variant is its name (`"Monitor"`), any other variant
+//!   is an object with its name as the only key, holding its value
+//!   (`{"Control": {"SetAtten": 10.5}}`), an array of its values (`{"Set": [2, {"F32": 10.5}]}`)
+//!   or an object of its fields (`{"Download": {"object": 0, "offset": 256}}`).
+//! - Fields are named as they are in the Rust types.
+//! - Bit masks and identifiers, like [`crate::Actions`] and [`crate::ParamId`], are bare
+//!   integers.
+//! - Quantities of [`crate::units`] are bare numbers in their unit, checked when they're read.
+//! - `None` is `null`, strings and lists are JSON strings and arrays.
+//!
+//! JSON has no NaN or infinity, so a float that isn't finite is written as `null` and can't be
+//! read back.
+//!
+//! Renaming a variant or field leaves the postcard encoding alone but changes the JSON. The
+//! [`documents`] are kept in `schema/` and checked against the types by the tests, so a change
+//! like that shows up in review.
+
+use crate::{v2::Frame, Command, MonitorPayload, MonitorPayloadV2, Response};
+use schemars::{schema_for, Schema};
+
+/// JSON Schema documents of the messages, along with the names they're kept under
+pub fn documents() -> [(&'static str, Schema); 6] {
+    [
+        ("command", schema_for!(Command)),
+        ("response", schema_for!(Response)),
+        ("command_frame", schema_for!(Frame<Command>)),
+        ("response_frame", schema_for!(Frame<Response>)),
+        ("monitor_payload", schema_for!(MonitorPayload)),
+        ("monitor_payload_v2", schema_for!(MonitorPayloadV2)),
+    ]
+}
@@ -0,0 +1,377 @@
+//! SCPI-style text commands, for talking to the FEM from a plain terminal
+//!
+//! Every line is one command, made of a header and an optional argument, like `ATT 10.5`,
+//! `LNA1 OFF` or `MEAS:IF1?`. Headers are case-insensitive. Lines map onto the same
+//! [`Command`]s as frames do, and the [`Response`] is turned back into a line of text.
+//!
+//! | Line                  | Meaning                                      |
+//! |-----------------------|----------------------------------------------|
+//! | `*IDN?`               | Identity of the FEM                          |
+//! | `MEAS:IF1?`, `IF2?`   | IF power in dBm                              |
+//! | `MEAS:TEMP?`          | RP2040 temperature in C                      |
+//! | `MEAS:<rail>:VOLT?`   | Voltage of `LNA1`, `LNA2` or `ANALOG` rail   |
+//! | `MEAS:<rail>:CURR?`   | Current of `LNA1`, `LNA2` or `ANALOG` rail   |
+//! | `ATT <dB>`, `ATT?`    | Set or get the attenuation                   |
+//! | `LNA1 ON\|OFF`, `LNA1?` | Set or get the power of LNA1 (same for LNA2) |
+//! | `IF:THR <dBm>`, `IF:THR?` | Set or get the IF "Good" threshold       |
+//!
+//! The FEM tells text apart from frames with a [`Splitter`]: frames end in a zero byte, which
+//! a terminal never sends, and lines end in a carriage return or line feed. Replies end in a
+//! zero byte after their line ending too, so a host reading frames off the same bus drops
+//! them as broken frames rather than taking them for the start of the next one.
+
+use crate::{wire, Action, Attenuation, Command, Dbm, ErrorCode, Rail, Response, Validity};
+use core::fmt::{self, Write};
+
+/// Something that can be asked for on a line of text
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+pub enum Request {
+    /// `*IDN?`
+    Identify,
+    /// `MEAS:...?`
+    Measure(Measurement),
+    /// `ATT?`, `LNA1?`, `LNA2?` or `IF:THR?`
+    Query(Setting),
+    /// `ATT <dB>`, `LNA1 ON|OFF`, `LNA2 ON|OFF` or `IF:THR <dBm>`
+    Set(Action),
+}
+
+/// Readings that can be measured with `MEAS:...?`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+pub enum Measurement {
+    If1Power,
+    If2Power,
+    Temperature,
+    Voltage(Rail),
+    Current(Rail),
+}
+
+/// Settings that can be queried
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+pub enum Setting {
+    Attenuation,
+    Lna1Power,
+    Lna2Power,
+    IfThreshold,
+}
+
+/// Reasons a line could not be parsed
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+pub enum ParseError {
+    /// The line was blank
+    Empty,
+    /// The header isn't one we know
+    UnknownHeader,
+    /// The header needs an argument, but didn't get one
+    MissingArgument,
+    /// The argument couldn't be understood, or the header doesn't take one
+    BadArgument,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "empty command"),
+            ParseError::UnknownHeader => write!(f, "unknown command"),
+            ParseError::MissingArgument => write!(f, "missing argument"),
+            ParseError::BadArgument => write!(f, "bad argument"),
+        }
+    }
+}
+
+/// Parse a line of text (without the line ending) into a request
+pub fn parse(line: &str) -> Result<Request, ParseError> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    let (header, arg) = match line.split_once(|c: char| c.is_ascii_whitespace()) {
+        Some((header, arg)) => (header, Some(arg.trim())),
+        None => (line, None),
+    };
+    let is = |name: &str| header.eq_ignore_ascii_case(name);
+    let query = |req| match arg {
+        None => Ok(req),
+        Some(_) => Err(ParseError::BadArgument),
+    };
+    let number = || -> Result<f32, ParseError> {
+        arg.ok_or(ParseError::MissingArgument)?
+            .parse()
+            .map_err(|_| ParseError::BadArgument)
+    };
+    let switch = || -> Result<bool, ParseError> {
+        let arg = arg.ok_or(ParseError::MissingArgument)?;
+        if arg.eq_ignore_ascii_case("ON") || arg == "1" {
+            Ok(true)
+        } else if arg.eq_ignore_ascii_case("OFF") || arg == "0" {
+            Ok(false)
+        } else {
+            Err(ParseError::BadArgument)
+        }
+    };
+    if is("*IDN?") {
+        query(Request::Identify)
+    } else if is("ATT") {
+        // Out of range is as bad as not a number
+        let atten = Attenuation::new(number()?).map_err(|_| ParseError::BadArgument)?;
+        Ok(Request::Set(Action::SetAtten(atten)))
+    } else if is("ATT?") {
+        query(Request::Query(Setting::Attenuation))
+    } else if is("LNA1") {
+        Ok(Request::Set(Action::Lna1Power(switch()?)))
+    } else if is("LNA1?") {
+        query(Request::Query(Setting::Lna1Power))
+    } else if is("LNA2") {
+        Ok(Request::Set(Action::Lna2Power(switch()?)))
+    } else if is("LNA2?") {
+        query(Request::Query(Setting::Lna2Power))
+    } else if is("IF:THR") {
+        let level = Dbm::new(number()?).map_err(|_| ParseError::BadArgument)?;
+        Ok(Request::Set(Action::SetIfLevel(level)))
+    } else if is("IF:THR?") {
+        query(Request::Query(Setting::IfThreshold))
+    } else if let Some(what) = strip_prefix_ignore_case(header, "MEAS:") {
+        query(Request::Measure(measurement(what)?))
+    } else {
+        Err(ParseError::UnknownHeader)
+    }
+}
+
+/// Parse what comes after `MEAS:`
+fn measurement(what: &str) -> Result<Measurement, ParseError> {
+    let what = what.strip_suffix('?').ok_or(ParseError::UnknownHeader)?;
+    let is = |name: &str| what.eq_ignore_ascii_case(name);
+    if is("IF1") {
+        return Ok(Measurement::If1Power);
+    } else if is("IF2") {
+        return Ok(Measurement::If2Power);
+    } else if is("TEMP") {
+        return Ok(Measurement::Temperature);
+    }
+    let (rail, quantity) = what.split_once(':').ok_or(ParseError::UnknownHeader)?;
+    let rail = if rail.eq_ignore_ascii_case("LNA1") {
+        Rail::Lna1
+    } else if rail.eq_ignore_ascii_case("LNA2") {
+        Rail::Lna2
+    } else if rail.eq_ignore_ascii_case("ANALOG") {
+        Rail::Analog
+    } else {
+        return Err(ParseError::UnknownHeader);
+    };
+    if quantity.eq_ignore_ascii_case("VOLT") {
+        Ok(Measurement::Voltage(rail))
+    } else if quantity.eq_ignore_ascii_case("CURR") {
+        Ok(Measurement::Current(rail))
+    } else {
+        Err(ParseError::UnknownHeader)
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &s[prefix.len()..])
+}
+
+impl Request {
+    /// The command that carries out this request
+    pub fn command(&self) -> Command {
+        match self {
+            Request::Identify => Command::Identify,
+            Request::Measure(_) => Command::MonitorV2,
+            Request::Query(_) => Command::GetState,
+            Request::Set(action) => Command::Control(*action),
+        }
+    }
+
+    /// Write the reply to the response of [`Request::command`], without a line ending
+    ///
+    /// Errors are written as `ERR <reason>`, and readings that aren't valid as `NAN`.
+    pub fn reply<W: Write>(&self, resp: &Response, out: &mut W) -> fmt::Result {
+        let on_off = |en: bool| if en { "ON" } else { "OFF" };
+        match (self, resp) {
+            (_, Response::Error(code)) => write_error(out, code),
+            (Request::Set(_), Response::Ack) => write!(out, "OK"),
+            (Request::Identify, Response::Identity(id)) => write!(
+                out,
+                "GREX,FEM,{:016X},{}+{}",
+                id.serial, id.firmware_version, id.git_hash
+            ),
+            (Request::Query(setting), Response::State(state)) => match setting {
+                Setting::Attenuation => write!(out, "{}", state.atten1),
+                Setting::Lna1Power => write!(out, "{}", on_off(state.lna1_enabled)),
+                Setting::Lna2Power => write!(out, "{}", on_off(state.lna2_enabled)),
+                Setting::IfThreshold => write!(out, "{}", state.if_good_threshold),
+            },
+            (Request::Measure(m), Response::MonitorV2(p)) => {
+                let (value, field): (f32, _) = match m {
+                    Measurement::If1Power => (p.if1_power.into(), Validity::IF1_POWER),
+                    Measurement::If2Power => (p.if2_power.into(), Validity::IF2_POWER),
+                    Measurement::Temperature => (p.ic_temp, Validity::IC_TEMP),
+                    Measurement::Voltage(Rail::Lna1) => {
+                        (p.lna1_power.voltage.into(), Validity::LNA1_VOLTAGE)
+                    }
+                    Measurement::Voltage(Rail::Lna2) => {
+                        (p.lna2_power.voltage.into(), Validity::LNA2_VOLTAGE)
+                    }
+                    Measurement::Voltage(Rail::Analog) => {
+                        (p.analog_power.voltage.into(), Validity::ANALOG_VOLTAGE)
+                    }
+                    Measurement::Current(Rail::Lna1) => {
+                        (p.lna1_power.current.into(), Validity::LNA1_CURRENT)
+                    }
+                    Measurement::Current(Rail::Lna2) => {
+                        (p.lna2_power.current.into(), Validity::LNA2_CURRENT)
+                    }
+                    Measurement::Current(Rail::Analog) => {
+                        (p.analog_power.current.into(), Validity::ANALOG_CURRENT)
+                    }
+                };
+                if p.valid.contains(field) {
+                    write!(out, "{value}")
+                } else {
+                    write!(out, "NAN")
+                }
+            }
+            _ => write_error(out, &ErrorCode::Unsupported),
+        }
+    }
+}
+
+/// Write the reply to a line that failed, without a line ending
+pub fn write_error<W: Write, E: fmt::Display>(out: &mut W, e: &E) -> fmt::Result {
+    write!(out, "ERR {e}")
+}
+
+/// Splits bytes off the UART into frames and lines of text, holding up to `N` bytes
+///
+/// Bytes are collected until either a zero byte, which ends a frame, or a carriage return or
+/// line feed. The latter only end a line of text if everything before it was printable ASCII
+/// starting with a whole header, otherwise they're part of a frame.
+///
+/// A frame can start with a line ending too, as its first COBS code byte, or with a couple of
+/// printable bytes and then one, so those at the start are held on to until it's clear whether
+/// they're left over from a line (like the LF of CR LF) or the start of a frame, going by the
+/// CRC of the frame (see [`crate::wire`]).
+pub struct Splitter<const N: usize> {
+    buf: [u8; N],
+    idx: usize,
+    /// Number of bytes at the start of the buffer that are either a frame or left over text
+    lead: usize,
+    text: bool,
+    overfull: bool,
+}
+
+/// The result of feeding bytes to a [`Splitter`]
+pub enum Split<'a, 'b> {
+    /// All of the input was consumed without completing a frame or line
+    Consumed,
+    /// A frame or line was too large for the splitter and was dropped
+    OverFull(&'b [u8]),
+    /// A whole frame (without the zero delimiter) is ready to be decoded
+    Frame {
+        frame: &'a mut [u8],
+        remaining: &'b [u8],
+    },
+    /// A whole line of text (without the line ending) is ready to be parsed
+    Line { line: &'a str, remaining: &'b [u8] },
+}
+
+impl<const N: usize> Default for Splitter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Splitter<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            idx: 0,
+            lead: 0,
+            text: true,
+            overfull: false,
+        }
+    }
+
+    /// Feed bytes into the splitter, stopping at the end of the first complete frame or line
+    pub fn feed<'a, 'b>(&'a mut self, input: &'b [u8]) -> Split<'a, 'b> {
+        for (i, &byte) in input.iter().enumerate() {
+            let remaining = &input[i + 1..];
+            let line_ending = byte == b'\r' || byte == b'\n';
+            let line = line_ending && self.text && is_header(&self.buf[self.lead..self.idx]);
+            if byte == 0 || line {
+                let len = self.idx;
+                let lead = self.lead;
+                let overfull = self.overfull;
+                self.idx = 0;
+                self.lead = 0;
+                self.text = true;
+                self.overfull = false;
+                if overfull {
+                    return Split::OverFull(remaining);
+                }
+                if byte == 0 {
+                    let start = frame_start(&self.buf[..len], lead);
+                    // Back-to-back delimiters, or line endings before one, are just idle line
+                    if start == len {
+                        continue;
+                    }
+                    return Split::Frame {
+                        frame: &mut self.buf[start..len],
+                        remaining,
+                    };
+                }
+                // Only printable ASCII made it in here past the line endings
+                let line = core::str::from_utf8(&self.buf[lead..len]).unwrap_or_default();
+                return Split::Line { line, remaining };
+            }
+            if line_ending && self.text && self.idx < N {
+                // Too short to be a line, so it's either a frame or nothing
+                self.lead = self.idx + 1;
+            } else {
+                self.text &= byte.is_ascii_graphic() || byte == b' ';
+            }
+            if self.idx < N {
+                self.buf[self.idx] = byte;
+                self.idx += 1;
+            } else {
+                self.overfull = true;
+            }
+        }
+        Split::Consumed
+    }
+}
+
+/// Whether `line` starts with a whole header, of the at least three characters every header
+/// has
+///
+/// Before any line ending in it, a frame has at most two printable bytes: its COBS code byte
+/// and the low byte of its sequence number. The byte after them, the start of the message or
+/// the magic of a v2 frame (see [`crate::v2::MAGIC`]), isn't printable unless it's a line
+/// ending itself.
+fn is_header(line: &[u8]) -> bool {
+    let line = line.trim_ascii_start();
+    let len = line.iter().position(|&b| b == b' ').unwrap_or(line.len());
+    len >= 3
+        && line[..len]
+            .iter()
+            .all(|&b| b.is_ascii_alphanumeric() || b"*:?".contains(&b))
+}
+
+/// Where a frame starts, past whichever of the `lead` bytes before it aren't its own
+///
+/// That's the first place its CRC checks out from, or the very start if there's no such place
+/// and it's broken anyway.
+fn frame_start(bytes: &[u8], lead: usize) -> usize {
+    if lead == bytes.len() {
+        return lead;
+    }
+    (0..=lead)
+        .find(|&start| wire::is_intact(&bytes[start..]))
+        .unwrap_or(0)
+}
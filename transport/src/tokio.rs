@@ -0,0 +1,319 @@
+//! Async codec and client for [tokio](https://tokio.rs) consumers of the FEM protocol
+//!
+//! [`FemClient`] works over anything that's [`AsyncRead`] + [`AsyncWrite`], like a
+//! `tokio_serial::SerialStream`:
+//!
+//! ```ignore
+//! let port = tokio_serial::new("/dev/ttyAMA0", 115_200).open_native_async()?;
+//! let fem = FemClient::new(port);
+//! let resp = fem.request(Command::Monitor).await?;
+//! ```
+
+use crate::{
+    codec, io::Error, is_reserved_seq, wire, Command, Event, Frame, Response, TelemetryPayload,
+    BROADCAST, DEFAULT_ADDR, NO_SEQ,
+};
+use ::tokio::{
+    io::{AsyncRead, AsyncWrite, WriteHalf},
+    sync::{
+        broadcast::{self, error::RecvError},
+        oneshot, Mutex,
+    },
+    task::JoinHandle,
+    time::timeout,
+};
+use bytes::{BufMut, BytesMut};
+use futures_util::{stream, SinkExt, Stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    io,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU16, AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite};
+
+/// Largest frame the codec will buffer before giving up on it
+pub const MAX_FRAME: usize = crate::MAX_FRAME_LEN;
+
+/// Number of telemetry frames held for a lagging [`FemClient::telemetry`] stream
+pub const TELEMETRY_DEPTH: usize = 16;
+
+/// Number of events held for a lagging [`FemClient::events`] stream
+pub const EVENT_DEPTH: usize = 16;
+
+/// [`Decoder`] of frames carrying a `T`, and [`Encoder`] of frames carrying anything
+///
+/// Malformed frames are yielded as errors instead of ending the stream.
+pub struct FrameCodec<T> {
+    overfull: bool,
+    _msg: PhantomData<T>,
+}
+
+/// Codec for the MnC side of the link, decoding [`Response`]s and encoding [`Command`]s
+pub type HostCodec = FrameCodec<Response>;
+
+/// Codec for the FEM side of the link, decoding [`Command`]s and encoding [`Response`]s
+pub type DeviceCodec = FrameCodec<Command>;
+
+impl<T> Default for FrameCodec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> FrameCodec<T> {
+    pub fn new() -> Self {
+        Self {
+            overfull: false,
+            _msg: PhantomData,
+        }
+    }
+}
+
+impl<T> Decoder for FrameCodec<T>
+where
+    T: DeserializeOwned,
+{
+    type Item = Result<Frame<T>, codec::Error>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            let Some(end) = src.iter().position(|&b| b == 0) else {
+                // Throw away frames that will never fit, but remember we're in one
+                if src.len() > MAX_FRAME {
+                    src.clear();
+                    self.overfull = true;
+                }
+                return Ok(None);
+            };
+            let mut frame = src.split_to(end + 1);
+            frame.truncate(end);
+            if std::mem::take(&mut self.overfull) || end > MAX_FRAME {
+                return Ok(Some(Err(codec::Error::BufferFull)));
+            }
+            // Back-to-back delimiters are just idle line
+            if frame.is_empty() {
+                continue;
+            }
+            return Ok(Some(wire::decode(&mut frame[..])));
+        }
+    }
+}
+
+impl<T, U> Encoder<Frame<U>> for FrameCodec<T>
+where
+    U: Serialize,
+{
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Frame<U>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let mut buf = [0u8; MAX_FRAME];
+        let bytes = wire::encode(&item, &mut buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        dst.reserve(bytes.len());
+        dst.put_slice(bytes);
+        Ok(())
+    }
+}
+
+type Pending = Arc<std::sync::Mutex<HashMap<u16, oneshot::Sender<Response>>>>;
+type Writer = FramedWrite<WriteHalf<Box<dyn AsyncReadWrite>>, HostCodec>;
+
+/// Something that can be read from and written to asynchronously
+pub trait AsyncReadWrite: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<S> AsyncReadWrite for S where S: AsyncRead + AsyncWrite + Send + Unpin {}
+
+/// Async client of a FEM
+///
+/// Requests can be pipelined: every request gets its own sequence number, so any number can
+/// be in flight at once from concurrent tasks and their responses are routed back by a
+/// background reader task.
+pub struct FemClient {
+    writer: Mutex<Writer>,
+    pending: Pending,
+    telemetry: broadcast::Receiver<TelemetryPayload>,
+    events: broadcast::Receiver<Event>,
+    next_seq: AtomicU16,
+    addr: Arc<AtomicU8>,
+    timeout: Duration,
+    reader: JoinHandle<()>,
+}
+
+impl FemClient {
+    /// Default time to wait for a response
+    pub const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Start a client over `io`, spawning its reader task on the current runtime
+    pub fn new<S>(io: S) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let (rx, tx) = ::tokio::io::split(Box::new(io) as Box<dyn AsyncReadWrite>);
+        let pending = Pending::default();
+        let (telemetry_tx, telemetry) = broadcast::channel(TELEMETRY_DEPTH);
+        let (events_tx, events) = broadcast::channel(EVENT_DEPTH);
+        let addr = Arc::new(AtomicU8::new(DEFAULT_ADDR));
+        let reader = ::tokio::spawn(route_responses(
+            FramedRead::new(rx, HostCodec::new()),
+            addr.clone(),
+            pending.clone(),
+            telemetry_tx,
+            events_tx,
+        ));
+        Self {
+            writer: Mutex::new(FramedWrite::new(tx, HostCodec::new())),
+            pending,
+            telemetry,
+            events,
+            next_seq: AtomicU16::new(crate::io::random_seq()),
+            addr,
+            timeout: Self::TIMEOUT,
+            reader,
+        }
+    }
+
+    /// Set the time to wait for a response
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Talk to the FEM at `addr` on the bus, rather than the one at [`DEFAULT_ADDR`]
+    ///
+    /// Frames from any other FEM are ignored.
+    pub fn with_addr(self, addr: u8) -> Self {
+        self.addr.store(addr, Ordering::Relaxed);
+        self
+    }
+
+    /// Send a command and wait for its response
+    pub async fn request(&self, cmd: Command) -> Result<Response, Error> {
+        let seq = self.seq();
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(seq, tx);
+        let frame = Frame::new(seq, cmd).with_addr(self.addr.load(Ordering::Relaxed));
+        let res = self.send_and_wait(frame, rx).await;
+        self.pending.lock().unwrap().remove(&seq);
+        res
+    }
+
+    /// Send a command to every FEM on the bus, none of which respond
+    pub async fn broadcast(&self, cmd: Command) -> Result<(), Error> {
+        let frame = Frame::new(self.seq(), cmd).with_addr(BROADCAST);
+        Ok(self.writer.lock().await.send(frame).await?)
+    }
+
+    /// Stream of the telemetry frames sent by the FEM after a [`Command::Subscribe`]
+    ///
+    /// Only frames that arrive after the stream is created are yielded, skipping the oldest
+    /// if it falls too far behind. The stream ends when the link is closed.
+    pub fn telemetry(&self) -> impl Stream<Item = TelemetryPayload> {
+        broadcast_stream(self.telemetry.resubscribe())
+    }
+
+    /// Stream of the events reported by the FEM
+    ///
+    /// Like [`FemClient::telemetry`], only events that arrive after the stream is created are
+    /// yielded and the stream ends when the link is closed.
+    pub fn events(&self) -> impl Stream<Item = Event> {
+        broadcast_stream(self.events.resubscribe())
+    }
+
+    async fn send_and_wait(
+        &self,
+        frame: Frame<Command>,
+        rx: oneshot::Receiver<Response>,
+    ) -> Result<Response, Error> {
+        self.writer.lock().await.send(frame).await?;
+        match timeout(self.timeout, rx).await {
+            Ok(Ok(resp)) => Ok(resp),
+            Ok(Err(_)) => Err(Error::Closed),
+            Err(_) => Err(Error::Timeout),
+        }
+    }
+
+    fn seq(&self) -> u16 {
+        loop {
+            let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+            if !is_reserved_seq(seq) {
+                return seq;
+            }
+        }
+    }
+}
+
+impl Drop for FemClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Stream of everything sent on a broadcast channel, skipping anything it lagged behind on
+fn broadcast_stream<T: Clone>(rx: broadcast::Receiver<T>) -> impl Stream<Item = T> {
+    stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(item) => return Some((item, rx)),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+/// Hand every response to the request waiting for it, and everything else to its streams
+async fn route_responses<R>(
+    mut frames: FramedRead<R, HostCodec>,
+    addr: Arc<AtomicU8>,
+    pending: Pending,
+    telemetry: broadcast::Sender<TelemetryPayload>,
+    events: broadcast::Sender<Event>,
+) where
+    R: AsyncRead + Unpin,
+{
+    while let Some(Ok(frame)) = frames.next().await {
+        let Ok(Frame {
+            seq,
+            addr: from,
+            body,
+            ..
+        }) = frame
+        else {
+            continue;
+        };
+        // Other FEMs on the bus are none of our business
+        if from != addr.load(Ordering::Relaxed) {
+            continue;
+        }
+        let mut pending = pending.lock().unwrap();
+        let waiter = match (seq, body) {
+            // Nobody listening is fine
+            (NO_SEQ, Response::Telemetry(payload)) => {
+                let _ = telemetry.send(payload);
+                None
+            }
+            (NO_SEQ, Response::Event(event)) => {
+                let _ = events.send(event);
+                None
+            }
+            (NO_SEQ, body @ Response::Error(_)) if pending.len() == 1 => {
+                // The FEM couldn't read the sequence number, but there's only one it could be
+                let seq = *pending.keys().next().unwrap();
+                pending.remove(&seq).map(|tx| (tx, body))
+            }
+            (seq, body) => pending.remove(&seq).map(|tx| (tx, body)),
+        };
+        if let Some((tx, body)) = waiter {
+            let _ = tx.send(body);
+        }
+    }
+    // Dropping the waiters tells them the link is gone
+    pending.lock().unwrap().clear();
+}
@@ -0,0 +1,181 @@
+//! Physical quantities carried by the messages, checked when they're made
+//!
+//! Each is a bare `f32` on the wire, as it always was, but can only hold a sensible value:
+//! making one out of range fails, and so does deserializing a message with one out of range.
+//! Something like a NaN attenuation is then caught by whoever tries to send it, rather than
+//! by the FEM (or not at all).
+
+use crate::v1::ErrorCode;
+use core::fmt;
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+/// A quantity was given a value outside of its range, which NaN always is
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+pub struct OutOfRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "must be between {} and {}", self.min, self.max)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for OutOfRange {}
+
+impl From<OutOfRange> for ErrorCode {
+    fn from(e: OutOfRange) -> Self {
+        ErrorCode::OutOfRange {
+            min: e.min,
+            max: e.max,
+        }
+    }
+}
+
+/// Attenuation of the HMC624A attenuators in dB, a whole number of [`Attenuation::STEP`]s
+/// from [`Attenuation::MIN`] to [`Attenuation::MAX`]
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, PartialOrd, Default, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[serde(try_from = "f32", into = "f32")]
+pub struct Attenuation(f32);
+
+impl Attenuation {
+    /// Step of the attenuators in dB
+    pub const STEP: f32 = 0.5;
+    /// No attenuation
+    pub const MIN: Self = Self(0.0);
+    /// Every one of the 6 bits of the attenuators set
+    pub const MAX: Self = Self(63.0 * Self::STEP);
+
+    /// Attenuation of `db`, rounded to the nearest step
+    pub fn new(db: f32) -> Result<Self, OutOfRange> {
+        if !(Self::MIN.0..=Self::MAX.0).contains(&db) {
+            return Err(OutOfRange {
+                min: Self::MIN.0,
+                max: Self::MAX.0,
+            });
+        }
+        // It's positive, so truncating rounds to nearest with the half added
+        let steps = (db / Self::STEP + 0.5) as u8;
+        Ok(Self(steps as f32 * Self::STEP))
+    }
+
+    /// The attenuation in dB
+    pub const fn db(self) -> f32 {
+        self.0
+    }
+
+    /// Number of steps of the attenuation, at most 63
+    pub fn steps(self) -> u8 {
+        (self.0 / Self::STEP) as u8
+    }
+}
+
+impl TryFrom<f32> for Attenuation {
+    type Error = OutOfRange;
+
+    fn try_from(db: f32) -> Result<Self, OutOfRange> {
+        Self::new(db)
+    }
+}
+
+impl From<Attenuation> for f32 {
+    fn from(atten: Attenuation) -> f32 {
+        atten.0
+    }
+}
+
+impl fmt::Display for Attenuation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// By hand to get the range in, which the derive leaves out of quantities made with `try_from`
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Attenuation {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "Attenuation".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "description": "Attenuation in dB, rounded to the nearest step of the attenuators",
+            "type": "number",
+            "minimum": Self::MIN.0,
+            "maximum": Self::MAX.0,
+        })
+    }
+}
+
+/// Quantities that can take any finite value
+macro_rules! finite_quantity {
+    ($(#[$doc:meta])* $name:ident, $get:ident) => {
+        $(#[$doc])*
+        #[derive(
+            Serialize, Deserialize, MaxSize, Debug, PartialEq, PartialOrd, Default, Clone, Copy,
+        )]
+        #[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+        #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+        #[serde(try_from = "f32", into = "f32")]
+        pub struct $name(f32);
+
+        impl $name {
+            /// Fails for infinities and NaN
+            pub fn new(x: f32) -> Result<Self, OutOfRange> {
+                if x.is_finite() {
+                    Ok(Self(x))
+                } else {
+                    Err(OutOfRange {
+                        min: f32::MIN,
+                        max: f32::MAX,
+                    })
+                }
+            }
+
+            pub const fn $get(self) -> f32 {
+                self.0
+            }
+        }
+
+        impl TryFrom<f32> for $name {
+            type Error = OutOfRange;
+
+            fn try_from(x: f32) -> Result<Self, OutOfRange> {
+                Self::new(x)
+            }
+        }
+
+        impl From<$name> for f32 {
+            fn from(x: $name) -> f32 {
+                x.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+    };
+}
+
+finite_quantity!(
+    /// Power in dBm
+    Dbm,
+    dbm
+);
+finite_quantity!(
+    /// Potential in volts
+    Volts,
+    volts
+);
+finite_quantity!(
+    /// Current in amps
+    Amps,
+    amps
+);
@@ -0,0 +1,224 @@
+//! Version 0.2 of the protocol, still spoken by fielded boards
+//!
+//! Messages are bare: there's no sequence number or CRC, just the postcard-serialized message,
+//! COBS encoded and terminated with a zero byte. 0.2 firmware doesn't respond to frames it
+//! can't deserialize, so newer frames sent to it simply time out.
+//!
+//! These types are frozen, they must match what the 0.2 firmware was built with. The
+//! quantities of [`crate::units`] are bare floats on the wire, so they're used here too.
+
+use crate::{
+    v1::{self, Amps, Attenuation, Dbm, Volts},
+    wire::{Error, CRC, CRC_LEN},
+};
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+/// Actions that can be performed
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Action {
+    /// Set the IF "Good" power threshold
+    SetIfLevel(Dbm),
+    /// Control the power state of the LNA1 regulator
+    Lna1Power(bool),
+    /// Control the power state of the LNA2 regulator
+    Lna2Power(bool),
+    /// Set attenuation
+    SetAtten(Attenuation),
+}
+
+/// Monitor data sent in response to a [`Command::Monitor`] call
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Default, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MonitorPayload {
+    /// IF1 power
+    pub if1_power: Dbm,
+    /// IF2 power
+    pub if2_power: Dbm,
+    /// RP2040 internal temperature in C
+    pub ic_temp: f32,
+    /// Voltage and current of LNA1
+    pub lna1_power: Power,
+    /// Voltage and current of LNA2
+    pub lna2_power: Power,
+    /// Voltage and current of the analog rail
+    pub analog_power: Power,
+}
+
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Default, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Power {
+    pub voltage: Volts,
+    pub current: Amps,
+}
+
+/// Payloads from MnC software to the FEM
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Command {
+    Monitor,
+    Control(Action),
+}
+
+/// Payloads from FEM to MnC software
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Response {
+    /// Previous command was ok, but didn't need a response
+    Ack,
+    /// Error
+    Error,
+    /// Response to monitor request
+    Monitor(MonitorPayload),
+}
+
+/// Serialize a message into `buf` as a complete 0.2 frame, including the zero delimiter
+pub fn encode<'a, T>(msg: &T, buf: &'a mut [u8]) -> Result<&'a mut [u8], Error>
+where
+    T: Serialize + ?Sized,
+{
+    postcard::to_slice_cobs(msg, buf).map_err(|_| Error::BufferFull)
+}
+
+/// Decode a single 0.2 frame (without the zero delimiter) in place and deserialize it
+///
+/// postcard ignores trailing bytes, so a 0.2 message can be read off the front of most later
+/// frames, like telemetry read as an [`Response::Ack`]. Anything ending in a CRC of the rest
+/// is taken to be one of those and rejected with [`Error::Deserialize`], which a 0.2 frame only
+/// does by chance, once in 65536.
+pub fn decode<'a, T>(frame: &'a mut [u8]) -> Result<T, Error>
+where
+    T: Deserialize<'a>,
+{
+    let n = cobs::decode_in_place(frame).map_err(|_| Error::Cobs)?;
+    if is_framed(&frame[..n]) {
+        return Err(Error::Deserialize);
+    }
+    postcard::from_bytes(&frame[..n]).map_err(|_| Error::Deserialize)
+}
+
+/// Whether decoded bytes check out as a later frame, with a CRC trailer
+fn is_framed(bytes: &[u8]) -> bool {
+    match bytes.len().checked_sub(CRC_LEN) {
+        Some(n) if n > 0 => CRC.checksum(&bytes[..n]).to_le_bytes() == bytes[n..],
+        _ => false,
+    }
+}
+
+impl From<Action> for v1::Action {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::SetIfLevel(level) => v1::Action::SetIfLevel(level),
+            Action::Lna1Power(en) => v1::Action::Lna1Power(en),
+            Action::Lna2Power(en) => v1::Action::Lna2Power(en),
+            Action::SetAtten(atten) => v1::Action::SetAtten(atten),
+        }
+    }
+}
+
+impl From<v1::Action> for Action {
+    fn from(action: v1::Action) -> Self {
+        match action {
+            v1::Action::SetIfLevel(level) => Action::SetIfLevel(level),
+            v1::Action::Lna1Power(en) => Action::Lna1Power(en),
+            v1::Action::Lna2Power(en) => Action::Lna2Power(en),
+            v1::Action::SetAtten(atten) => Action::SetAtten(atten),
+        }
+    }
+}
+
+impl From<Power> for v1::Power {
+    fn from(p: Power) -> Self {
+        v1::Power {
+            voltage: p.voltage,
+            current: p.current,
+        }
+    }
+}
+
+impl From<v1::Power> for Power {
+    fn from(p: v1::Power) -> Self {
+        Power {
+            voltage: p.voltage,
+            current: p.current,
+        }
+    }
+}
+
+impl From<MonitorPayload> for v1::MonitorPayload {
+    fn from(p: MonitorPayload) -> Self {
+        v1::MonitorPayload {
+            if1_power: p.if1_power,
+            if2_power: p.if2_power,
+            ic_temp: p.ic_temp,
+            lna1_power: p.lna1_power.into(),
+            lna2_power: p.lna2_power.into(),
+            analog_power: p.analog_power.into(),
+        }
+    }
+}
+
+impl From<v1::MonitorPayload> for MonitorPayload {
+    fn from(p: v1::MonitorPayload) -> Self {
+        MonitorPayload {
+            if1_power: p.if1_power,
+            if2_power: p.if2_power,
+            ic_temp: p.ic_temp,
+            lna1_power: p.lna1_power.into(),
+            lna2_power: p.lna2_power.into(),
+            analog_power: p.analog_power.into(),
+        }
+    }
+}
+
+impl From<Command> for v1::Command {
+    fn from(cmd: Command) -> Self {
+        match cmd {
+            Command::Monitor => v1::Command::Monitor,
+            Command::Control(action) => v1::Command::Control(action.into()),
+        }
+    }
+}
+
+impl TryFrom<v1::Command> for Command {
+    type Error = v1::ErrorCode;
+
+    /// Fails with [`v1::ErrorCode::Unsupported`] for commands 0.2 firmware doesn't have
+    fn try_from(cmd: v1::Command) -> Result<Self, v1::ErrorCode> {
+        match cmd {
+            v1::Command::Monitor => Ok(Command::Monitor),
+            v1::Command::Control(action) => Ok(Command::Control(action.into())),
+            _ => Err(v1::ErrorCode::Unsupported),
+        }
+    }
+}
+
+impl From<Response> for v1::Response {
+    fn from(resp: Response) -> Self {
+        match resp {
+            Response::Ack => v1::Response::Ack,
+            Response::Error => v1::Response::Error(v1::ErrorCode::Unknown),
+            Response::Monitor(payload) => v1::Response::Monitor(payload.into()),
+        }
+    }
+}
+
+impl TryFrom<v1::Response> for Response {
+    type Error = v1::ErrorCode;
+
+    /// Fails with [`v1::ErrorCode::Unsupported`] for responses 0.2 firmware doesn't have
+    fn try_from(resp: v1::Response) -> Result<Self, v1::ErrorCode> {
+        match resp {
+            v1::Response::Ack => Ok(Response::Ack),
+            v1::Response::Error(_) => Ok(Response::Error),
+            v1::Response::Monitor(payload) => Ok(Response::Monitor(payload.into())),
+            _ => Err(v1::ErrorCode::Unsupported),
+        }
+    }
+}
@@ -0,0 +1,849 @@
+//! Version 1 of the protocol, spoken since every message was wrapped in a sequence-numbered
+//! [`Frame`] protected by a CRC (see [`crate::wire`])
+//!
+//! New variants must only ever be added at the end of an enum: postcard encodes variants by
+//! index, so inserting one would change the meaning of every variant after it. The golden
+//! fixtures in `tests/fixtures` catch accidental changes to the encoding. Names don't matter
+//! to postcard, but they do to the JSON form of the messages (see `crate::schema`), so
+//! variants and fields aren't renamed either.
+
+use crate::wire;
+use heapless::{String, Vec};
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+pub use crate::units::{Amps, Attenuation, Dbm, OutOfRange, Volts};
+
+/// Version of the wire protocol spoken by this crate, reported by the FEM in [`Identity`]
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Sequence number of frames that don't belong to a request, never used by the MnC software
+pub const NO_SEQ: u16 = 0;
+
+/// Envelope around every [`Command`] and [`Response`] on the wire
+///
+/// The FEM echoes the sequence number of a command back in its response, so the MnC software
+/// can pair them up and the FEM can spot retries of the same command.
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Frame<T> {
+    /// Sequence number of the request
+    pub seq: u16,
+    /// The message itself
+    pub body: T,
+}
+
+impl<T> Frame<T> {
+    pub fn new(seq: u16, body: T) -> Self {
+        Self { seq, body }
+    }
+}
+
+/// Actions that can be performed
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Action {
+    /// Set the IF "Good" power threshold
+    SetIfLevel(Dbm),
+    /// Control the power state of the LNA1 regulator
+    Lna1Power(bool),
+    /// Control the power state of the LNA2 regulator
+    Lna2Power(bool),
+    // Set attenuation
+    SetAtten(Attenuation),
+}
+
+/// Maximum number of actions in a [`Command::Batch`]
+pub const MAX_BATCH: usize = 8;
+
+/// Outcome of a single action in a [`Command::Batch`]
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum ActionResult {
+    /// The action was applied
+    Applied,
+    /// The action was rejected, so the batch was not applied
+    Rejected(ErrorCode),
+    /// The action was not applied (or was undone) because another action was rejected
+    Skipped,
+}
+
+/// Monitor data sent in response to a [`Command::Monitor`] call
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Default, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MonitorPayload {
+    /// IF1 power
+    pub if1_power: Dbm,
+    /// IF2 power
+    pub if2_power: Dbm,
+    /// RP2040 internal temperature in C
+    pub ic_temp: f32,
+    /// Voltage and current of LNA1
+    pub lna1_power: Power,
+    /// Voltage and current of LNA2
+    pub lna2_power: Power,
+    /// Voltage and current of the analog rail
+    pub analog_power: Power,
+}
+
+impl MonitorPayload {
+    /// Telemetry of the selected fields of the payload
+    pub fn select(&self, fields: MonitorFields) -> TelemetryPayload {
+        let has = |field| fields.contains(field);
+        TelemetryPayload {
+            if1_power: has(MonitorFields::IF_POWER).then_some(self.if1_power),
+            if2_power: has(MonitorFields::IF_POWER).then_some(self.if2_power),
+            ic_temp: has(MonitorFields::IC_TEMP).then_some(self.ic_temp),
+            lna1_power: has(MonitorFields::LNA1_POWER).then(|| self.lna1_power.clone()),
+            lna2_power: has(MonitorFields::LNA2_POWER).then(|| self.lna2_power.clone()),
+            analog_power: has(MonitorFields::ANALOG_POWER).then(|| self.analog_power.clone()),
+        }
+    }
+}
+
+/// Monitor data pushed by the FEM during a subscription, with just the fields subscribed to
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Default, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TelemetryPayload {
+    /// IF1 power
+    pub if1_power: Option<Dbm>,
+    /// IF2 power
+    pub if2_power: Option<Dbm>,
+    /// RP2040 internal temperature in C
+    pub ic_temp: Option<f32>,
+    /// Voltage and current of LNA1
+    pub lna1_power: Option<Power>,
+    /// Voltage and current of LNA2
+    pub lna2_power: Option<Power>,
+    /// Voltage and current of the analog rail
+    pub analog_power: Option<Power>,
+}
+
+/// Set of [`MonitorPayload`] fields to send in a subscription, as a bit mask
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MonitorFields(pub u8);
+
+impl MonitorFields {
+    /// Both IF powers
+    pub const IF_POWER: Self = Self(1 << 0);
+    /// RP2040 internal temperature
+    pub const IC_TEMP: Self = Self(1 << 1);
+    /// Voltage and current of LNA1
+    pub const LNA1_POWER: Self = Self(1 << 2);
+    /// Voltage and current of LNA2
+    pub const LNA2_POWER: Self = Self(1 << 3);
+    /// Voltage and current of the analog rail
+    pub const ANALOG_POWER: Self = Self(1 << 4);
+    /// Every field
+    pub const ALL: Self = Self(0x1f);
+    /// No fields
+    pub const NONE: Self = Self(0);
+
+    /// Whether every field of `other` is in the set
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for MonitorFields {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Full scale of the RP2040's 12-bit ADC in counts
+pub const ADC_MAX_COUNTS: u16 = 4095;
+
+/// Monitor data with the raw readings behind it, sent in response to a
+/// [`Command::MonitorV2`] call
+///
+/// The converted fields are the same as in a [`MonitorPayload`], which can be made from this
+/// with `into()`. Readings that failed hold zero.
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Default, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MonitorPayloadV2 {
+    /// Time since boot when the sample was taken in milliseconds
+    pub uptime_ms: u64,
+    /// Number of the sample, counting up from boot (wrapping)
+    pub sample_seq: u32,
+    /// Readings that were measured and in range
+    pub valid: Validity,
+    /// IF1 power
+    pub if1_power: Dbm,
+    /// ADC counts behind `if1_power`
+    pub if1_counts: u16,
+    /// IF2 power
+    pub if2_power: Dbm,
+    /// ADC counts behind `if2_power`
+    pub if2_counts: u16,
+    /// RP2040 internal temperature in C
+    pub ic_temp: f32,
+    /// ADC counts behind `ic_temp`
+    pub ic_temp_counts: u16,
+    /// Voltage and current of LNA1
+    pub lna1_power: Power,
+    /// Voltage and current of LNA2
+    pub lna2_power: Power,
+    /// Voltage and current of the analog rail
+    pub analog_power: Power,
+}
+
+impl From<MonitorPayloadV2> for MonitorPayload {
+    fn from(p: MonitorPayloadV2) -> Self {
+        MonitorPayload {
+            if1_power: p.if1_power,
+            if2_power: p.if2_power,
+            ic_temp: p.ic_temp,
+            lna1_power: p.lna1_power,
+            lna2_power: p.lna2_power,
+            analog_power: p.analog_power,
+        }
+    }
+}
+
+/// Set of readings of a [`MonitorPayloadV2`] that are valid, as a bit mask
+///
+/// A reading is invalid if it failed, or for ADC readings if the ADC was saturated.
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Eq, Default, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Validity(pub u16);
+
+impl Validity {
+    pub const IF1_POWER: Self = Self(1 << 0);
+    pub const IF2_POWER: Self = Self(1 << 1);
+    pub const IC_TEMP: Self = Self(1 << 2);
+    pub const LNA1_VOLTAGE: Self = Self(1 << 3);
+    pub const LNA1_CURRENT: Self = Self(1 << 4);
+    pub const LNA2_VOLTAGE: Self = Self(1 << 5);
+    pub const LNA2_CURRENT: Self = Self(1 << 6);
+    pub const ANALOG_VOLTAGE: Self = Self(1 << 7);
+    pub const ANALOG_CURRENT: Self = Self(1 << 8);
+    /// Every reading
+    pub const ALL: Self = Self(0x1ff);
+    /// No readings
+    pub const NONE: Self = Self(0);
+
+    /// Whether every reading of `other` is in the set
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Add or remove the readings of `other` from the set
+    pub fn set(&mut self, other: Self, valid: bool) {
+        if valid {
+            self.0 |= other.0;
+        } else {
+            self.0 &= !other.0;
+        }
+    }
+}
+
+impl core::ops::BitOr for Validity {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Shortest interval between telemetry frames of a [`Command::Subscribe`] in milliseconds
+pub const MIN_SUBSCRIBE_INTERVAL_MS: u32 = 10;
+
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Default, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Power {
+    pub voltage: Volts,
+    pub current: Amps,
+}
+
+/// Control state of the FEM, sent in response to a [`Command::GetState`] call
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Default, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ControlState {
+    /// Power state of the LNA1 regulator
+    pub lna1_enabled: bool,
+    /// Power state of the LNA2 regulator
+    pub lna2_enabled: bool,
+    /// Attenuation of channel 1, as latched in the attenuator
+    pub atten1: Attenuation,
+    /// Attenuation of channel 2, as latched in the attenuator
+    pub atten2: Attenuation,
+    /// IF "Good" power threshold
+    pub if_good_threshold: Dbm,
+    /// State of the channel 1 calibration tone, `None` if the board doesn't have one
+    pub cal1: Option<bool>,
+    /// State of the channel 2 calibration tone, `None` if the board doesn't have one
+    pub cal2: Option<bool>,
+    /// Time since boot in milliseconds
+    pub uptime_ms: u64,
+}
+
+/// Identifying information of the FEM, sent in response to a [`Command::Identify`] call
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Default, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Identity {
+    /// Wire protocol version (see [`PROTOCOL_VERSION`])
+    pub protocol_version: u16,
+    /// Firmware crate version
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "std::string::String", length(max = 16))
+    )]
+    pub firmware_version: String<16>,
+    /// Git hash the firmware was built from
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "std::string::String", length(max = 20))
+    )]
+    pub git_hash: String<20>,
+    /// Build time of the firmware in seconds since the unix epoch
+    pub build_timestamp: u64,
+    /// Unique ID of the RP2040's flash chip
+    pub serial: u64,
+}
+
+/// What a FEM can do, sent in response to a [`Command::Capabilities`] call
+///
+/// Boards differ in what's fitted, so the MnC software should go by this rather than assume.
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Capabilities {
+    /// Actions the FEM can perform
+    pub actions: Actions,
+    /// Number of IF channels
+    pub channels: u8,
+    /// Lowest attenuation in dB
+    pub atten_min: f32,
+    /// Highest attenuation in dB
+    pub atten_max: f32,
+    /// Step of the attenuators in dB, other settings are rounded to the nearest step
+    pub atten_step: f32,
+    /// Sensors fitted to the board
+    pub sensors: Sensors,
+    /// Optional features of the board
+    pub features: Features,
+}
+
+impl Capabilities {
+    /// Whether an attenuation in dB is in range and a whole number of steps
+    pub fn atten_valid(&self, atten: f32) -> bool {
+        if !(self.atten_min..=self.atten_max).contains(&atten) {
+            return false;
+        }
+        // Leave some slack for settings like 0.1 that floats can't represent
+        let steps = (atten - self.atten_min) / self.atten_step;
+        let frac = steps - steps as u32 as f32;
+        !(1e-3..=1.0 - 1e-3).contains(&frac)
+    }
+}
+
+/// Set of [`Action`]s, as a bit mask
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Actions(pub u8);
+
+impl Actions {
+    /// [`Action::SetIfLevel`]
+    pub const SET_IF_LEVEL: Self = Self(1 << 0);
+    /// [`Action::Lna1Power`]
+    pub const LNA1_POWER: Self = Self(1 << 1);
+    /// [`Action::Lna2Power`]
+    pub const LNA2_POWER: Self = Self(1 << 2);
+    /// [`Action::SetAtten`]
+    pub const SET_ATTEN: Self = Self(1 << 3);
+    /// Every action
+    pub const ALL: Self = Self(0xf);
+    /// No actions
+    pub const NONE: Self = Self(0);
+
+    /// Whether every action of `other` is in the set
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl From<&Action> for Actions {
+    fn from(action: &Action) -> Self {
+        match action {
+            Action::SetIfLevel(_) => Self::SET_IF_LEVEL,
+            Action::Lna1Power(_) => Self::LNA1_POWER,
+            Action::Lna2Power(_) => Self::LNA2_POWER,
+            Action::SetAtten(_) => Self::SET_ATTEN,
+        }
+    }
+}
+
+impl core::ops::BitOr for Actions {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Set of sensors fitted to a FEM, as a bit mask
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Sensors(pub u8);
+
+impl Sensors {
+    /// Log detectors measuring the IF powers
+    pub const IF_POWER: Self = Self(1 << 0);
+    /// RP2040 internal temperature
+    pub const IC_TEMP: Self = Self(1 << 1);
+    /// INA3221 measuring the voltages and currents of the rails
+    pub const RAIL_POWER: Self = Self(1 << 2);
+    /// TMP100 measuring the board temperature
+    pub const BOARD_TEMP: Self = Self(1 << 3);
+    /// No sensors
+    pub const NONE: Self = Self(0);
+
+    /// Whether every sensor of `other` is in the set
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for Sensors {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Set of optional features of a FEM, as a bit mask
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Features(pub u16);
+
+impl Features {
+    /// Calibration tones injected into both channels
+    pub const CAL_TONE: Self = Self(1 << 0);
+    /// No features
+    pub const NONE: Self = Self(0);
+
+    /// Whether every feature of `other` is in the set
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for Features {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Identifier of a parameter of the FEM, for [`Command::Get`] and [`Command::Set`]
+///
+/// The well-known parameters have constants here, but a FEM lists the ones it actually has
+/// (along with their names and limits) in response to [`Command::ListParams`].
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ParamId(pub u8);
+
+impl ParamId {
+    /// Power state of the LNA1 regulator, same as [`Action::Lna1Power`]
+    pub const LNA1_POWER: Self = Self(0);
+    /// Power state of the LNA2 regulator, same as [`Action::Lna2Power`]
+    pub const LNA2_POWER: Self = Self(1);
+    /// Attenuation in dB, same as [`Action::SetAtten`]
+    pub const ATTEN: Self = Self(2);
+    /// IF "Good" power threshold in dBm, same as [`Action::SetIfLevel`]
+    pub const IF_THRESHOLD: Self = Self(3);
+    /// Bus address, same as [`Command::SetAddress`]
+    pub const ADDRESS: Self = Self(4);
+    /// Number of incoming frames dropped due to a bad checksum
+    pub const CRC_ERRORS: Self = Self(5);
+}
+
+/// Value of a parameter
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Value {
+    Bool(bool),
+    F32(f32),
+    U32(u32),
+}
+
+impl Value {
+    /// What kind of value this is
+    pub fn kind(&self) -> ValueKind {
+        match self {
+            Value::Bool(_) => ValueKind::Bool,
+            Value::F32(_) => ValueKind::F32,
+            Value::U32(_) => ValueKind::U32,
+        }
+    }
+}
+
+impl core::fmt::Display for Value {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Value::Bool(true) => write!(f, "on"),
+            Value::Bool(false) => write!(f, "off"),
+            Value::F32(x) => write!(f, "{x}"),
+            Value::U32(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+/// Kinds of [`Value`] a parameter can hold
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum ValueKind {
+    Bool,
+    F32,
+    U32,
+}
+
+/// Maximum number of parameters in a [`Response::Params`], so the list fits in one frame
+pub const MAX_PARAMS: usize = 6;
+
+/// Description of a parameter, sent in response to a [`Command::ListParams`] call
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ParamInfo {
+    /// Identifier to get or set the parameter by
+    pub id: ParamId,
+    /// Name of the parameter, in `snake_case`
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "std::string::String", length(max = 16))
+    )]
+    pub name: String<16>,
+    /// Unit of the value, empty if it has none
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "std::string::String", length(max = 4))
+    )]
+    pub unit: String<4>,
+    /// Kind of value the parameter holds
+    pub kind: ValueKind,
+    /// Lowest value that can be set, ignored for [`ValueKind::Bool`]
+    pub min: f32,
+    /// Highest value that can be set, ignored for [`ValueKind::Bool`]
+    pub max: f32,
+    /// Whether the parameter can only be read
+    pub read_only: bool,
+    /// Whether the parameter is kept across power cycles
+    pub persisted: bool,
+}
+
+impl ParamInfo {
+    /// Check that a value can be set, like the FEM would
+    pub fn check(&self, value: &Value) -> Result<(), ErrorCode> {
+        if self.read_only {
+            return Err(ErrorCode::ReadOnly);
+        }
+        let x = match *value {
+            _ if value.kind() != self.kind => return Err(ErrorCode::WrongType),
+            Value::Bool(_) => return Ok(()),
+            Value::F32(x) => x,
+            Value::U32(x) => x as f32,
+        };
+        if (self.min..=self.max).contains(&x) {
+            Ok(())
+        } else {
+            Err(ErrorCode::OutOfRange {
+                min: self.min,
+                max: self.max,
+            })
+        }
+    }
+}
+
+impl Action {
+    /// The parameter the action sets, and the value it sets it to
+    pub fn param(&self) -> (ParamId, Value) {
+        match *self {
+            Action::SetIfLevel(level) => (ParamId::IF_THRESHOLD, Value::F32(level.into())),
+            Action::Lna1Power(en) => (ParamId::LNA1_POWER, Value::Bool(en)),
+            Action::Lna2Power(en) => (ParamId::LNA2_POWER, Value::Bool(en)),
+            Action::SetAtten(atten) => (ParamId::ATTEN, Value::F32(atten.into())),
+        }
+    }
+
+    /// The action that sets a parameter, if there is one
+    ///
+    /// Fails if the value is out of range for the action.
+    pub fn from_param(id: ParamId, value: Value) -> Result<Option<Self>, ErrorCode> {
+        let action = match (id, value) {
+            (ParamId::IF_THRESHOLD, Value::F32(level)) => Action::SetIfLevel(level.try_into()?),
+            (ParamId::LNA1_POWER, Value::Bool(en)) => Action::Lna1Power(en),
+            (ParamId::LNA2_POWER, Value::Bool(en)) => Action::Lna2Power(en),
+            (ParamId::ATTEN, Value::F32(atten)) => Action::SetAtten(atten.try_into()?),
+            _ => return Ok(None),
+        };
+        Ok(Some(action))
+    }
+}
+
+/// Largest number of bytes in a [`Chunk`], so a chunk fits in one frame
+pub const CHUNK_SIZE: usize = 128;
+
+/// Something on the FEM too large for a frame, moved in [`Chunk`]s (see [`crate::bulk`])
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ObjectId(pub u8);
+
+impl ObjectId {
+    /// Calibration table of the board, kept by the FEM for the MnC software
+    pub const CAL_TABLE: Self = Self(0);
+}
+
+/// Piece of an object in a bulk transfer
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Chunk {
+    /// Where the data starts in the object, in bytes
+    pub offset: u32,
+    /// The data itself, empty past the end of the object
+    #[cfg_attr(feature = "schema", schemars(with = "std::vec::Vec<u8>", length(max = CHUNK_SIZE)))]
+    pub data: Vec<u8, CHUNK_SIZE>,
+    /// CRC of the offset and data (see [`Chunk::new`])
+    pub crc: u16,
+}
+
+/// Something that happened on the FEM, sent unsolicited in a [`Response::Event`]
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Event {
+    /// Time since boot in milliseconds
+    pub uptime_ms: u64,
+    /// What happened
+    pub kind: EventKind,
+}
+
+/// Changes of condition reported by the FEM
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum EventKind {
+    /// IF power of a channel (1 or 2) dropped below the "Good" threshold
+    IfLost { channel: u8 },
+    /// IF power of a channel (1 or 2) is back above the "Good" threshold
+    IfRestored { channel: u8 },
+    /// Current drawn from a rail went over its limit
+    OverCurrent { rail: Rail, amps: Amps },
+    /// Current drawn from a rail is back under its limit
+    CurrentRestored { rail: Rail, amps: Amps },
+    /// RP2040 temperature in C went over its limit
+    OverTemp { celsius: f32 },
+    /// RP2040 temperature in C is back under its limit
+    TempRestored { celsius: f32 },
+    /// The FEM started up, sent once after boot
+    Rebooted { reason: ResetReason },
+}
+
+/// Power rails monitored by the FEM
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Rail {
+    Lna1,
+    Lna2,
+    Analog,
+}
+
+/// Why the FEM last started up
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum ResetReason {
+    /// Power was applied, or the reset pin was asserted
+    PowerOn,
+    /// The watchdog timed out
+    Watchdog,
+    /// The firmware asked to be reset
+    Software,
+}
+
+/// Reasons the FEM could not carry out a command
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum ErrorCode {
+    /// The incoming command could not be deserialized
+    Deserialize,
+    /// A value was outside of the supported (inclusive) range
+    OutOfRange { min: f32, max: f32 },
+    /// Communication with an SPI peripheral failed
+    SpiFault,
+    /// Communication with an I2C peripheral failed
+    I2cFault,
+    /// The command is not supported by this FEM
+    Unsupported,
+    /// The FEM can't handle the command right now
+    Busy,
+    /// The command was corrupted on the way to the FEM
+    Checksum,
+    /// The FEM failed without saying why, only reported by 0.2 firmware
+    Unknown,
+    /// The parameter can't be set
+    ReadOnly,
+    /// The value is the wrong kind for the parameter
+    WrongType,
+    /// A chunk arrived without an upload underway
+    NotStarted,
+}
+
+impl core::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ErrorCode::Deserialize => write!(f, "the FEM could not deserialize the command"),
+            ErrorCode::OutOfRange { min, max } => {
+                write!(f, "value out of range, must be between {min} and {max}")
+            }
+            ErrorCode::SpiFault => write!(f, "SPI communication failed on the FEM"),
+            ErrorCode::I2cFault => write!(f, "I2C communication failed on the FEM"),
+            ErrorCode::Unsupported => write!(f, "command not supported by the FEM"),
+            ErrorCode::Busy => write!(f, "the FEM is busy"),
+            ErrorCode::Checksum => write!(f, "the command was corrupted on the way to the FEM"),
+            ErrorCode::Unknown => write!(f, "the FEM reported an unknown error"),
+            ErrorCode::ReadOnly => write!(f, "the parameter is read-only"),
+            ErrorCode::WrongType => write!(f, "wrong kind of value for the parameter"),
+            ErrorCode::NotStarted => write!(f, "no upload underway on the FEM"),
+        }
+    }
+}
+
+impl From<wire::Error> for ErrorCode {
+    fn from(e: wire::Error) -> Self {
+        match e {
+            wire::Error::Checksum => ErrorCode::Checksum,
+            _ => ErrorCode::Deserialize,
+        }
+    }
+}
+
+/// Payloads from MnC software to the FEM
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Command {
+    Monitor,
+    Control(Action),
+    /// Request the [`Identity`] of the FEM
+    Identify,
+    /// Apply all of the actions or none of them
+    Batch(
+        #[cfg_attr(feature = "schema", schemars(with = "std::vec::Vec<Action>", length(max = MAX_BATCH)))]
+         Vec<Action, MAX_BATCH>,
+    ),
+    /// Request the [`ControlState`] of the FEM
+    GetState,
+    /// Have the FEM send [`Response::Telemetry`] frames with [`NO_SEQ`] every `interval_ms`
+    ///
+    /// Only the selected fields are filled in. Replaces any previous subscription.
+    Subscribe {
+        interval_ms: u32,
+        fields: MonitorFields,
+    },
+    /// Stop the telemetry frames started by [`Command::Subscribe`]
+    Unsubscribe,
+    /// Request the latest [`MonitorPayloadV2`]
+    MonitorV2,
+    /// Give the FEM a new bus address (see [`crate::v2::Frame`]), kept across power cycles
+    ///
+    /// The response still comes from the old address.
+    SetAddress(u8),
+    /// Request the [`Capabilities`] of the FEM
+    Capabilities,
+    /// Request the value of a parameter
+    Get(ParamId),
+    /// Set the value of a parameter
+    Set(ParamId, Value),
+    /// Request the [`ParamInfo`] of every parameter of the FEM
+    ListParams,
+    /// Start uploading `len` bytes of an object with a CRC of `crc` (see
+    /// [`crate::bulk::OBJECT_CRC`])
+    ///
+    /// If the same upload is already underway, it's resumed rather than started over.
+    UploadStart {
+        object: ObjectId,
+        len: u32,
+        crc: u32,
+    },
+    /// Next piece of the object being uploaded
+    ///
+    /// Chunks that don't start where the last one ended are ignored. Once the last chunk is
+    /// in, the FEM checks the object against its CRC and keeps it.
+    UploadChunk(Chunk),
+    /// Request the chunk of an object starting at `offset`
+    Download {
+        object: ObjectId,
+        offset: u32,
+    },
+}
+
+/// Payloads from FEM to MnC software
+// There's no allocator on the FEM to box the parameter list with
+#[allow(clippy::large_enum_variant)]
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Response {
+    /// Previous command was ok, but didn't need a response
+    Ack,
+    /// Previous command failed
+    Error(ErrorCode),
+    /// Response to monitor request
+    Monitor(MonitorPayload),
+    /// Response to identify request
+    Identity(Identity),
+    /// Response to batch request, with the outcome of every action in order
+    Batch(
+        #[cfg_attr(feature = "schema", schemars(with = "std::vec::Vec<ActionResult>", length(max = MAX_BATCH)))]
+         Vec<ActionResult, MAX_BATCH>,
+    ),
+    /// Response to get state request
+    State(ControlState),
+    /// Something happened on the FEM, always sent with [`NO_SEQ`]
+    Event(Event),
+    /// Response to monitor v2 request
+    MonitorV2(MonitorPayloadV2),
+    /// Response to capabilities request
+    Capabilities(Capabilities),
+    /// Response to get request
+    Value(Value),
+    /// Response to list params request
+    Params(
+        #[cfg_attr(feature = "schema", schemars(with = "std::vec::Vec<ParamInfo>", length(max = MAX_PARAMS)))]
+         Vec<ParamInfo, MAX_PARAMS>,
+    ),
+    /// Response to upload requests, with the number of bytes the FEM has so far
+    UploadProgress { received: u32 },
+    /// Response to download request, with the length of the whole object
+    Chunk { len: u32, chunk: Chunk },
+    /// Telemetry of a subscription, always sent with [`NO_SEQ`]
+    Telemetry(TelemetryPayload),
+}
@@ -0,0 +1,160 @@
+//! Version 2 of the protocol, which added the address of the FEM to every [`Frame`] so several
+//! FEMs can share one RS-485 bus
+//!
+//! The messages themselves are the ones of [`crate::v1`]. Frames start with [`MAGIC`], so
+//! firmware of an earlier version can't read them as one of its own commands.
+
+use crate::wire;
+use postcard::experimental::max_size::MaxSize;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+pub use crate::v1::*;
+
+/// Version of the wire protocol spoken by this crate, reported by the FEM in [`Identity`]
+pub const PROTOCOL_VERSION: u16 = 2;
+
+/// Address every FEM on the bus acts on, without responding
+pub const BROADCAST: u8 = 0;
+
+/// Address of a FEM that was never given one
+pub const DEFAULT_ADDR: u8 = 1;
+
+/// Highest address a FEM can be given
+///
+/// The erased state of the flash the address is kept in reads as `0xff`, so that's left out.
+pub const MAX_ADDR: u8 = 0xfe;
+
+/// Whether a FEM can be given `addr` with [`Command::SetAddress`]
+pub const fn is_valid_addr(addr: u8) -> bool {
+    addr != BROADCAST && addr <= MAX_ADDR
+}
+
+/// Longest frame of a [`Command`] on the wire, zero delimiter included
+pub const MAX_COMMAND_FRAME_LEN: usize = wire::max_frame_len(Frame::<Command>::POSTCARD_MAX_SIZE);
+
+/// Longest frame of a [`Response`] on the wire, zero delimiter included
+pub const MAX_RESPONSE_FRAME_LEN: usize = wire::max_frame_len(Frame::<Response>::POSTCARD_MAX_SIZE);
+
+/// Longest frame on the wire either way
+pub const MAX_FRAME_LEN: usize = if MAX_COMMAND_FRAME_LEN > MAX_RESPONSE_FRAME_LEN {
+    MAX_COMMAND_FRAME_LEN
+} else {
+    MAX_RESPONSE_FRAME_LEN
+};
+
+// Boards and MnC software already in the field drop frames longer than 256 bytes, so a
+// message that could grow past that needs splitting up (see `crate::bulk`)
+const _: () = assert!(
+    MAX_FRAME_LEN <= 256,
+    "frames no longer fit the fielded buffers"
+);
+
+/// Bytes every [`Frame`] starts with, ahead of its sequence number
+///
+/// v1 firmware reads them as a sequence number too long for a `u16`, and 0.2 firmware as the
+/// index of a command far past its last, so both reject a v2 frame rather than carry out the
+/// wrong command. The last byte is [`PROTOCOL_VERSION`] with its top bit set.
+pub const MAGIC: [u8; 3] = [0xff, 0xff, 0x80 | PROTOCOL_VERSION as u8];
+
+/// [`MAGIC`] on the wire, and just [`PROTOCOL_VERSION`] in the JSON form of a frame
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+struct Magic;
+
+impl Serialize for Magic {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_u16(PROTOCOL_VERSION)
+        } else {
+            MAGIC.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Magic {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let matches = if deserializer.is_human_readable() {
+            u16::deserialize(deserializer)? == PROTOCOL_VERSION
+        } else {
+            <[u8; MAGIC.len()]>::deserialize(deserializer)? == MAGIC
+        };
+        if matches {
+            Ok(Magic)
+        } else {
+            Err(de::Error::custom("not a v2 frame"))
+        }
+    }
+}
+
+impl MaxSize for Magic {
+    const POSTCARD_MAX_SIZE: usize = MAGIC.len();
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Magic {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "Version".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "description": "Version of the protocol the frame is in",
+            "const": PROTOCOL_VERSION,
+        })
+    }
+}
+
+/// Envelope around every [`Command`] and [`Response`] on the wire
+///
+/// The FEM echoes the sequence number of a command back in its response, so the MnC software
+/// can pair them up and the FEM can spot retries of the same command. Commands carry the
+/// address of the FEM they're for, and responses the address of the FEM they came from.
+#[derive(Serialize, Deserialize, MaxSize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(rename = "{T}Frame")
+)]
+pub struct Frame<T> {
+    /// Tells the frame apart from those of earlier versions
+    version: Magic,
+    /// Sequence number of the request
+    pub seq: u16,
+    /// Address of the FEM
+    pub addr: u8,
+    /// The message itself
+    pub body: T,
+}
+
+impl<T> Frame<T> {
+    /// Frame a message to or from a FEM at [`DEFAULT_ADDR`]
+    pub fn new(seq: u16, body: T) -> Self {
+        Self {
+            version: Magic,
+            seq,
+            addr: DEFAULT_ADDR,
+            body,
+        }
+    }
+
+    /// Set the address of the frame
+    pub fn with_addr(mut self, addr: u8) -> Self {
+        self.addr = addr;
+        self
+    }
+}
+
+/// The start of a [`Frame`], which can be read even when the message after it can't
+///
+/// Once the CRC of a frame has checked out, this says who to tell that its message couldn't be
+/// read, like a command that's newer than the firmware.
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+pub struct Header {
+    version: Magic,
+    /// Sequence number of the request
+    pub seq: u16,
+    /// Address of the FEM
+    pub addr: u8,
+}
@@ -0,0 +1,186 @@
+//! Framing of messages on the UART link
+//!
+//! Every message is serialized with postcard, followed by a little-endian CRC-16 of the
+//! serialized bytes, then COBS encoded and terminated with a zero byte. COBS alone only
+//! delimits frames, the CRC catches bit flips that would otherwise still deserialize.
+
+use crc::{Crc, CRC_16_IBM_SDLC};
+use postcard::{
+    ser_flavors::{crc::CrcModifier, Cobs, Slice},
+    serialize_with_flavor,
+};
+use serde::{Deserialize, Serialize};
+
+/// CRC protecting every frame
+pub const CRC: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_SDLC);
+
+/// Number of bytes of the CRC trailer
+pub const CRC_LEN: usize = 2;
+
+/// Longest frame, zero delimiter included, of a message of up to `max_size` serialized bytes
+///
+/// Use it with the [`MaxSize`](postcard::experimental::max_size::MaxSize) of a message to
+/// size buffers at compile time.
+pub const fn max_frame_len(max_size: usize) -> usize {
+    cobs::max_encoding_length(max_size + CRC_LEN) + 1
+}
+
+/// Reasons a frame could not be encoded or decoded
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "use-defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The buffer was too small to hold the frame
+    BufferFull,
+    /// The frame was not valid COBS
+    Cobs,
+    /// The CRC didn't match the contents of the frame
+    Checksum,
+    /// The contents of the frame could not be deserialized
+    Deserialize,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::BufferFull => write!(f, "frame too large for the buffer"),
+            Error::Cobs => write!(f, "malformed COBS frame"),
+            Error::Checksum => write!(f, "bad frame checksum"),
+            Error::Deserialize => write!(f, "frame contents could not be deserialized"),
+        }
+    }
+}
+
+/// Serialize a message into `buf` as a complete frame, including the zero delimiter
+pub fn encode<'a, T>(msg: &T, buf: &'a mut [u8]) -> Result<&'a mut [u8], Error>
+where
+    T: Serialize + ?Sized,
+{
+    let cobs = Cobs::try_new(Slice::new(buf)).map_err(|_| Error::BufferFull)?;
+    serialize_with_flavor(msg, CrcModifier::new(cobs, CRC.digest())).map_err(|_| Error::BufferFull)
+}
+
+/// Decode a single frame (without the zero delimiter) in place and deserialize its contents
+pub fn decode<'a, T>(frame: &'a mut [u8]) -> Result<T, Error>
+where
+    T: Deserialize<'a>,
+{
+    deserialize(payload(frame)?)
+}
+
+/// Decode a single frame (without the zero delimiter) in place and check its CRC, giving the
+/// serialized contents
+pub fn payload(frame: &mut [u8]) -> Result<&[u8], Error> {
+    let n = cobs::decode_in_place(frame).map_err(|_| Error::Cobs)?;
+    if n < CRC_LEN {
+        return Err(Error::Cobs);
+    }
+    // Check the CRC before trying to make sense of the contents
+    let (payload, crc) = frame[..n].split_at(n - CRC_LEN);
+    if CRC.checksum(payload).to_le_bytes() != crc {
+        return Err(Error::Checksum);
+    }
+    Ok(payload)
+}
+
+/// Deserialize the contents of a frame, as given by [`payload`]
+pub fn deserialize<'a, T>(payload: &'a [u8]) -> Result<T, Error>
+where
+    T: Deserialize<'a>,
+{
+    postcard::from_bytes(payload).map_err(|_| Error::Deserialize)
+}
+
+/// Whether a frame (without the zero delimiter) is valid COBS with a CRC matching its contents,
+/// checked without decoding it
+pub fn is_intact(frame: &[u8]) -> bool {
+    let mut digest = CRC.digest();
+    // The last bytes decoded are the CRC, so every byte is held back until there are more
+    let mut held = [0u8; CRC_LEN];
+    let mut len = 0;
+    let mut i = 0;
+    while i < frame.len() {
+        let code = frame[i] as usize;
+        if code == 0 || i + code > frame.len() {
+            return false;
+        }
+        // Every block but full ones and the last ends in a zero
+        let zero = (code < 0xff && i + code < frame.len()).then_some(0);
+        for byte in frame[i + 1..i + code].iter().copied().chain(zero) {
+            if len >= CRC_LEN {
+                digest.update(&held[..1]);
+            }
+            held.rotate_left(1);
+            held[CRC_LEN - 1] = byte;
+            len += 1;
+        }
+        i += code;
+    }
+    len >= CRC_LEN && digest.finalize().to_le_bytes() == held
+}
+
+/// Collects bytes off the wire until a whole frame has arrived
+pub struct Accumulator<const N: usize> {
+    buf: [u8; N],
+    idx: usize,
+    overfull: bool,
+}
+
+/// The result of feeding bytes to an [`Accumulator`]
+pub enum FeedResult<'a, 'b> {
+    /// All of the input was consumed without completing a frame
+    Consumed,
+    /// A frame was too large for the accumulator and was dropped
+    OverFull(&'b [u8]),
+    /// A whole frame (without the zero delimiter) is ready to be decoded
+    Frame {
+        frame: &'a mut [u8],
+        remaining: &'b [u8],
+    },
+}
+
+impl<const N: usize> Default for Accumulator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Accumulator<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            idx: 0,
+            overfull: false,
+        }
+    }
+
+    /// Feed bytes into the accumulator, stopping at the end of the first complete frame
+    pub fn feed<'a, 'b>(&'a mut self, input: &'b [u8]) -> FeedResult<'a, 'b> {
+        for (i, &byte) in input.iter().enumerate() {
+            let remaining = &input[i + 1..];
+            if byte == 0 {
+                let len = self.idx;
+                let overfull = self.overfull;
+                self.idx = 0;
+                self.overfull = false;
+                if overfull {
+                    return FeedResult::OverFull(remaining);
+                }
+                // Back-to-back delimiters are just idle line
+                if len == 0 {
+                    continue;
+                }
+                return FeedResult::Frame {
+                    frame: &mut self.buf[..len],
+                    remaining,
+                };
+            }
+            if self.idx < N {
+                self.buf[self.idx] = byte;
+                self.idx += 1;
+            } else {
+                self.overfull = true;
+            }
+        }
+        FeedResult::Consumed
+    }
+}
@@ -0,0 +1,214 @@
+//! Bulk transfers through the blocking client, against a FEM that lives in the test
+
+use std::io::{self, Read, Write};
+use transport::{
+    bulk::OBJECT_CRC,
+    codec::{FrameDecoder, FrameEncoder},
+    io::{BlockingClient, Error},
+    Chunk, Command, ErrorCode, Frame, ObjectId, Response, CHUNK_SIZE,
+};
+
+/// Upload underway on the FEM
+struct Upload {
+    len: u32,
+    crc: u32,
+    data: Vec<u8>,
+}
+
+/// FEM keeping a calibration table in memory, with ways to make the link misbehave
+#[derive(Default)]
+struct FakeFem {
+    decoder: FrameDecoder<Frame<Command>, 256>,
+    encoder: FrameEncoder<256>,
+    outbox: Vec<u8>,
+    stored: Vec<u8>,
+    upload: Option<Upload>,
+    /// Number of chunks that made it to the FEM, good or bad
+    chunks: usize,
+    /// Chunks (by count) to flip a bit of on the way in or out
+    corrupt: Vec<usize>,
+    /// Chunks (by count) to lose the response to
+    lose: Vec<usize>,
+    /// Chunks (by count) after which the link goes down
+    unplug_after: Option<usize>,
+}
+
+impl FakeFem {
+    fn handle(&mut self, cmd: Command) -> Response {
+        match cmd {
+            Command::UploadStart { object, len, crc } => {
+                assert_eq!(object, ObjectId::CAL_TABLE);
+                match &self.upload {
+                    Some(up) if up.len == len && up.crc == crc => (),
+                    _ => {
+                        self.upload = Some(Upload {
+                            len,
+                            crc,
+                            data: vec![],
+                        })
+                    }
+                }
+                self.progress()
+            }
+            Command::UploadChunk(mut chunk) => {
+                self.chunks += 1;
+                if self.corrupt.contains(&self.chunks) {
+                    chunk.data[0] ^= 1;
+                }
+                let Some(up) = &mut self.upload else {
+                    return Response::Error(ErrorCode::NotStarted);
+                };
+                if !chunk.is_intact() {
+                    return Response::Error(ErrorCode::Checksum);
+                }
+                if chunk.offset as usize == up.data.len() {
+                    up.data.extend_from_slice(&chunk.data);
+                    if up.data.len() as u32 == up.len {
+                        assert_eq!(OBJECT_CRC.checksum(&up.data), up.crc);
+                        self.stored = up.data.clone();
+                    }
+                }
+                self.progress()
+            }
+            Command::Download { object, offset } => {
+                assert_eq!(object, ObjectId::CAL_TABLE);
+                self.chunks += 1;
+                let mut chunk = Chunk::new(offset, &self.stored[offset as usize..]);
+                if self.corrupt.contains(&self.chunks) {
+                    chunk.data[0] ^= 1;
+                }
+                Response::Chunk {
+                    len: self.stored.len() as u32,
+                    chunk,
+                }
+            }
+            _ => Response::Error(ErrorCode::Unsupported),
+        }
+    }
+
+    fn progress(&self) -> Response {
+        Response::UploadProgress {
+            received: self.upload.as_ref().map_or(0, |up| up.data.len() as u32),
+        }
+    }
+}
+
+impl Write for FakeFem {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.unplug_after.is_some_and(|n| self.chunks >= n) {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        let frames: Vec<_> = self.decoder.feed(buf).map(Result::unwrap).collect();
+        for frame in frames {
+            let resp = self.handle(frame.body);
+            if !self.lose.contains(&self.chunks) {
+                let bytes = self.encoder.encode(&Frame::new(frame.seq, resp)).unwrap();
+                self.outbox.extend_from_slice(bytes);
+            } else {
+                self.lose.retain(|&n| n != self.chunks);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for FakeFem {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.outbox.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let n = buf.len().min(self.outbox.len());
+        buf[..n].copy_from_slice(&self.outbox[..n]);
+        self.outbox.drain(..n);
+        Ok(n)
+    }
+}
+
+/// Something that doesn't split evenly into chunks
+fn table() -> Vec<u8> {
+    (0..1000u32).map(|i| (i * 7) as u8).collect()
+}
+
+#[test]
+fn upload_then_download() {
+    let mut client = BlockingClient::new(FakeFem::default());
+    client.upload(ObjectId::CAL_TABLE, &table()).unwrap();
+    assert_eq!(client.download(ObjectId::CAL_TABLE).unwrap(), table());
+
+    let chunks = table().len().div_ceil(CHUNK_SIZE);
+    assert_eq!(client.into_inner().chunks, 2 * chunks);
+}
+
+#[test]
+fn empty_objects() {
+    let mut client = BlockingClient::new(FakeFem::default());
+    assert!(client.download(ObjectId::CAL_TABLE).unwrap().is_empty());
+    client.upload(ObjectId::CAL_TABLE, &[]).unwrap();
+    assert_eq!(client.into_inner().chunks, 1);
+}
+
+#[test]
+fn interrupted_uploads_resume() {
+    let fem = FakeFem {
+        unplug_after: Some(3),
+        ..Default::default()
+    };
+    let mut client = BlockingClient::new(fem);
+    assert!(matches!(
+        client.upload(ObjectId::CAL_TABLE, &table()),
+        Err(Error::Io(_))
+    ));
+
+    let mut fem = client.into_inner();
+    fem.unplug_after = None;
+    let mut client = BlockingClient::new(fem);
+    client.upload(ObjectId::CAL_TABLE, &table()).unwrap();
+    // Nothing was sent twice
+    let fem = client.into_inner();
+    assert_eq!(fem.chunks, table().len().div_ceil(CHUNK_SIZE));
+    assert_eq!(fem.stored, table());
+}
+
+#[test]
+fn uploads_survive_a_bad_link() {
+    let fem = FakeFem {
+        corrupt: vec![2],
+        lose: vec![4],
+        ..Default::default()
+    };
+    let mut client = BlockingClient::new(fem);
+    client.upload(ObjectId::CAL_TABLE, &table()).unwrap();
+    let fem = client.into_inner();
+    assert_eq!(fem.stored, table());
+    // The corrupt chunk and the one with the lost response went twice
+    assert_eq!(fem.chunks, table().len().div_ceil(CHUNK_SIZE) + 2);
+}
+
+#[test]
+fn downloads_survive_a_bad_link() {
+    let fem = FakeFem {
+        stored: table(),
+        corrupt: vec![3],
+        lose: vec![5],
+        ..Default::default()
+    };
+    let mut client = BlockingClient::new(fem);
+    assert_eq!(client.download(ObjectId::CAL_TABLE).unwrap(), table());
+}
+
+#[test]
+fn uploads_give_up_on_a_hopeless_link() {
+    let fem = FakeFem {
+        corrupt: (1..100).collect(),
+        ..Default::default()
+    };
+    let mut client = BlockingClient::new(fem);
+    assert!(matches!(
+        client.upload(ObjectId::CAL_TABLE, &table()),
+        Err(Error::Rejected(ErrorCode::Checksum))
+    ));
+}
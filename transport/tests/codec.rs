@@ -0,0 +1,428 @@
+use transport::{
+    client::{Client, Stats},
+    codec::{Error, FrameDecoder, FrameEncoder},
+    Action, Attenuation, Command, ErrorCode, Event, EventKind, Frame, Header, MonitorPayload,
+    Response, TelemetryPayload, BROADCAST, NO_SEQ,
+};
+
+type CommandDecoder = FrameDecoder<Frame<Command>, 256>;
+
+fn encode<T: serde::Serialize>(msg: &T) -> Vec<u8> {
+    FrameEncoder::<256>::new().encode(msg).unwrap().to_vec()
+}
+
+#[test]
+fn roundtrip() {
+    let frame = Frame::new(
+        42,
+        Command::Control(Action::SetAtten(Attenuation::new(10.5).unwrap())),
+    );
+    let bytes = encode(&frame);
+    assert_eq!(bytes.last(), Some(&0));
+    assert_eq!(bytes.iter().filter(|&&b| b == 0).count(), 1);
+
+    let mut decoder = CommandDecoder::new();
+    let decoded: Vec<_> = decoder.feed(&bytes).collect();
+    assert_eq!(decoded, [Ok(frame)]);
+}
+
+#[test]
+fn frames_split_across_reads() {
+    let frame = Frame::new(7, Command::Monitor);
+    let bytes = encode(&frame);
+    let mut decoder = CommandDecoder::new();
+    let (head, tail) = bytes.split_at(2);
+    assert_eq!(decoder.feed(head).count(), 0);
+    assert_eq!(decoder.feed(tail).collect::<Vec<_>>(), [Ok(frame)]);
+}
+
+#[test]
+fn several_frames_in_one_read() {
+    let frames = [
+        Frame::new(1, Command::Monitor),
+        Frame::new(2, Command::Identify),
+        Frame::new(3, Command::Control(Action::Lna1Power(false))),
+    ];
+    let bytes: Vec<u8> = frames.iter().flat_map(encode).collect();
+    let mut decoder = CommandDecoder::new();
+    let decoded: Vec<_> = decoder.feed(&bytes).map(Result::unwrap).collect();
+    assert_eq!(decoded, frames);
+}
+
+#[test]
+fn reports_bad_checksum() {
+    let mut bytes = encode(&Frame::new(
+        42,
+        Command::Control(Action::SetAtten(Attenuation::new(10.5).unwrap())),
+    ));
+    // Flip a bit in the attenuation, which still deserializes
+    let n = bytes.len();
+    bytes[n - 5] ^= 0x10;
+    let good = encode(&Frame::new(43, Command::Monitor));
+    bytes.extend_from_slice(&good);
+
+    let mut decoder = CommandDecoder::new();
+    let decoded: Vec<_> = decoder.feed(&bytes).collect();
+    assert_eq!(
+        decoded,
+        [Err(Error::Checksum), Ok(Frame::new(43, Command::Monitor))]
+    );
+}
+
+#[test]
+fn checks_frames_without_decoding_them() {
+    // All zeros, so plenty of COBS blocks
+    let payload = MonitorPayload::default();
+    let mut bytes = encode(&Frame::new(NO_SEQ, Response::Monitor(payload)));
+    bytes.pop();
+    assert!(transport::wire::is_intact(&bytes));
+    let n = bytes.len();
+    bytes[n - 3] ^= 0x10;
+    assert!(!transport::wire::is_intact(&bytes));
+    assert!(!transport::wire::is_intact(&bytes[1..]));
+    assert!(!transport::wire::is_intact(&[]));
+}
+
+#[test]
+fn unreadable_frames_still_have_headers() {
+    // Like a command newer than the firmware, past the last variant it knows
+    let mut bytes = encode(&Frame::new(42, 200u32).with_addr(7));
+    bytes.pop();
+    let payload = transport::wire::payload(&mut bytes).unwrap();
+    assert_eq!(
+        transport::wire::deserialize::<Frame<Command>>(payload),
+        Err(Error::Deserialize)
+    );
+    let header: Header = transport::wire::deserialize(payload).unwrap();
+    assert_eq!((header.seq, header.addr), (42, 7));
+}
+
+#[test]
+fn reports_overfull_frames() {
+    let mut bytes = vec![0x55; 300];
+    bytes.push(0);
+    let good = encode(&Frame::new(1, Command::Monitor));
+    bytes.extend_from_slice(&good);
+
+    let mut decoder = CommandDecoder::new();
+    let decoded: Vec<_> = decoder.feed(&bytes).collect();
+    assert_eq!(
+        decoded,
+        [Err(Error::BufferFull), Ok(Frame::new(1, Command::Monitor))]
+    );
+}
+
+#[test]
+fn client_matches_responses_to_requests() {
+    let mut client = Client::new(100);
+    let request = client.request(Command::Monitor).unwrap().to_vec();
+    let mut decoder = CommandDecoder::new();
+    let sent: Vec<_> = decoder.feed(&request).map(Result::unwrap).collect();
+    assert_eq!(sent, [Frame::new(100, Command::Monitor)]);
+    assert_eq!(client.pending(), Some(100));
+
+    // A stale response is thrown away, ours is kept
+    let mut bytes = encode(&Frame::new(99, Response::Ack));
+    bytes.extend(encode(&Frame::new(100, Response::Ack)));
+    client.receive(&bytes);
+    assert_eq!(client.poll_response(), Some(Response::Ack));
+    assert_eq!(client.pending(), None);
+    assert_eq!(
+        client.stats(),
+        Stats {
+            stale_responses: 1,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn client_retries_with_the_same_sequence_number() {
+    let mut client = Client::new(5);
+    let first = client.request(Command::Identify).unwrap().to_vec();
+    let retry = client.retry().unwrap().to_vec();
+    assert_eq!(first, retry);
+    let next = client.request(Command::Identify).unwrap().to_vec();
+    assert_ne!(first, next);
+}
+
+#[test]
+fn client_skips_reserved_sequence_numbers() {
+    let mut client = Client::new(NO_SEQ);
+    client.request(Command::Monitor).unwrap();
+    assert_eq!(client.pending(), Some(2));
+}
+
+#[test]
+fn client_accepts_unsequenced_errors() {
+    let mut client = Client::new(1);
+    client.request(Command::Monitor).unwrap();
+    client.receive(&encode(&Frame::new(
+        NO_SEQ,
+        Response::Error(ErrorCode::Checksum),
+    )));
+    assert_eq!(
+        client.poll_response(),
+        Some(Response::Error(ErrorCode::Checksum))
+    );
+}
+
+#[test]
+fn client_keeps_telemetry_apart_from_responses() {
+    let mut client = Client::new(10);
+    client.request(Command::Monitor).unwrap();
+    let pushed = TelemetryPayload {
+        ic_temp: Some(30.0),
+        ..Default::default()
+    };
+    let mut bytes = encode(&Frame::new(NO_SEQ, Response::Telemetry(pushed.clone())));
+    bytes.extend(encode(&Frame::new(
+        10,
+        Response::Monitor(MonitorPayload::default()),
+    )));
+    client.receive(&bytes);
+    assert_eq!(
+        client.poll_response(),
+        Some(Response::Monitor(MonitorPayload::default()))
+    );
+    assert_eq!(client.poll_telemetry(), Some(pushed));
+    assert_eq!(client.poll_telemetry(), None);
+    assert_eq!(client.stats(), Stats::default());
+}
+
+#[test]
+fn client_ignores_other_addresses() {
+    let mut client = Client::new(10);
+    client.set_addr(7);
+    let bytes = client.request(Command::Monitor).unwrap().to_vec();
+    let mut decoder = CommandDecoder::new();
+    let sent = decoder.feed(&bytes).next().unwrap().unwrap();
+    assert_eq!(sent.addr, 7);
+
+    // Another FEM on the bus, with the same sequence number and unsolicited frames
+    let mut bytes = encode(&Frame::new(10, Response::Ack).with_addr(8));
+    bytes.extend(encode(
+        &Frame::new(NO_SEQ, Response::Telemetry(TelemetryPayload::default())).with_addr(8),
+    ));
+    client.receive(&bytes);
+    assert_eq!(client.poll_response(), None);
+    assert_eq!(client.poll_telemetry(), None);
+
+    client.receive(&encode(
+        &Frame::new(10, Response::Error(ErrorCode::Busy)).with_addr(7),
+    ));
+    assert_eq!(
+        client.poll_response(),
+        Some(Response::Error(ErrorCode::Busy))
+    );
+    assert_eq!(client.stats(), Stats::default());
+}
+
+#[test]
+fn client_broadcasts_without_waiting() {
+    let mut client = Client::new(10);
+    let bytes = client
+        .broadcast(Command::Control(Action::Lna1Power(false)))
+        .unwrap()
+        .to_vec();
+    let mut decoder = CommandDecoder::new();
+    let sent = decoder.feed(&bytes).next().unwrap().unwrap();
+    assert_eq!(sent.addr, BROADCAST);
+    assert_eq!(client.pending(), None);
+    // The next request gets a sequence number of its own
+    client.request(Command::Monitor).unwrap();
+    assert_eq!(client.pending(), Some(11));
+}
+
+#[test]
+fn client_keeps_events_apart_from_responses() {
+    let mut client = Client::new(10);
+    client.request(Command::GetState).unwrap();
+    let event = Event {
+        uptime_ms: 1234,
+        kind: EventKind::IfLost { channel: 2 },
+    };
+    let mut bytes = encode(&Frame::new(NO_SEQ, Response::Event(event.clone())));
+    bytes.extend(encode(&Frame::new(10, Response::Ack)));
+    client.receive(&bytes);
+    assert_eq!(client.poll_event(), Some(event));
+    assert_eq!(client.poll_response(), Some(Response::Ack));
+    assert_eq!(client.poll_telemetry(), None);
+}
+
+#[cfg(feature = "std")]
+mod blocking {
+    use super::*;
+    use std::{
+        io::{self, Read, Write},
+        time::Duration,
+    };
+    use transport::{
+        io::{BlockingClient, Error as IoError, Protocol},
+        v0_2,
+    };
+
+    /// Fake FEM that acks every command it's sent
+    #[derive(Default)]
+    struct Loopback {
+        decoder: CommandDecoder,
+        rx: Vec<u8>,
+        requests: Vec<Frame<Command>>,
+    }
+
+    impl Write for Loopback {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for frame in self.decoder.feed(buf) {
+                let frame = frame.unwrap();
+                self.rx
+                    .extend(encode(&Frame::new(frame.seq, Response::Ack)));
+                self.requests.push(frame);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for Loopback {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.rx.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let n = buf.len().min(self.rx.len());
+            buf[..n].copy_from_slice(&self.rx[..n]);
+            self.rx.drain(..n);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn request_response() {
+        let mut client = BlockingClient::new(Loopback::default());
+        let resp = client.request(Command::Control(Action::Lna2Power(true)));
+        assert_eq!(resp.unwrap(), Response::Ack);
+        let port = client.into_inner();
+        assert_eq!(
+            port.requests[0].body,
+            Command::Control(Action::Lna2Power(true))
+        );
+    }
+
+    /// Fake FEM with a subscription running, that loses the ack to every control
+    #[derive(Default)]
+    struct Subscribed {
+        decoder: CommandDecoder,
+        rx: Vec<u8>,
+    }
+
+    impl Write for Subscribed {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for frame in self.decoder.feed(buf) {
+                if let Ok(Frame {
+                    seq,
+                    body: Command::Identify,
+                    ..
+                }) = frame
+                {
+                    let resp = Response::Error(ErrorCode::Unsupported);
+                    self.rx.extend(encode(&Frame::new(seq, resp)));
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for Subscribed {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            // Telemetry keeps coming whatever was asked
+            let payload = TelemetryPayload::default();
+            self.rx
+                .extend(encode(&Frame::new(NO_SEQ, Response::Telemetry(payload))));
+            let n = buf.len().min(self.rx.len());
+            buf[..n].copy_from_slice(&self.rx[..n]);
+            self.rx.drain(..n);
+            Ok(n)
+        }
+    }
+
+    /// Fake FEM running 0.2 firmware, which only knows its own framing
+    #[derive(Default)]
+    struct V0_2 {
+        rx: Vec<u8>,
+    }
+
+    impl Write for V0_2 {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut buf = buf.to_vec();
+            for frame in buf.split_mut(|&b| b == 0).filter(|f| !f.is_empty()) {
+                if let Ok(v0_2::Command::Monitor) = v0_2::decode(frame) {
+                    let resp = v0_2::Response::Monitor(Default::default());
+                    let mut out = [0u8; 256];
+                    self.rx
+                        .extend_from_slice(v0_2::encode(&resp, &mut out).unwrap());
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for V0_2 {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.rx.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let n = buf.len().min(self.rx.len());
+            buf[..n].copy_from_slice(&self.rx[..n]);
+            self.rx.drain(..n);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn lost_acks_under_telemetry_time_out() {
+        let mut client =
+            BlockingClient::new(Subscribed::default()).with_timeout(Duration::from_millis(50));
+        assert_eq!(client.detect().unwrap(), Protocol::Framed);
+        let cmd = Command::Control(Action::Lna1Power(true));
+        assert!(matches!(client.request(cmd), Err(IoError::Timeout)));
+        // Not even 0.2 takes telemetry for an ack
+        let cmd = v0_2::Command::Control(v0_2::Action::Lna1Power(true));
+        assert!(matches!(client.request_v0_2(cmd), Err(IoError::Timeout)));
+        assert!(client.telemetry().next().unwrap().is_ok());
+    }
+
+    #[test]
+    fn detects_the_protocol() {
+        let mut client = BlockingClient::new(Loopback::default());
+        assert_eq!(client.detect().unwrap(), Protocol::Framed);
+        let mut client =
+            BlockingClient::new(V0_2::default()).with_timeout(Duration::from_millis(50));
+        assert_eq!(client.detect().unwrap(), Protocol::V0_2);
+        // 0.2 boards have no address of their own
+        let mut client = client.with_addr(5);
+        assert!(matches!(client.detect(), Err(IoError::Timeout)));
+        assert!(matches!(
+            client.request_v0_2(v0_2::Command::Monitor),
+            Err(IoError::Unaddressable)
+        ));
+    }
+
+    #[test]
+    fn times_out_without_a_response() {
+        let mut client = BlockingClient::new(io::Cursor::new(Vec::new()));
+        assert!(matches!(
+            client.request(Command::Monitor),
+            Err(IoError::Timeout)
+        ));
+    }
+}
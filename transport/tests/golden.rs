@@ -0,0 +1,762 @@
+//! Golden byte fixtures of every message variant of every protocol version
+//!
+//! A failure here means the encoding of a message changed, which would break fielded boards
+//! or MnC software. If the change is intended (say a variant was added at the end of an
+//! enum), rerun with `BLESS=1` to regenerate the fixtures and check the diff only adds lines.
+//!
+//! The JSON form of the messages (see `transport::schema`) has fixtures of its own, which
+//! change with the names of variants and fields too.
+
+use heapless::Vec;
+use std::{collections::BTreeMap, fmt::Write as _, path::PathBuf};
+use transport::{
+    v0_2, v1,
+    v1::{
+        Action, ActionResult, Actions, Amps, Attenuation, Capabilities, Chunk, Command,
+        ControlState, Dbm, ErrorCode, Event, EventKind, Features, Frame, Identity, MonitorFields,
+        MonitorPayloadV2, ObjectId, ParamId, ParamInfo, Rail, ResetReason, Response, Sensors,
+        Validity, Value, ValueKind, Volts, CHUNK_SIZE, MAX_PARAMS,
+    },
+    v2, wire,
+};
+
+/// Sequence number of every v1 fixture
+const SEQ: u16 = 0x1234;
+
+fn action_name(action: &Action) -> &'static str {
+    // A new variant here needs a sample in `v1_commands` too
+    match action {
+        Action::SetIfLevel(_) => "SetIfLevel",
+        Action::Lna1Power(_) => "Lna1Power",
+        Action::Lna2Power(_) => "Lna2Power",
+        Action::SetAtten(_) => "SetAtten",
+    }
+}
+
+fn error_name(code: &ErrorCode) -> &'static str {
+    // A new variant here needs a sample in `v1_responses` too
+    match code {
+        ErrorCode::Deserialize => "Deserialize",
+        ErrorCode::OutOfRange { .. } => "OutOfRange",
+        ErrorCode::SpiFault => "SpiFault",
+        ErrorCode::I2cFault => "I2cFault",
+        ErrorCode::Unsupported => "Unsupported",
+        ErrorCode::Busy => "Busy",
+        ErrorCode::Checksum => "Checksum",
+        ErrorCode::Unknown => "Unknown",
+        ErrorCode::ReadOnly => "ReadOnly",
+        ErrorCode::WrongType => "WrongType",
+        ErrorCode::NotStarted => "NotStarted",
+    }
+}
+
+fn result_name(result: &ActionResult) -> &'static str {
+    match result {
+        ActionResult::Applied => "Applied",
+        ActionResult::Rejected(_) => "Rejected",
+        ActionResult::Skipped => "Skipped",
+    }
+}
+
+fn event_name(kind: &EventKind) -> String {
+    // A new variant here needs a sample in `v1_responses` too
+    match kind {
+        EventKind::IfLost { .. } => "IfLost".into(),
+        EventKind::IfRestored { .. } => "IfRestored".into(),
+        EventKind::OverCurrent { rail, .. } => format!("OverCurrent.{rail:?}"),
+        EventKind::CurrentRestored { rail, .. } => format!("CurrentRestored.{rail:?}"),
+        EventKind::OverTemp { .. } => "OverTemp".into(),
+        EventKind::TempRestored { .. } => "TempRestored".into(),
+        EventKind::Rebooted { reason } => format!("Rebooted.{reason:?}"),
+    }
+}
+
+fn command_name(cmd: &Command) -> String {
+    // A new variant here needs a sample in `v1_commands` too
+    match cmd {
+        Command::Monitor => "Monitor".into(),
+        Command::Control(action) => format!("Control.{}", action_name(action)),
+        Command::Identify => "Identify".into(),
+        Command::Batch(_) => "Batch".into(),
+        Command::GetState => "GetState".into(),
+        Command::Subscribe { .. } => "Subscribe".into(),
+        Command::Unsubscribe => "Unsubscribe".into(),
+        Command::MonitorV2 => "MonitorV2".into(),
+        Command::SetAddress(_) => "SetAddress".into(),
+        Command::Capabilities => "Capabilities".into(),
+        Command::Get(_) => "Get".into(),
+        Command::Set(_, value) => format!("Set.{:?}", value.kind()),
+        Command::ListParams => "ListParams".into(),
+        Command::UploadStart { .. } => "UploadStart".into(),
+        Command::UploadChunk(_) => "UploadChunk".into(),
+        Command::Download { .. } => "Download".into(),
+    }
+}
+
+fn response_name(resp: &Response) -> String {
+    // A new variant here needs a sample in `v1_responses` too
+    match resp {
+        Response::Ack => "Ack".into(),
+        Response::Error(code) => format!("Error.{}", error_name(code)),
+        Response::Monitor(_) => "Monitor".into(),
+        Response::Identity(_) => "Identity".into(),
+        Response::Batch(results) => {
+            let names: std::vec::Vec<_> = results.iter().map(result_name).collect();
+            format!("Batch.{}", names.join("."))
+        }
+        Response::State(_) => "State".into(),
+        Response::Event(event) => format!("Event.{}", event_name(&event.kind)),
+        Response::MonitorV2(_) => "MonitorV2".into(),
+        Response::Capabilities(_) => "Capabilities".into(),
+        Response::Value(value) => format!("Value.{:?}", value.kind()),
+        Response::Params(_) => "Params".into(),
+        Response::UploadProgress { .. } => "UploadProgress".into(),
+        Response::Chunk { .. } => "Chunk".into(),
+        Response::Telemetry(_) => "Telemetry".into(),
+    }
+}
+
+fn atten(db: f32) -> Attenuation {
+    Attenuation::new(db).unwrap()
+}
+
+fn dbm(x: f32) -> Dbm {
+    Dbm::new(x).unwrap()
+}
+
+fn amps(x: f32) -> Amps {
+    Amps::new(x).unwrap()
+}
+
+fn power(voltage: f32, current: f32) -> v1::Power {
+    v1::Power {
+        voltage: Volts::new(voltage).unwrap(),
+        current: amps(current),
+    }
+}
+
+fn monitor_payload() -> v1::MonitorPayload {
+    v1::MonitorPayload {
+        if1_power: dbm(-30.5),
+        if2_power: dbm(-31.25),
+        ic_temp: 27.0,
+        lna1_power: power(5.0, 0.0625),
+        lna2_power: power(4.5, 0.125),
+        analog_power: power(6.0, 0.25),
+    }
+}
+
+fn v1_commands() -> std::vec::Vec<Command> {
+    let actions = [
+        Action::SetIfLevel(dbm(-10.0)),
+        Action::Lna1Power(true),
+        Action::Lna2Power(false),
+        Action::SetAtten(atten(10.5)),
+    ];
+    let mut cmds = vec![Command::Monitor];
+    cmds.extend(actions.iter().copied().map(Command::Control));
+    cmds.push(Command::Identify);
+    cmds.push(Command::Batch(Vec::from_slice(&actions).unwrap()));
+    cmds.push(Command::GetState);
+    cmds.push(Command::Subscribe {
+        interval_ms: 1000,
+        fields: MonitorFields::IF_POWER | MonitorFields::ANALOG_POWER,
+    });
+    cmds.push(Command::Unsubscribe);
+    cmds.push(Command::MonitorV2);
+    cmds.push(Command::SetAddress(0x2a));
+    cmds.push(Command::Capabilities);
+    cmds.push(Command::Get(ParamId::ATTEN));
+    cmds.push(Command::Set(ParamId::LNA1_POWER, Value::Bool(true)));
+    cmds.push(Command::Set(ParamId::ATTEN, Value::F32(10.5)));
+    cmds.push(Command::Set(ParamId::ADDRESS, Value::U32(0x2a)));
+    cmds.push(Command::ListParams);
+    cmds.push(Command::UploadStart {
+        object: ObjectId::CAL_TABLE,
+        len: 300,
+        crc: 0xcbf4_3926,
+    });
+    cmds.push(Command::UploadChunk(Chunk::new(256, b"calibration")));
+    cmds.push(Command::Download {
+        object: ObjectId::CAL_TABLE,
+        offset: 256,
+    });
+    cmds
+}
+
+fn v1_responses() -> std::vec::Vec<Response> {
+    let codes = [
+        ErrorCode::Deserialize,
+        ErrorCode::OutOfRange {
+            min: 0.0,
+            max: 31.5,
+        },
+        ErrorCode::SpiFault,
+        ErrorCode::I2cFault,
+        ErrorCode::Unsupported,
+        ErrorCode::Busy,
+        ErrorCode::Checksum,
+        ErrorCode::Unknown,
+        ErrorCode::ReadOnly,
+        ErrorCode::WrongType,
+        ErrorCode::NotStarted,
+    ];
+    let mut resps = vec![Response::Ack];
+    resps.extend(codes.iter().copied().map(Response::Error));
+    resps.push(Response::Monitor(monitor_payload()));
+    resps.push(Response::Identity(Identity {
+        protocol_version: v1::PROTOCOL_VERSION,
+        firmware_version: "0.3.0".into(),
+        git_hash: "0123456789ab-dirty".into(),
+        build_timestamp: 1_700_000_000,
+        serial: 0xe660_5838_3b2f_6a2c,
+    }));
+    resps.push(Response::Batch(
+        Vec::from_slice(&[
+            ActionResult::Applied,
+            ActionResult::Rejected(ErrorCode::OutOfRange {
+                min: 0.0,
+                max: 31.5,
+            }),
+            ActionResult::Skipped,
+        ])
+        .unwrap(),
+    ));
+    resps.push(Response::State(ControlState {
+        lna1_enabled: true,
+        lna2_enabled: false,
+        atten1: atten(10.5),
+        atten2: atten(31.5),
+        if_good_threshold: dbm(-10.0),
+        cal1: Some(true),
+        cal2: None,
+        uptime_ms: 123_456_789,
+    }));
+    let events = [
+        EventKind::IfLost { channel: 1 },
+        EventKind::IfRestored { channel: 2 },
+        EventKind::OverCurrent {
+            rail: Rail::Lna1,
+            amps: amps(0.125),
+        },
+        EventKind::OverCurrent {
+            rail: Rail::Lna2,
+            amps: amps(0.25),
+        },
+        EventKind::OverCurrent {
+            rail: Rail::Analog,
+            amps: amps(0.75),
+        },
+        EventKind::CurrentRestored {
+            rail: Rail::Analog,
+            amps: amps(0.5),
+        },
+        EventKind::OverTemp { celsius: 71.5 },
+        EventKind::TempRestored { celsius: 64.5 },
+        EventKind::Rebooted {
+            reason: ResetReason::PowerOn,
+        },
+        EventKind::Rebooted {
+            reason: ResetReason::Watchdog,
+        },
+        EventKind::Rebooted {
+            reason: ResetReason::Software,
+        },
+    ];
+    resps.extend(events.map(|kind| {
+        Response::Event(Event {
+            uptime_ms: 60_000,
+            kind,
+        })
+    }));
+    let payload = monitor_payload();
+    resps.push(Response::MonitorV2(MonitorPayloadV2 {
+        uptime_ms: 60_000,
+        sample_seq: 1234,
+        valid: Validity::ALL,
+        if1_power: payload.if1_power,
+        if1_counts: 1080,
+        if2_power: payload.if2_power,
+        if2_counts: 1066,
+        ic_temp: payload.ic_temp,
+        ic_temp_counts: 876,
+        lna1_power: payload.lna1_power,
+        lna2_power: payload.lna2_power,
+        analog_power: payload.analog_power,
+    }));
+    resps.push(Response::Capabilities(Capabilities {
+        actions: Actions::ALL,
+        channels: 2,
+        atten_min: 0.0,
+        atten_max: 31.5,
+        atten_step: 0.5,
+        sensors: Sensors::IF_POWER | Sensors::IC_TEMP | Sensors::RAIL_POWER,
+        features: Features::CAL_TONE,
+    }));
+    resps.extend([Value::Bool(true), Value::F32(10.5), Value::U32(3)].map(Response::Value));
+    resps.push(Response::Params(
+        Vec::from_slice(&[
+            ParamInfo {
+                id: ParamId::ATTEN,
+                name: "atten".into(),
+                unit: "dB".into(),
+                kind: ValueKind::F32,
+                min: 0.0,
+                max: 31.5,
+                read_only: false,
+                persisted: false,
+            },
+            ParamInfo {
+                id: ParamId::CRC_ERRORS,
+                name: "crc_errors".into(),
+                unit: "".into(),
+                kind: ValueKind::U32,
+                min: 0.0,
+                max: u32::MAX as f32,
+                read_only: true,
+                persisted: false,
+            },
+        ])
+        .unwrap(),
+    ));
+    resps.push(Response::UploadProgress { received: 256 });
+    resps.push(Response::Chunk {
+        len: 300,
+        chunk: Chunk::new(256, b"calibration"),
+    });
+    resps.push(Response::Telemetry(
+        monitor_payload().select(MonitorFields::IF_POWER | MonitorFields::ANALOG_POWER),
+    ));
+    resps
+}
+
+fn v0_2_commands() -> std::vec::Vec<v0_2::Command> {
+    let mut cmds = vec![v0_2::Command::Monitor];
+    cmds.extend(
+        [
+            v0_2::Action::SetIfLevel(dbm(-10.0)),
+            v0_2::Action::Lna1Power(true),
+            v0_2::Action::Lna2Power(false),
+            v0_2::Action::SetAtten(atten(10.5)),
+        ]
+        .map(v0_2::Command::Control),
+    );
+    cmds
+}
+
+fn v0_2_responses() -> std::vec::Vec<v0_2::Response> {
+    vec![
+        v0_2::Response::Ack,
+        v0_2::Response::Error,
+        v0_2::Response::Monitor(monitor_payload().into()),
+    ]
+}
+
+fn v1_bytes<T: serde::Serialize>(body: T) -> std::vec::Vec<u8> {
+    let mut buf = [0u8; 256];
+    wire::encode(&Frame::new(SEQ, body), &mut buf)
+        .unwrap()
+        .to_vec()
+}
+
+fn v2_bytes<T: serde::Serialize>(frame: &v2::Frame<T>) -> std::vec::Vec<u8> {
+    let mut buf = [0u8; 256];
+    wire::encode(frame, &mut buf).unwrap().to_vec()
+}
+
+fn v0_2_bytes<T: serde::Serialize>(msg: &T) -> std::vec::Vec<u8> {
+    let mut buf = [0u8; 256];
+    v0_2::encode(msg, &mut buf).unwrap().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        write!(s, "{b:02x}").unwrap();
+        s
+    })
+}
+
+/// Compare encoded messages against a fixture file of `name hex` lines
+fn check_fixtures(file: &str, encoded: &[(String, std::vec::Vec<u8>)]) {
+    let encoded: std::vec::Vec<_> = encoded
+        .iter()
+        .map(|(name, bytes)| (name.clone(), hex(bytes)))
+        .collect();
+    check_lines(file, &encoded);
+}
+
+/// Compare messages against a fixture file of `name encoding` lines
+fn check_lines(file: &str, encoded: &[(String, String)]) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(file);
+    if std::env::var_os("BLESS").is_some() {
+        let lines: String = encoded
+            .iter()
+            .map(|(name, encoding)| format!("{name} {encoding}\n"))
+            .collect();
+        std::fs::write(&path, lines).unwrap();
+    }
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let fixtures: BTreeMap<_, _> = contents
+        .lines()
+        .map(|line| line.split_once(' ').unwrap())
+        .collect();
+    for (name, encoding) in encoded {
+        let fixture = fixtures
+            .get(name.as_str())
+            .unwrap_or_else(|| panic!("{file} has no fixture for {name}"));
+        assert_eq!(encoding, fixture, "{file}: encoding of {name} changed");
+    }
+    assert_eq!(
+        fixtures.len(),
+        encoded.len(),
+        "{file} has fixtures that no sample covers"
+    );
+}
+
+#[test]
+fn v1_commands_match_fixtures() {
+    let encoded: std::vec::Vec<_> = v1_commands()
+        .into_iter()
+        .map(|cmd| (command_name(&cmd), v1_bytes(cmd)))
+        .collect();
+    check_fixtures("v1_commands.txt", &encoded);
+}
+
+#[test]
+fn v1_responses_match_fixtures() {
+    let encoded: std::vec::Vec<_> = v1_responses()
+        .into_iter()
+        .map(|resp| (response_name(&resp), v1_bytes(resp)))
+        .collect();
+    check_fixtures("v1_responses.txt", &encoded);
+}
+
+#[test]
+fn v1_json_matches_fixtures() {
+    let encoded: std::vec::Vec<_> = v1_commands()
+        .iter()
+        .map(|cmd| (command_name(cmd), serde_json::to_string(cmd).unwrap()))
+        .collect();
+    check_lines("v1_commands_json.txt", &encoded);
+    let encoded: std::vec::Vec<_> = v1_responses()
+        .iter()
+        .map(|resp| (response_name(resp), serde_json::to_string(resp).unwrap()))
+        .collect();
+    check_lines("v1_responses_json.txt", &encoded);
+}
+
+/// Read a message back from its JSON, which has to give the same message and bytes on the wire
+fn json_round_trip<T>(msg: &T)
+where
+    T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+{
+    let json = serde_json::to_string(msg).unwrap();
+    let back: T = serde_json::from_str(&json).unwrap();
+    assert_eq!(&back, msg, "{json}");
+    assert_eq!(v1_bytes(&back), v1_bytes(msg), "{json}");
+}
+
+#[test]
+fn json_round_trips() {
+    for cmd in v1_commands() {
+        json_round_trip(&cmd);
+        json_round_trip(&v2::Frame::new(SEQ, cmd).with_addr(0x2a));
+    }
+    for resp in v1_responses() {
+        json_round_trip(&resp);
+        json_round_trip(&v2::Frame::new(SEQ, resp).with_addr(0x2a));
+    }
+    // Quantities are checked on the way in, same as from postcard
+    for json in [
+        r#"{"Control":{"SetAtten":40.0}}"#,
+        r#"{"Control":{"SetIfLevel":null}}"#,
+    ] {
+        assert!(serde_json::from_str::<Command>(json).is_err(), "{json}");
+    }
+}
+
+#[test]
+fn v2_frames_match_fixtures() {
+    // The messages are the same as v1, only the envelope changed
+    let encoded = vec![
+        (
+            "Monitor.Default".to_string(),
+            v2_bytes(&v2::Frame::new(SEQ, Command::Monitor)),
+        ),
+        (
+            "Monitor.Addressed".to_string(),
+            v2_bytes(&v2::Frame::new(SEQ, Command::Monitor).with_addr(0x2a)),
+        ),
+        (
+            "Monitor.Broadcast".to_string(),
+            v2_bytes(&v2::Frame::new(SEQ, Command::Monitor).with_addr(v2::BROADCAST)),
+        ),
+        (
+            "Error.Busy.Addressed".to_string(),
+            v2_bytes(&v2::Frame::new(SEQ, Response::Error(ErrorCode::Busy)).with_addr(0x2a)),
+        ),
+    ];
+    check_fixtures("v2_frames.txt", &encoded);
+
+    let mut bytes = v2_bytes(&v2::Frame::new(SEQ, Command::Monitor).with_addr(0x2a));
+    bytes.pop();
+    let frame: v2::Frame<Command> = wire::decode(&mut bytes).unwrap();
+    assert_eq!(frame, v2::Frame::new(SEQ, Command::Monitor).with_addr(0x2a));
+}
+
+#[test]
+fn v0_2_commands_match_fixtures() {
+    let encoded: std::vec::Vec<_> = v0_2_commands()
+        .into_iter()
+        .map(|cmd| (command_name(&cmd.clone().into()), v0_2_bytes(&cmd)))
+        .collect();
+    check_fixtures("v0_2_commands.txt", &encoded);
+}
+
+#[test]
+fn v0_2_responses_match_fixtures() {
+    let encoded: std::vec::Vec<_> = v0_2_responses()
+        .into_iter()
+        .map(|resp| (response_name(&resp.clone().into()), v0_2_bytes(&resp)))
+        .collect();
+    check_fixtures("v0_2_responses.txt", &encoded);
+}
+
+#[test]
+fn fixtures_decode() {
+    for cmd in v1_commands() {
+        let mut bytes = v1_bytes(&cmd);
+        bytes.pop();
+        let frame: Frame<Command> = wire::decode(&mut bytes).unwrap();
+        assert_eq!(frame, Frame::new(SEQ, cmd));
+    }
+    for resp in v1_responses() {
+        let mut bytes = v1_bytes(&resp);
+        bytes.pop();
+        let frame: Frame<Response> = wire::decode(&mut bytes).unwrap();
+        assert_eq!(frame, Frame::new(SEQ, resp));
+    }
+    for cmd in v0_2_commands() {
+        let mut bytes = v0_2_bytes(&cmd);
+        bytes.pop();
+        assert_eq!(v0_2::decode::<v0_2::Command>(&mut bytes), Ok(cmd));
+    }
+    for resp in v0_2_responses() {
+        let mut bytes = v0_2_bytes(&resp);
+        bytes.pop();
+        assert_eq!(v0_2::decode::<v0_2::Response>(&mut bytes), Ok(resp));
+    }
+}
+
+#[test]
+fn v0_2_boards_reject_v1_requests() {
+    for cmd in v1_commands() {
+        for seq in (0..=u16::MAX)
+            .filter(|&s| !transport::is_reserved_seq(s))
+            .step_by(97)
+        {
+            let mut buf = [0u8; 256];
+            let bytes = wire::encode(&Frame::new(seq, cmd.clone()), &mut buf).unwrap();
+            let n = bytes.len() - 1;
+            assert!(v0_2::decode::<v0_2::Command>(&mut bytes[..n]).is_err());
+        }
+    }
+}
+
+#[test]
+fn v0_2_boards_reject_v2_requests() {
+    for cmd in v1_commands() {
+        for seq in (0..=u16::MAX)
+            .filter(|&s| !transport::is_reserved_seq(s))
+            .step_by(97)
+        {
+            for addr in [v2::BROADCAST, v2::DEFAULT_ADDR, v2::MAX_ADDR] {
+                let frame = v2::Frame::new(seq, cmd.clone()).with_addr(addr);
+                let mut bytes = v2_bytes(&frame);
+                let n = bytes.len() - 1;
+                assert!(v0_2::decode::<v0_2::Command>(&mut bytes[..n]).is_err());
+            }
+        }
+    }
+}
+
+#[test]
+fn v1_boards_reject_v2_requests() {
+    // Read as v1, the address would be the start of the body
+    let frame = v2::Frame::new(1000, Command::Control(Action::Lna1Power(false)));
+    let mut bytes = v2_bytes(&frame);
+    bytes.pop();
+    assert!(wire::decode::<Frame<Command>>(&mut bytes).is_err());
+
+    for cmd in v1_commands() {
+        for seq in (0..=u16::MAX).step_by(97) {
+            for addr in [v2::BROADCAST, v2::DEFAULT_ADDR, v2::MAX_ADDR] {
+                let frame = v2::Frame::new(seq, cmd.clone()).with_addr(addr);
+                let mut bytes = v2_bytes(&frame);
+                bytes.pop();
+                assert!(wire::decode::<Frame<Command>>(&mut bytes).is_err());
+            }
+        }
+    }
+}
+
+#[test]
+fn v2_clients_and_boards_reject_v1_frames() {
+    for cmd in v1_commands() {
+        let mut bytes = v1_bytes(&cmd);
+        bytes.pop();
+        assert!(wire::decode::<v2::Frame<Command>>(&mut bytes).is_err());
+    }
+    for resp in v1_responses() {
+        let mut bytes = v1_bytes(&resp);
+        bytes.pop();
+        assert!(wire::decode::<v2::Frame<Response>>(&mut bytes).is_err());
+    }
+}
+
+#[test]
+fn v0_2_clients_reject_later_responses() {
+    // Unsolicited frames most of all, which come whenever they like
+    for resp in v1_responses() {
+        for seq in [v1::NO_SEQ, SEQ, u16::MAX] {
+            let mut buf = [0u8; 256];
+            let bytes = wire::encode(&Frame::new(seq, resp.clone()), &mut buf).unwrap();
+            let n = bytes.len() - 1;
+            assert!(v0_2::decode::<v0_2::Response>(&mut bytes[..n]).is_err());
+            for addr in [v2::DEFAULT_ADDR, v2::MAX_ADDR] {
+                let frame = v2::Frame::new(seq, resp.clone()).with_addr(addr);
+                let mut bytes = v2_bytes(&frame);
+                let n = bytes.len() - 1;
+                assert!(v0_2::decode::<v0_2::Response>(&mut bytes[..n]).is_err());
+            }
+        }
+    }
+}
+
+#[test]
+fn conversions() {
+    for cmd in v0_2_commands() {
+        let v1: Command = cmd.clone().into();
+        assert_eq!(v0_2::Command::try_from(v1), Ok(cmd));
+    }
+    for resp in v0_2_responses() {
+        let v1: Response = resp.clone().into();
+        assert_eq!(v0_2::Response::try_from(v1), Ok(resp));
+    }
+    assert_eq!(
+        v0_2::Command::try_from(Command::Identify),
+        Err(ErrorCode::Unsupported)
+    );
+    assert_eq!(
+        Response::from(v0_2::Response::Error),
+        Response::Error(ErrorCode::Unknown)
+    );
+}
+
+#[test]
+fn attenuation_checked_against_capabilities() {
+    let caps = v1_responses()
+        .into_iter()
+        .find_map(|resp| match resp {
+            Response::Capabilities(caps) => Some(caps),
+            _ => None,
+        })
+        .unwrap();
+    for atten in [0.0, 0.5, 10.5, 31.0, 31.5] {
+        assert!(caps.atten_valid(atten), "{atten} dB");
+    }
+    for atten in [-0.5, 0.1, 10.25, 32.0, f32::NAN] {
+        assert!(!caps.atten_valid(atten), "{atten} dB");
+    }
+}
+
+/// The frame length limits are worked out from the types, so check them against the largest
+/// messages there are
+#[test]
+fn largest_messages_within_max_frame_len() {
+    let data = [0u8; CHUNK_SIZE];
+    let chunk = Chunk::new(u32::MAX, &data);
+    assert!(chunk.is_intact());
+    let mut buf = [0u8; v2::MAX_COMMAND_FRAME_LEN];
+    let cmd = Command::UploadChunk(chunk.clone());
+    wire::encode(&v2::Frame::new(u16::MAX, cmd), &mut buf).unwrap();
+
+    let mut buf = [0u8; v2::MAX_RESPONSE_FRAME_LEN];
+    let resp = Response::Chunk {
+        len: u32::MAX,
+        chunk,
+    };
+    wire::encode(&v2::Frame::new(u16::MAX, resp), &mut buf).unwrap();
+    let info = ParamInfo {
+        id: ParamId(u8::MAX),
+        name: "sixteen_letters_".into(),
+        unit: "dBm ".into(),
+        kind: ValueKind::F32,
+        min: f32::MIN,
+        max: f32::MAX,
+        read_only: true,
+        persisted: true,
+    };
+    let params = std::iter::repeat_n(info, MAX_PARAMS).collect();
+    wire::encode(
+        &v2::Frame::new(u16::MAX, Response::Params(params)),
+        &mut buf,
+    )
+    .unwrap();
+
+    for resp in v1_responses() {
+        wire::encode(&v2::Frame::new(u16::MAX, resp), &mut buf).unwrap();
+    }
+    let mut buf = [0u8; v2::MAX_COMMAND_FRAME_LEN];
+    for cmd in v1_commands() {
+        wire::encode(&v2::Frame::new(u16::MAX, cmd), &mut buf).unwrap();
+    }
+}
+
+#[test]
+fn params_checked_like_the_fem_would() {
+    let params = v1_responses()
+        .into_iter()
+        .find_map(|resp| match resp {
+            Response::Params(params) => Some(params),
+            _ => None,
+        })
+        .unwrap();
+    let (atten_info, crc_errors) = (&params[0], &params[1]);
+    assert_eq!(atten_info.check(&Value::F32(10.5)), Ok(()));
+    assert_eq!(
+        atten_info.check(&Value::F32(32.0)),
+        Err(ErrorCode::OutOfRange {
+            min: 0.0,
+            max: 31.5
+        })
+    );
+    assert_eq!(
+        atten_info.check(&Value::Bool(true)),
+        Err(ErrorCode::WrongType)
+    );
+    assert_eq!(crc_errors.check(&Value::U32(0)), Err(ErrorCode::ReadOnly));
+
+    for action in [
+        Action::SetIfLevel(dbm(-10.0)),
+        Action::Lna1Power(true),
+        Action::Lna2Power(false),
+        Action::SetAtten(atten(10.5)),
+    ] {
+        let (id, value) = action.param();
+        assert_eq!(Action::from_param(id, value), Ok(Some(action)));
+    }
+    assert_eq!(
+        Action::from_param(ParamId::ATTEN, Value::Bool(true)),
+        Ok(None)
+    );
+    assert_eq!(
+        Action::from_param(ParamId::ATTEN, Value::F32(f32::NAN)),
+        Err(ErrorCode::OutOfRange {
+            min: 0.0,
+            max: 31.5
+        })
+    );
+}
@@ -0,0 +1,23 @@
+//! The JSON Schema documents in `schema/`, which have to match the types
+//!
+//! A failure here means the JSON form of a message changed. If the change is intended, rerun
+//! with `BLESS=1` to write the documents again.
+
+use std::path::PathBuf;
+use transport::schema;
+
+#[test]
+fn documents_match_files() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("schema");
+    for (name, doc) in schema::documents() {
+        let path = dir.join(format!("{name}.json"));
+        let json = serde_json::to_string_pretty(&doc).unwrap() + "\n";
+        if std::env::var_os("BLESS").is_some() {
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(&path, &json).unwrap();
+        }
+        let file = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("can't read {}: {e}", path.display()));
+        assert_eq!(json, file, "schema of {name} changed");
+    }
+}
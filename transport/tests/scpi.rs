@@ -0,0 +1,251 @@
+use transport::{
+    codec::FrameDecoder,
+    scpi::{parse, Measurement, ParseError, Request, Setting, Split, Splitter},
+    v1, wire, Action, Attenuation, Chunk, ControlState, Dbm, ErrorCode, Frame, Identity,
+    MonitorPayloadV2, Rail, Response, Validity,
+};
+
+fn reply(req: &Request, resp: &Response) -> String {
+    let mut out = String::new();
+    req.reply(resp, &mut out).unwrap();
+    out
+}
+
+#[test]
+fn parses_queries() {
+    assert_eq!(parse("*IDN?"), Ok(Request::Identify));
+    assert_eq!(
+        parse("meas:if1?"),
+        Ok(Request::Measure(Measurement::If1Power))
+    );
+    assert_eq!(
+        parse("MEAS:TEMP?"),
+        Ok(Request::Measure(Measurement::Temperature))
+    );
+    assert_eq!(
+        parse("MEAS:ANALOG:CURR?"),
+        Ok(Request::Measure(Measurement::Current(Rail::Analog)))
+    );
+    assert_eq!(parse("  LNA2?  "), Ok(Request::Query(Setting::Lna2Power)));
+    assert_eq!(parse("IF:THR?"), Ok(Request::Query(Setting::IfThreshold)));
+}
+
+#[test]
+fn parses_settings() {
+    let atten = Attenuation::new(10.5).unwrap();
+    assert_eq!(parse("ATT 10.5"), Ok(Request::Set(Action::SetAtten(atten))));
+    assert_eq!(
+        parse("lna1 off"),
+        Ok(Request::Set(Action::Lna1Power(false)))
+    );
+    assert_eq!(parse("LNA2 1"), Ok(Request::Set(Action::Lna2Power(true))));
+    assert_eq!(
+        parse("IF:THR -12"),
+        Ok(Request::Set(Action::SetIfLevel(Dbm::new(-12.0).unwrap())))
+    );
+}
+
+#[test]
+fn rejects_bad_lines() {
+    assert_eq!(parse(""), Err(ParseError::Empty));
+    assert_eq!(parse("FOO 1"), Err(ParseError::UnknownHeader));
+    assert_eq!(parse("MEAS:IF3?"), Err(ParseError::UnknownHeader));
+    assert_eq!(parse("MEAS:IF1"), Err(ParseError::UnknownHeader));
+    assert_eq!(parse("ATT"), Err(ParseError::MissingArgument));
+    assert_eq!(parse("ATT ten"), Err(ParseError::BadArgument));
+    assert_eq!(parse("ATT 40"), Err(ParseError::BadArgument));
+    assert_eq!(parse("IF:THR NaN"), Err(ParseError::BadArgument));
+    assert_eq!(parse("LNA1 MAYBE"), Err(ParseError::BadArgument));
+    assert_eq!(parse("*IDN? 2"), Err(ParseError::BadArgument));
+}
+
+#[test]
+fn replies() {
+    let set = Request::Set(Action::SetAtten(Attenuation::MAX));
+    assert_eq!(reply(&set, &Response::Ack), "OK");
+    assert_eq!(
+        reply(
+            &set,
+            &Response::Error(ErrorCode::OutOfRange {
+                min: 0.0,
+                max: 31.5
+            })
+        ),
+        "ERR value out of range, must be between 0 and 31.5"
+    );
+
+    let identity = Identity {
+        firmware_version: "0.3.0".into(),
+        git_hash: "0123456789ab".into(),
+        serial: 0xe6605838_3b2f6a2c,
+        ..Default::default()
+    };
+    assert_eq!(
+        reply(&Request::Identify, &Response::Identity(identity)),
+        "GREX,FEM,E66058383B2F6A2C,0.3.0+0123456789ab"
+    );
+
+    let state = ControlState {
+        lna1_enabled: true,
+        atten1: Attenuation::new(10.5).unwrap(),
+        ..Default::default()
+    };
+    let query = |setting| reply(&Request::Query(setting), &Response::State(state.clone()));
+    assert_eq!(query(Setting::Lna1Power), "ON");
+    assert_eq!(query(Setting::Lna2Power), "OFF");
+    assert_eq!(query(Setting::Attenuation), "10.5");
+
+    let payload = MonitorPayloadV2 {
+        valid: Validity::IF1_POWER,
+        if1_power: Dbm::new(-30.5).unwrap(),
+        ..Default::default()
+    };
+    let measure = |m| reply(&Request::Measure(m), &Response::MonitorV2(payload.clone()));
+    assert_eq!(measure(Measurement::If1Power), "-30.5");
+    assert_eq!(measure(Measurement::If2Power), "NAN");
+
+    // Responses that don't fit the request
+    assert_eq!(
+        reply(&Request::Identify, &Response::Ack),
+        "ERR command not supported by the FEM"
+    );
+}
+
+#[test]
+fn splits_lines_from_frames() {
+    let mut buf = [0u8; 64];
+    let frame = wire::encode(&Frame::new(7, transport::Command::Monitor), &mut buf)
+        .unwrap()
+        .to_vec();
+    let mut bytes = b"ATT 10.5\r\n".to_vec();
+    bytes.extend(&frame);
+    bytes.extend(b"*IDN?\n");
+
+    let mut splitter = Splitter::<64>::new();
+    let mut window = &bytes[..];
+    let mut lines = vec![];
+    let mut frames = vec![];
+    while !window.is_empty() {
+        window = match splitter.feed(window) {
+            Split::Consumed => break,
+            Split::OverFull(remaining) => panic!("overfull with {remaining:?} left"),
+            Split::Frame { frame, remaining } => {
+                frames.push(wire::decode::<Frame<transport::Command>>(frame).unwrap());
+                remaining
+            }
+            Split::Line { line, remaining } => {
+                lines.push(line.to_owned());
+                remaining
+            }
+        };
+    }
+    assert_eq!(lines, ["ATT 10.5", "*IDN?"]);
+    assert_eq!(frames, [Frame::new(7, transport::Command::Monitor)]);
+}
+
+#[test]
+fn line_feeds_inside_frames_stay_in_the_frame() {
+    // A sequence number with a line feed in it
+    let mut buf = [0u8; 64];
+    let frame = wire::encode(&Frame::new(0x0a, transport::Command::Monitor), &mut buf)
+        .unwrap()
+        .to_vec();
+    assert!(frame.contains(&b'\n'));
+
+    let mut splitter = Splitter::<64>::new();
+    match splitter.feed(&frame) {
+        Split::Frame { frame, remaining } => {
+            assert!(remaining.is_empty());
+            let decoded: Frame<transport::Command> = wire::decode(frame).unwrap();
+            assert_eq!(decoded.seq, 0x0a);
+        }
+        _ => panic!("expected a frame"),
+    }
+}
+
+#[test]
+fn printable_starts_of_frames_stay_in_the_frame() {
+    // No zero for long enough that the COBS code byte is printable, then a line ending as
+    // the sequence number of a v1 frame
+    for seq in [0x0a, 0x0d] {
+        let chunk = Chunk::new(1, &[0x55; 40]);
+        let sent = v1::Frame::new(seq, transport::Command::UploadChunk(chunk));
+        let mut buf = [0u8; 128];
+        let frame = wire::encode(&sent, &mut buf).unwrap();
+        assert!(
+            frame[0].is_ascii_graphic() && frame[1] == seq as u8,
+            "{frame:02x?}"
+        );
+
+        let mut splitter = Splitter::<128>::new();
+        match splitter.feed(frame) {
+            Split::Frame { frame, remaining } => {
+                assert!(remaining.is_empty());
+                assert_eq!(wire::decode(frame), Ok(sent));
+            }
+            _ => panic!("expected a frame"),
+        }
+    }
+}
+
+#[test]
+fn lines_start_with_a_whole_header() {
+    let mut splitter = Splitter::<64>::new();
+    match splitter.feed(b"x\r\nLNA1?\r\n") {
+        Split::Line { line, remaining } => {
+            assert_eq!(line, "LNA1?");
+            assert_eq!(remaining, b"\n");
+        }
+        _ => panic!("expected a line"),
+    }
+}
+
+#[test]
+fn replies_are_dropped_by_hosts() {
+    let mut buf = [0u8; 64];
+    let sent = Frame::new(7, Response::Ack);
+    let mut bytes = b"OK\r\n\0".to_vec();
+    bytes.extend_from_slice(wire::encode(&sent, &mut buf).unwrap());
+
+    let mut decoder = FrameDecoder::<Frame<Response>, 64>::new();
+    let frames: Vec<_> = decoder.feed(&bytes).collect();
+    assert!(frames[0].is_err());
+    assert_eq!(frames[1..], [Ok(sent)]);
+}
+
+#[test]
+fn frames_come_through_whole() {
+    let commands = [
+        transport::Command::Monitor,
+        transport::Command::Identify,
+        transport::Command::GetState,
+        transport::Command::Control(Action::Lna1Power(true)),
+        transport::Command::Control(Action::SetAtten(Attenuation::new(10.5).unwrap())),
+        transport::Command::Control(Action::SetIfLevel(Dbm::new(-12.0).unwrap())),
+    ];
+    let mut splitter = Splitter::<64>::new();
+    for cmd in commands {
+        for seq in (0..=u16::MAX).filter(|&s| !transport::is_reserved_seq(s)) {
+            let sent = Frame::new(seq, cmd.clone());
+            let mut buf = [0u8; 64];
+            let frame = wire::encode(&sent, &mut buf).unwrap();
+            // On its own, and after a line from a terminal that left its LF behind
+            for before in [&b""[..], b"*IDN?\r\n"] {
+                let mut bytes = before.to_vec();
+                bytes.extend_from_slice(frame);
+                let mut window = &bytes[..];
+                let received = loop {
+                    window = match splitter.feed(window) {
+                        Split::Frame { frame, remaining } => {
+                            assert!(remaining.is_empty());
+                            break wire::decode::<Frame<transport::Command>>(frame);
+                        }
+                        Split::Line { remaining, .. } => remaining,
+                        Split::Consumed | Split::OverFull(_) => panic!("{sent:?} was lost"),
+                    };
+                };
+                assert_eq!(received.as_ref(), Ok(&sent), "{bytes:02x?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use futures_util::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio_util::codec::Framed;
+use transport::{
+    codec::Error,
+    io::Error as IoError,
+    tokio::{DeviceCodec, FemClient},
+    Action, Command, ErrorCode, Event, EventKind, Frame, MonitorFields, Response, TelemetryPayload,
+    NO_SEQ,
+};
+
+#[tokio::test]
+async fn pipelined_requests_get_their_own_responses() {
+    let (host, device) = tokio::io::duplex(1024);
+    let fem = FemClient::new(host);
+
+    // Answer both requests, in the opposite order to how they were sent
+    let device = tokio::spawn(async move {
+        let mut device = Framed::new(device, DeviceCodec::new());
+        let a = device.next().await.unwrap().unwrap().unwrap();
+        let b = device.next().await.unwrap().unwrap().unwrap();
+        for req in [b, a] {
+            let resp = match req.body {
+                Command::Control(_) => Response::Ack,
+                _ => Response::Error(ErrorCode::Unsupported),
+            };
+            device.send(Frame::new(req.seq, resp)).await.unwrap();
+        }
+    });
+
+    let (control, identify) = tokio::join!(
+        fem.request(Command::Control(Action::Lna1Power(true))),
+        fem.request(Command::Identify),
+    );
+    assert_eq!(control.unwrap(), Response::Ack);
+    assert_eq!(identify.unwrap(), Response::Error(ErrorCode::Unsupported));
+    device.await.unwrap();
+}
+
+#[tokio::test]
+async fn unsequenced_errors_answer_the_only_request() {
+    let (host, device) = tokio::io::duplex(1024);
+    let fem = FemClient::new(host);
+    tokio::spawn(async move {
+        let mut device = Framed::new(device, DeviceCodec::new());
+        device.next().await;
+        let resp = Response::Error(ErrorCode::Checksum);
+        device.send(Frame::new(NO_SEQ, resp)).await.unwrap();
+        device.next().await;
+    });
+    assert_eq!(
+        fem.request(Command::Monitor).await.unwrap(),
+        Response::Error(ErrorCode::Checksum)
+    );
+}
+
+#[tokio::test]
+async fn times_out_without_a_response() {
+    let (host, _device) = tokio::io::duplex(1024);
+    let fem = FemClient::new(host).with_timeout(Duration::from_millis(10));
+    assert!(matches!(
+        fem.request(Command::Monitor).await,
+        Err(IoError::Timeout)
+    ));
+}
+
+#[tokio::test]
+async fn closed_link() {
+    let (host, device) = tokio::io::duplex(1024);
+    let fem = FemClient::new(host);
+    tokio::spawn(async move {
+        let mut device = Framed::new(device, DeviceCodec::new());
+        device.next().await;
+    });
+    assert!(matches!(
+        fem.request(Command::Monitor).await,
+        Err(IoError::Closed)
+    ));
+}
+
+#[tokio::test]
+async fn telemetry_is_streamed() {
+    let (host, device) = tokio::io::duplex(1024);
+    let fem = FemClient::new(host);
+    let telemetry = fem.telemetry();
+    tokio::spawn(async move {
+        let mut device = Framed::new(device, DeviceCodec::new());
+        let req = device.next().await.unwrap().unwrap().unwrap();
+        device
+            .send(Frame::new(req.seq, Response::Ack))
+            .await
+            .unwrap();
+        for i in 0..3 {
+            let payload = TelemetryPayload {
+                ic_temp: Some(i as f32),
+                ..Default::default()
+            };
+            let resp = Response::Telemetry(payload);
+            device.send(Frame::new(NO_SEQ, resp)).await.unwrap();
+        }
+    });
+    let subscribe = Command::Subscribe {
+        interval_ms: 100,
+        fields: MonitorFields::IC_TEMP,
+    };
+    assert_eq!(fem.request(subscribe).await.unwrap(), Response::Ack);
+    // The stream ends once the device hangs up
+    let temps: Vec<_> = telemetry.map(|p| p.ic_temp).collect().await;
+    assert_eq!(temps, [Some(0.0), Some(1.0), Some(2.0)]);
+}
+
+#[tokio::test]
+async fn events_are_streamed_apart_from_telemetry() {
+    let (host, device) = tokio::io::duplex(1024);
+    let fem = FemClient::new(host);
+    let events = fem.events();
+    let telemetry = fem.telemetry();
+    let event = Event {
+        uptime_ms: 10,
+        kind: EventKind::OverTemp { celsius: 75.0 },
+    };
+    let sent = event.clone();
+    tokio::spawn(async move {
+        let mut device = Framed::new(device, DeviceCodec::new());
+        let telemetry = Response::Telemetry(TelemetryPayload::default());
+        device.send(Frame::new(NO_SEQ, telemetry)).await.unwrap();
+        device
+            .send(Frame::new(NO_SEQ, Response::Event(sent)))
+            .await
+            .unwrap();
+    });
+    assert_eq!(events.collect::<Vec<_>>().await, [event]);
+    assert_eq!(telemetry.count().await, 1);
+}
+
+#[tokio::test]
+async fn codec_reports_bad_frames() {
+    let (mut host, device) = tokio::io::duplex(1024);
+    let mut device = Framed::new(device, DeviceCodec::new());
+    let mut bytes = vec![0x55; 300];
+    bytes.extend_from_slice(&[0, 0, 0x02, 0x01, 0x01, 0]);
+    tokio::io::AsyncWriteExt::write_all(&mut host, &bytes)
+        .await
+        .unwrap();
+    assert_eq!(
+        device.next().await.unwrap().unwrap(),
+        Err(Error::BufferFull)
+    );
+    assert!(device.next().await.unwrap().unwrap().is_err());
+}
@@ -0,0 +1,73 @@
+//! Quantities are bare floats on the wire, but only valid ones make it through
+
+use transport::{wire, Action, Amps, Attenuation, Command, Dbm, Frame, OutOfRange, Volts};
+
+#[test]
+fn attenuation_is_checked_and_rounded() {
+    let range = Err(OutOfRange {
+        min: 0.0,
+        max: 31.5,
+    });
+    assert_eq!(Attenuation::new(f32::NAN), range);
+    assert_eq!(Attenuation::new(-0.5), range);
+    assert_eq!(Attenuation::new(31.6), range);
+    assert_eq!(Attenuation::new(100.0), range);
+
+    assert_eq!(Attenuation::new(0.0), Ok(Attenuation::MIN));
+    assert_eq!(Attenuation::new(31.5), Ok(Attenuation::MAX));
+    assert_eq!(Attenuation::MAX.steps(), 63);
+    let atten = Attenuation::new(10.3).unwrap();
+    assert_eq!((atten.db(), atten.steps()), (10.5, 21));
+    assert_eq!(Attenuation::new(10.2).unwrap().db(), 10.0);
+}
+
+#[test]
+fn only_finite_values() {
+    for x in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+        assert!(Dbm::new(x).is_err());
+        assert!(Volts::new(x).is_err());
+        assert!(Amps::new(x).is_err());
+    }
+    assert_eq!(Dbm::new(-30.5).unwrap().dbm(), -30.5);
+    assert_eq!(Volts::new(5.0).unwrap().volts(), 5.0);
+    assert_eq!(Amps::new(-0.01).unwrap().amps(), -0.01);
+}
+
+#[test]
+fn same_bytes_as_a_float() {
+    let atten = Attenuation::new(10.5).unwrap();
+    assert_eq!(
+        postcard::to_vec::<_, 8>(&atten).unwrap(),
+        postcard::to_vec::<_, 8>(&10.5f32).unwrap()
+    );
+    let level = Dbm::new(-12.25).unwrap();
+    assert_eq!(
+        postcard::to_vec::<_, 8>(&level).unwrap(),
+        postcard::to_vec::<_, 8>(&-12.25f32).unwrap()
+    );
+}
+
+#[test]
+fn invalid_values_dont_deserialize() {
+    for x in [f32::NAN, -1.0, 40.0] {
+        let bytes = postcard::to_vec::<_, 8>(&x).unwrap();
+        assert!(postcard::from_bytes::<Attenuation>(&bytes).is_err());
+    }
+    let bytes = postcard::to_vec::<_, 8>(&f32::INFINITY).unwrap();
+    assert!(postcard::from_bytes::<Dbm>(&bytes).is_err());
+
+    // Command::Control(Action::SetAtten(_)) from something that doesn't check
+    let set_atten = |db: f32| {
+        let mut buf = [0u8; 32];
+        let bytes = wire::encode(&Frame::new(42, (1u8, 3u8, db)), &mut buf).unwrap();
+        let n = bytes.len() - 1;
+        wire::decode::<Frame<Command>>(&mut bytes[..n]).map(|f| f.body)
+    };
+    let atten = Attenuation::new(20.0).unwrap();
+    assert_eq!(
+        set_atten(20.0),
+        Ok(Command::Control(Action::SetAtten(atten)))
+    );
+    assert_eq!(set_atten(40.0), Err(wire::Error::Deserialize));
+    assert_eq!(set_atten(f32::NAN), Err(wire::Error::Deserialize));
+}